@@ -1,29 +1,41 @@
 // build.rs
 
-use std::process::Command;
+extern crate grass;
+
 use std::env;
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::Path;
 
 fn main() {
 
-    if let Ok(_) = env::var("CARGO_FEATURE_REGENERATE_CSS") {
+    // Compile the SCSS stylesheet of the theme to css
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    let scss_dir = Path::new(&manifest_dir).join("src/theme/scss");
 
-        // Compile stylus stylesheet to css
-        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let css = match grass::from_path(&scss_dir.join("book.scss"), &grass::Options::default()) {
+        Ok(css) => css,
+        Err(e) => panic!("Could not compile the theme stylesheet: {}", e),
+    };
+    File::create(Path::new(&out_dir).join("book.css"))
+        .and_then(|mut f| f.write_all(css.as_bytes()))
+        .expect("Could not write book.css");
 
-        let theme_dir = Path::new(&manifest_dir).join("src/theme/");
-        let stylus_dir = theme_dir.join("stylus/book.styl");
+    // Only compile again when a stylesheet changes
+    println!("cargo:rerun-if-changed=build.rs");
+    rerun_if_changed(&scss_dir);
+}
 
-        if !Command::new("stylus")
-                .arg(format!("{}", stylus_dir.to_str().unwrap()))
-                .arg("--out")
-                .arg(format!("{}", theme_dir.to_str().unwrap()))
-                .arg("--use")
-                .arg("nib")
-                .status().unwrap()
-                .success() {
-            panic!("Stylus encoutered an error");
+fn rerun_if_changed(dir: &Path) {
+    println!("cargo:rerun-if-changed={}", dir.display());
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            rerun_if_changed(&path);
+        } else {
+            println!("cargo:rerun-if-changed={}", path.display());
         }
     }
-
 }
@@ -5,6 +5,8 @@ extern crate clap;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+#[macro_use]
+extern crate crossterm;
 
 // Dependencies for the Watch feature
 #[cfg(feature = "watch")]
@@ -22,12 +24,19 @@ extern crate staticfile;
 #[cfg(feature = "serve")]
 extern crate ws;
 
+use std::cmp;
 use std::env;
 use std::error::Error;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 use clap::{App, ArgMatches, SubCommand, AppSettings};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen,
+                          LeaveAlternateScreen};
+use crossterm::tty::IsTty;
 
 // Uses for the Watch feature
 #[cfg(feature = "watch")]
@@ -36,7 +45,8 @@ use notify::Watcher;
 use std::sync::mpsc::channel;
 
 
-use mdbook::MDBook;
+use mdbook::{BookItem, MDBook};
+use mdbook::renderer::Text;
 
 const NAME: &'static str = "mdbook";
 
@@ -59,7 +69,9 @@ fn main() {
                         .arg_from_usage("--force 'skip confirmation prompts'"))
                     .subcommand(SubCommand::with_name("build")
                         .about("Build the book from the markdown files")
-                        .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when ommitted)'"))
+                        .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when ommitted)'")
+                        .arg_from_usage("--strict 'Fails when the book contains broken links'")
+                        .arg_from_usage("--renderer=[renderer] 'The output format: html, pdf, epub, markdown, man, text or json{n}(Defaults to html)'"))
                     .subcommand(SubCommand::with_name("watch")
                         .about("Watch the files for changes")
                         .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when ommitted)'"))
@@ -72,6 +84,9 @@ fn main() {
                         .arg_from_usage("-a, --address=[address] 'Address that the browser can reach the websocket server from{n}(Defaults to the interface addres)'"))
                     .subcommand(SubCommand::with_name("test")
                         .about("Test that code samples compile"))
+                    .subcommand(SubCommand::with_name("read")
+                        .about("Read the book in the terminal")
+                        .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when ommitted)'"))
                     .get_matches();
 
     // Check which subcomamnd the user ran...
@@ -83,6 +98,7 @@ fn main() {
         #[cfg(feature = "serve")]
         ("serve", Some(sub_matches)) => serve(sub_matches),
         ("test", Some(sub_matches)) => test(sub_matches),
+        ("read", Some(sub_matches)) => read(sub_matches),
         (_, _) => unreachable!(),
     };
 
@@ -159,7 +175,19 @@ fn init(args: &ArgMatches) -> Result<(), Box<Error>> {
 // Build command implementation
 fn build(args: &ArgMatches) -> Result<(), Box<Error>> {
     let book_dir = get_book_dir(args);
-    let mut book = MDBook::new(&book_dir,true).read_config();
+    let mut book = MDBook::new(&book_dir,true).read_config().set_strict(args.is_present("strict"));
+
+    book = match args.value_of("renderer") {
+        None | Some("html") => book,
+        Some("pdf") => book.set_renderer(Box::new(mdbook::renderer::Pdf::new())),
+        Some("epub") => book.set_renderer(Box::new(mdbook::renderer::Epub::new())),
+        Some("markdown") => book.set_renderer(Box::new(mdbook::renderer::Markdown::new())),
+        Some("man") => book.set_renderer(Box::new(mdbook::renderer::Man::new())),
+        Some("text") => book.set_renderer(Box::new(Text::new())),
+        Some("json") => book.set_renderer(Box::new(mdbook::renderer::Json::new())),
+        Some(other) => return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput,
+                                                          format!("Unknown renderer `{}`", other)))),
+    };
 
     try!(book.build());
 
@@ -264,6 +292,288 @@ fn test(args: &ArgMatches) -> Result<(), Box<Error>> {
 }
 
 
+// Read command implementation
+fn read(args: &ArgMatches) -> Result<(), Box<Error>> {
+    let book_dir = get_book_dir(args);
+    let mut book = MDBook::new(&book_dir, false).read_config();
+    try!(book.parse_summary());
+
+    // The chapters that have a file, with their section number and name
+    let chapters: Vec<(String, PathBuf)> = book.iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref s, ref ch) if ch.path != PathBuf::new() => {
+                Some((format!("{} {}", s, ch.name), ch.path.clone()))
+            },
+            BookItem::Affix(ref ch) if ch.path != PathBuf::new() => Some((ch.name.clone(), ch.path.clone())),
+            _ => None,
+        })
+        .collect();
+    if chapters.is_empty() {
+        return Err(Box::new(io::Error::new(io::ErrorKind::NotFound, "The book has no chapters")));
+    }
+
+    let (rows, columns) = match terminal_size() {
+        Some(size) => size,
+        None => {
+            // Not a terminal, the chapters are printed one after the other
+            let text = Text::new();
+            for &(_, ref path) in &chapters {
+                println!("{}", try!(text.render_chapter(&book, path)));
+            }
+            return Ok(());
+        },
+    };
+
+    // Long lines are hard to read, the text is never wider than 100 characters
+    let text = Text::new().set_ansi(true).set_width(cmp::min(columns.saturating_sub(1), 100));
+
+    let _terminal = try!(RawTerminal::new());
+    pager(&book, &text, &chapters, rows, columns)
+}
+
+/// Puts the terminal in the state the pager needs, and restores it when dropped, also when the
+/// pager fails or panics
+struct RawTerminal;
+
+impl RawTerminal {
+    fn new() -> io::Result<Self> {
+        // Ctrl-C is read as a key instead of killing mdbook with the terminal in this state
+        try!(terminal::enable_raw_mode());
+        // Do not wrap the lines wider than the terminal
+        try!(execute!(io::stdout(), EnterAlternateScreen, Hide, DisableLineWrap));
+
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        execute!(io::stdout(), EnableLineWrap, Show, LeaveAlternateScreen).ok();
+        if let Err(e) = terminal::disable_raw_mode() {
+            println!("Could not restore the terminal: {}", e);
+        }
+    }
+}
+
+/// The keys the pager reacts to
+enum Key {
+    Char(char),
+    Enter,
+    Escape,
+    Backspace,
+    Interrupt,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
+
+/// Pages through the chapters until `q` is pressed
+fn pager(book: &MDBook, text: &Text, chapters: &[(String, PathBuf)], rows: usize, columns: usize)
+         -> Result<(), Box<Error>> {
+    // The last row shows where the reader is
+    let height = cmp::max(rows, 2) - 1;
+
+    let mut current = 0;
+    let mut lines = try!(chapter_lines(book, text, &chapters[current].1));
+    let mut top = 0;
+    let mut stdout = io::stdout();
+
+    loop {
+        let bottom = cmp::min(top + height, lines.len());
+        let percent = if lines.is_empty() { 100 } else { bottom * 100 / lines.len() };
+        let status = format!("{} ({}/{}) {}%  q: quit  n/p: next/previous chapter  t: contents",
+                             chapters[current].0,
+                             current + 1,
+                             chapters.len(),
+                             percent);
+
+        try!(queue!(stdout, Clear(ClearType::All)));
+        for (row, line) in lines[top..bottom].iter().enumerate() {
+            try!(queue!(stdout, MoveTo(0, row as u16), Print(line)));
+        }
+        try!(queue!(stdout,
+                    MoveTo(0, rows as u16 - 1),
+                    SetAttribute(Attribute::Reverse),
+                    Print(status.chars().take(columns).collect::<String>()),
+                    SetAttribute(Attribute::Reset)));
+        try!(stdout.flush());
+
+        let max_top = lines.len().saturating_sub(height);
+        let mut next = None;
+        match try!(read_key()) {
+            Key::Char('q') | Key::Interrupt => return Ok(()),
+            Key::Down | Key::Char('j') | Key::Enter => top = cmp::min(top + 1, max_top),
+            Key::Up | Key::Char('k') => top = top.saturating_sub(1),
+            // At the end of a chapter the next page is the next chapter
+            Key::PageDown | Key::Char(' ') | Key::Char('f') => {
+                if top == max_top && current + 1 < chapters.len() {
+                    next = Some(current + 1);
+                } else {
+                    top = cmp::min(top + height, max_top);
+                }
+            },
+            Key::PageUp | Key::Char('b') => top = top.saturating_sub(height),
+            Key::Home | Key::Char('g') => top = 0,
+            Key::End | Key::Char('G') => top = max_top,
+            Key::Right | Key::Char('n') if current + 1 < chapters.len() => next = Some(current + 1),
+            Key::Left | Key::Char('p') if current > 0 => next = Some(current - 1),
+            Key::Char('t') => {
+                next = try!(contents(book, &chapters[current].1, rows))
+                    .and_then(|path| chapters.iter().position(|&(_, ref p)| *p == path))
+            },
+            _ => {},
+        }
+
+        if let Some(chapter) = next {
+            current = chapter;
+            lines = try!(chapter_lines(book, text, &chapters[current].1));
+            top = 0;
+        }
+    }
+}
+
+/// A line of the list of chapters, chapters without a file are listed but can not be read
+struct Entry<'a> {
+    depth: usize,
+    section: Option<&'a str>,
+    name: &'a str,
+    path: &'a Path,
+}
+
+/// Lists the tree of chapters depth first, sub chapters are one level deeper than their parent
+fn entries<'a>(items: &'a [BookItem], depth: usize, entries: &mut Vec<Entry<'a>>) {
+    for item in items {
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(&**s), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => continue,
+        };
+
+        entries.push(Entry {
+            depth: depth,
+            section: section,
+            name: &ch.name,
+            path: &ch.path,
+        });
+        self::entries(&ch.sub_items, depth + 1, entries);
+    }
+}
+
+/// Shows the list of chapters and asks which one to go to, by section number or part of its name.
+/// Returns the path of the chapter.
+fn contents(book: &MDBook, current: &Path, rows: usize) -> Result<Option<PathBuf>, Box<Error>> {
+    let mut list = vec![];
+    entries(&book.content, 0, &mut list);
+
+    // The window of the list that is shown keeps the current chapter in view
+    let height = cmp::max(rows, 3) - 2;
+    let position = list.iter().position(|entry| entry.path == current).unwrap_or(0);
+    let first = cmp::min(position.saturating_sub(height / 2), list.len().saturating_sub(height));
+
+    let mut stdout = io::stdout();
+    try!(queue!(stdout, Clear(ClearType::All)));
+    for (row, entry) in list.iter().skip(first).take(height).enumerate() {
+        let indent: String = std::iter::repeat("  ").take(entry.depth).collect();
+        let label = match entry.section {
+            Some(section) => format!("{}{} {}", indent, section, entry.name),
+            None => format!("{}{}", indent, entry.name),
+        };
+        try!(queue!(stdout, MoveTo(0, row as u16)));
+        if entry.path == current {
+            try!(queue!(stdout, SetAttribute(Attribute::Bold), Print(label), SetAttribute(Attribute::Reset)));
+        } else {
+            try!(queue!(stdout, Print(label)));
+        }
+    }
+
+    let mut input = String::new();
+    loop {
+        try!(queue!(stdout,
+                    MoveTo(0, rows as u16 - 1),
+                    Clear(ClearType::CurrentLine),
+                    Print(format!("Go to (section number or part of the name, nothing to cancel): {}", input))));
+        try!(stdout.flush());
+
+        match try!(read_key()) {
+            Key::Enter => break,
+            Key::Escape | Key::Interrupt => return Ok(None),
+            Key::Backspace => {
+                input.pop();
+            },
+            Key::Char(c) if !c.is_control() => input.push(c),
+            _ => {},
+        }
+    }
+
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    // Only the chapters with a file can be read
+    let list: Vec<&Entry> = list.iter().filter(|entry| *entry.path != PathBuf::new()).collect();
+
+    let section = input.trim_right_matches('.');
+    let by_section = list.iter().find(|entry| entry.section.map(|s| s.trim_right_matches('.')) == Some(section));
+    let by_name = || {
+        let input = input.to_lowercase();
+        list.iter().find(|entry| entry.name.to_lowercase().contains(&input))
+    };
+
+    Ok(by_section.or_else(by_name).map(|entry| entry.path.to_owned()))
+}
+
+fn chapter_lines(book: &MDBook, text: &Text, path: &Path) -> Result<Vec<String>, Box<Error>> {
+    Ok(try!(text.render_chapter(book, path)).lines().map(|l| l.to_owned()).collect())
+}
+
+/// Waits for a key the pager reacts to, other keys and events are skipped
+fn read_key() -> Result<Key, Box<Error>> {
+    loop {
+        let key = match try!(event::read()) {
+            // Windows also reports when keys are released
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+
+        return Ok(match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Interrupt,
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Escape,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            _ => continue,
+        });
+    }
+}
+
+/// The number of rows and columns of the terminal, `None` when the standard input or output is
+/// not a terminal
+fn terminal_size() -> Option<(usize, usize)> {
+    if !io::stdin().is_tty() || !io::stdout().is_tty() {
+        return None;
+    }
+
+    match terminal::size() {
+        Ok((columns, rows)) if rows > 0 && columns > 0 => Some((rows as usize, columns as usize)),
+        _ => None,
+    }
+}
+
+
 fn get_book_dir(args: &ArgMatches) -> PathBuf {
     if let Some(dir) = args.value_of("dir") {
         // Check if path is relative from current dir, or absolute...
@@ -1,4 +1,5 @@
 use serde_json;
+use theme::ColorScheme;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -8,11 +9,29 @@ pub struct BookConfig {
     pub title: String,
     pub author: String,
     pub description: String,
+    pub language: String,
+    pub identifier: Option<String>,
     root: PathBuf,
     pub dest: PathBuf,
     pub src: PathBuf,
     pub indent_spaces: i32,
     multilingual: bool,
+    pub svg_diagrams: bool,
+    pub math: bool,
+    pub prerender_math: bool,
+    pub js_engines: Option<Vec<String>>,
+    pub playground: String,
+    pub fold_level: Option<usize>,
+    pub markdown_toc: bool,
+    pub man_pages: Option<Vec<String>>,
+    pub man_section: String,
+    pub additional_css: Vec<PathBuf>,
+    pub additional_js: Vec<PathBuf>,
+    pub self_contained: bool,
+    pub highlight_js: bool,
+    pub themes: Vec<ColorScheme>,
+    pub default_theme: String,
+    pub preferred_dark_theme: String,
 }
 
 
@@ -22,11 +41,29 @@ impl BookConfig {
             title: String::new(),
             author: String::new(),
             description: String::new(),
+            language: String::from("en"),
+            identifier: None,
             root: root.to_owned(),
             dest: root.join("book"),
             src: root.join("src"),
             indent_spaces: 4, // indentation used for SUMMARY.md
             multilingual: false,
+            svg_diagrams: false,
+            math: false,
+            prerender_math: false,
+            js_engines: None,
+            playground: String::from("https://play.rust-lang.org/evaluate.json"),
+            fold_level: None,
+            markdown_toc: true,
+            man_pages: None,
+            man_section: String::from("1"),
+            additional_css: vec![],
+            additional_js: vec![],
+            self_contained: false,
+            highlight_js: false,
+            themes: ColorScheme::builtin(),
+            default_theme: String::from("light"),
+            preferred_dark_theme: String::from("navy"),
         }
     }
 
@@ -70,6 +107,139 @@ impl BookConfig {
                 self.description = a.to_string().replace("\"", "")
             }
 
+            // Language of the book, as a language tag like `en` or `pt-BR`
+            if let Some(a) = config.get("language") {
+                self.language = a.to_string().replace("\"", "")
+            }
+
+            // Unique identifier of the book, like `urn:isbn:...`
+            if let Some(a) = config.get("identifier") {
+                if let Some(identifier) = a.as_str() {
+                    self.identifier = Some(identifier.to_owned());
+                }
+            }
+
+            // Render nomnoml and railroad diagrams to SVG at build time
+            if let Some(a) = config.get("svg-diagrams") {
+                self.svg_diagrams = a.as_bool().unwrap_or(false);
+            }
+
+            // Protect math between `$` delimiters from the markdown parser
+            if let Some(a) = config.get("math") {
+                self.math = a.as_bool().unwrap_or(false);
+            }
+
+            // Convert math to MathML at build time instead of loading MathJax
+            if let Some(a) = config.get("prerender-math") {
+                self.prerender_math = a.as_bool().unwrap_or(false);
+            }
+
+            // JavaScript engines to include, instead of detecting the ones used by each page
+            if let Some(a) = config.get("js-engines") {
+                if let Some(engines) = a.as_array() {
+                    self.js_engines = Some(engines.iter()
+                        .filter_map(|e| e.as_str())
+                        .map(|e| e.to_owned())
+                        .collect());
+                }
+            }
+
+            // Playground the run button of playpens sends the code to
+            if let Some(a) = config.get("playground") {
+                self.playground = a.to_string().replace("\"", "");
+            }
+
+            // Depth of the sidebar below which sections are collapsed
+            if let Some(a) = config.get("fold-level") {
+                self.fold_level = a.as_u64().map(|l| l as usize);
+            }
+
+            // Table of contents at the start of the single markdown file
+            if let Some(a) = config.get("markdown-toc") {
+                self.markdown_toc = a.as_bool().unwrap_or(true);
+            }
+
+            // Chapters turned into man pages, instead of all of them
+            if let Some(a) = config.get("man-pages") {
+                if let Some(pages) = a.as_array() {
+                    self.man_pages = Some(pages.iter()
+                        .filter_map(|p| p.as_str())
+                        .map(|p| p.to_owned())
+                        .collect());
+                }
+            }
+
+            // Section of the man pages that do not set one in their front matter
+            if let Some(a) = config.get("man-section") {
+                self.man_section = a.to_string().replace("\"", "");
+            }
+
+            // Stylesheets and scripts included in every page, relative to the root of the book
+            if let Some(a) = config.get("additional-css") {
+                self.additional_css = self.paths_from_json(a);
+            }
+            if let Some(a) = config.get("additional-js") {
+                self.additional_js = self.paths_from_json(a);
+            }
+
+            // Fail the build when the theme loads assets from other hosts
+            if let Some(a) = config.get("self-contained") {
+                self.self_contained = a.as_bool().unwrap_or(false);
+            }
+
+            // Highlight code blocks in the browser too, for the languages mdBook does not highlight
+            if let Some(a) = config.get("highlight-js") {
+                self.highlight_js = a.as_bool().unwrap_or(false);
+            }
+
+            // Colour schemes of the theme picker, a scheme with the id of a built-in one changes the
+            // settings it gives
+            if let Some(a) = config.get("themes") {
+                if let Some(themes) = a.as_array() {
+                    for theme in themes.iter().filter_map(|t| t.as_object()) {
+                        let id = match theme.get("id").and_then(|id| id.as_str()) {
+                            Some(id) => id,
+                            None => continue,
+                        };
+                        let css = theme.get("css").and_then(|p| p.as_str()).map(|p| self.get_root().join(p));
+                        let highlight_css = theme.get("highlight-css")
+                            .and_then(|p| p.as_str())
+                            .map(|p| self.get_root().join(p));
+
+                        let index = match self.themes.iter().position(|t| t.id == id) {
+                            Some(index) => index,
+                            None => {
+                                self.themes.push(ColorScheme::new(id, id, false));
+                                self.themes.len() - 1
+                            },
+                        };
+                        let scheme = &mut self.themes[index];
+
+                        if let Some(name) = theme.get("name").and_then(|n| n.as_str()) {
+                            scheme.name = name.to_owned();
+                        }
+                        if let Some(dark) = theme.get("dark").and_then(|d| d.as_bool()) {
+                            scheme.dark = dark;
+                        }
+                        if css.is_some() {
+                            scheme.css = css;
+                        }
+                        if highlight_css.is_some() {
+                            scheme.highlight_css = highlight_css;
+                        }
+                    }
+                }
+            }
+
+            // Scheme used until the reader picks one, and the one used when the system prefers
+            // dark colours
+            if let Some(a) = config.get("default-theme") {
+                self.default_theme = a.to_string().replace("\"", "");
+            }
+            if let Some(a) = config.get("preferred-dark-theme") {
+                self.preferred_dark_theme = a.to_string().replace("\"", "");
+            }
+
             // Destination
             if let Some(a) = config.get("dest") {
                 let dest = PathBuf::from(&a.to_string().replace("\"", ""));
@@ -90,6 +260,18 @@ impl BookConfig {
         self
     }
 
+    fn paths_from_json(&self, paths: &serde_json::Value) -> Vec<PathBuf> {
+        match paths.as_array() {
+            Some(paths) => {
+                paths.iter()
+                    .filter_map(|p| p.as_str())
+                    .map(|p| self.get_root().join(p))
+                    .collect()
+            },
+            None => vec![],
+        }
+    }
+
     pub fn get_root(&self) -> &Path {
         &self.root
     }
@@ -13,6 +13,7 @@ use std::io::ErrorKind;
 use std::process::Command;
 
 use {theme, parse, utils};
+use theme::ColorScheme;
 use renderer::{Renderer, HtmlHandlebars};
 
 
@@ -24,12 +25,31 @@ pub struct MDBook {
     pub title: String,
     pub author: String,
     pub description: String,
+    pub language: String,
+    identifier: Option<String>,
 
     pub content: Vec<BookItem>,
     renderer: Box<Renderer>,
 
     livereload: Option<String>,
     buildfull: bool,
+    svg_diagrams: bool,
+    math: bool,
+    prerender_math: bool,
+    js_engines: Option<Vec<String>>,
+    playground: String,
+    strict: bool,
+    fold_level: Option<usize>,
+    markdown_toc: bool,
+    man_pages: Option<Vec<String>>,
+    man_section: String,
+    additional_css: Vec<PathBuf>,
+    additional_js: Vec<PathBuf>,
+    self_contained: bool,
+    highlight_js: bool,
+    themes: Vec<ColorScheme>,
+    default_theme: String,
+    preferred_dark_theme: String,
 }
 
 impl MDBook {
@@ -54,12 +74,31 @@ impl MDBook {
             title: String::new(),
             author: String::new(),
             description: String::new(),
+            language: String::from("en"),
+            identifier: None,
 
             content: vec![],
             renderer: Box::new(HtmlHandlebars::new()),
 
             livereload: None,
             buildfull: fullbuild,
+            svg_diagrams: false,
+            math: false,
+            prerender_math: false,
+            js_engines: None,
+            playground: String::from("https://play.rust-lang.org/evaluate.json"),
+            strict: false,
+            fold_level: None,
+            markdown_toc: true,
+            man_pages: None,
+            man_section: String::from("1"),
+            additional_css: vec![],
+            additional_js: vec![],
+            self_contained: false,
+            highlight_js: false,
+            themes: ColorScheme::builtin(),
+            default_theme: String::from("light"),
+            preferred_dark_theme: String::from("navy"),
         }
     }
 
@@ -267,6 +306,16 @@ impl MDBook {
         let mut highlight_js = try!(File::create(&theme_dir.join("highlight.js")));
         try!(highlight_js.write_all(theme::HIGHLIGHT_JS));
 
+        // partials/*.hbs
+        let partials_dir = theme_dir.join("partials");
+        if !partials_dir.exists() {
+            try!(fs::create_dir(&partials_dir));
+        }
+        for &(name, partial) in theme::PARTIALS {
+            let mut file = try!(File::create(&partials_dir.join(name).with_extension("hbs")));
+            try!(file.write_all(partial));
+        }
+
         Ok(())
     }
 
@@ -295,10 +344,29 @@ impl MDBook {
         self.title = config.title;
         self.description = config.description;
         self.author = config.author;
+        self.language = config.language;
+        self.identifier = config.identifier;
 
         self.dest = config.dest;
         self.src = config.src;
 
+        self.svg_diagrams = config.svg_diagrams;
+        self.math = config.math;
+        self.prerender_math = config.prerender_math;
+        self.js_engines = config.js_engines;
+        self.playground = config.playground;
+        self.fold_level = config.fold_level;
+        self.markdown_toc = config.markdown_toc;
+        self.man_pages = config.man_pages;
+        self.man_section = config.man_section;
+        self.additional_css = config.additional_css;
+        self.additional_js = config.additional_js;
+        self.self_contained = config.self_contained;
+        self.highlight_js = config.highlight_js;
+        self.themes = config.themes;
+        self.default_theme = config.default_theme;
+        self.preferred_dark_theme = config.preferred_dark_theme;
+
         self
     }
 
@@ -435,6 +503,33 @@ impl MDBook {
         &self.description
     }
 
+    /// Sets the language of the book, as a language tag like `en` or `pt-BR`. It is `en` by
+    /// default.
+
+    pub fn set_language(mut self, language: &str) -> Self {
+        self.language = language.to_owned();
+        self
+    }
+
+    pub fn get_language(&self) -> &str {
+        &self.language
+    }
+
+    /// Sets the unique identifier of the book, like `urn:isbn:...`. E-readers use it to recognize
+    /// new versions of the book.
+
+    pub fn set_identifier(mut self, identifier: &str) -> Self {
+        self.identifier = Some(identifier.to_owned());
+        self
+    }
+
+    pub fn get_identifier(&self) -> Option<&str> {
+        match self.identifier {
+            Some(ref identifier) => Some(&identifier),
+            None => None,
+        }
+    }
+
     pub fn set_livereload(&mut self, livereload: String) -> &mut Self {
         self.livereload = Some(livereload);
         self
@@ -452,8 +547,223 @@ impl MDBook {
         }
     }
 
-    // Construct book
-    fn parse_summary(&mut self) -> Result<(), Box<Error>> {
+    /// When enabled, nomnoml and railroad diagrams are rendered to inline SVG while building the
+    /// book instead of being drawn by JavaScript in the browser. Diagrams that use syntax the
+    /// build-time renderer does not understand are still left to the browser.
+
+    pub fn set_svg_diagrams(mut self, svg_diagrams: bool) -> Self {
+        self.svg_diagrams = svg_diagrams;
+        self
+    }
+
+    pub fn get_svg_diagrams(&self) -> bool {
+        self.svg_diagrams
+    }
+
+    /// When enabled, math between `$...$`, `$$...$$`, `\\(...\\)` and `\\[...\\]` is protected from
+    /// the markdown parser and MathJax is loaded by the pages that contain math. Books without math
+    /// keep their dollar signs as they are.
+
+    pub fn set_math(mut self, math: bool) -> Self {
+        self.math = math;
+        self
+    }
+
+    pub fn get_math(&self) -> bool {
+        self.math
+    }
+
+    /// When enabled together with `math`, math is converted to MathML while building the book and
+    /// MathJax is not loaded by the pages. Expressions the converter does not support are left as TeX.
+
+    pub fn set_prerender_math(mut self, prerender_math: bool) -> Self {
+        self.prerender_math = prerender_math;
+        self
+    }
+
+    pub fn get_prerender_math(&self) -> bool {
+        self.prerender_math
+    }
+
+    /// Sets the JavaScript engines (`mermaid`, `nomnoml`, `mathjax`, `jsxgraph` and `railroad`) that
+    /// are included in every page. By default only the engines a page actually uses are included.
+
+    pub fn set_js_engines(mut self, js_engines: Vec<String>) -> Self {
+        self.js_engines = Some(js_engines);
+        self
+    }
+
+    pub fn get_js_engines(&self) -> Option<&[String]> {
+        match self.js_engines {
+            Some(ref js_engines) => Some(&js_engines),
+            None => None,
+        }
+    }
+
+    /// Sets the url of the playground the run button of playpens sends the code to,
+    /// by default the code is run on `https://play.rust-lang.org/evaluate.json`
+
+    pub fn set_playground(mut self, playground: &str) -> Self {
+        self.playground = playground.to_owned();
+        self
+    }
+
+    pub fn get_playground(&self) -> &str {
+        &self.playground
+    }
+
+    /// In strict mode, the build fails when the book contains broken links. Without it broken
+    /// links are only reported.
+
+    pub fn set_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn get_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Collapses the sections of the sidebar nested deeper than `fold_level`, a fold level of 1
+    /// only shows the top level chapters. The reader can still expand them. By default every
+    /// section is expanded.
+
+    pub fn set_fold_level(mut self, fold_level: usize) -> Self {
+        self.fold_level = Some(fold_level);
+        self
+    }
+
+    pub fn get_fold_level(&self) -> Option<usize> {
+        self.fold_level
+    }
+
+    /// Whether the markdown renderer starts `book.md` with a table of contents linking to the
+    /// chapters. It does by default.
+
+    pub fn set_markdown_toc(mut self, markdown_toc: bool) -> Self {
+        self.markdown_toc = markdown_toc;
+        self
+    }
+
+    pub fn get_markdown_toc(&self) -> bool {
+        self.markdown_toc
+    }
+
+    /// Sets the chapters the man renderer turns into man pages, as paths relative to the source
+    /// directory like `commands/build.md`. By default every chapter becomes a man page.
+
+    pub fn set_man_pages(mut self, man_pages: Vec<String>) -> Self {
+        self.man_pages = Some(man_pages);
+        self
+    }
+
+    pub fn get_man_pages(&self) -> Option<&[String]> {
+        match self.man_pages {
+            Some(ref man_pages) => Some(&man_pages),
+            None => None,
+        }
+    }
+
+    /// Sets the section of the man pages whose front matter does not give one, `1` by default
+
+    pub fn set_man_section(mut self, man_section: &str) -> Self {
+        self.man_section = man_section.to_owned();
+        self
+    }
+
+    pub fn get_man_section(&self) -> &str {
+        &self.man_section
+    }
+
+    /// Sets stylesheets included in every page after the ones of the theme. They are copied to the
+    /// output directory: files in the source directory keep their place there, other files keep
+    /// their path relative to the root of the book.
+
+    pub fn set_additional_css(mut self, additional_css: Vec<PathBuf>) -> Self {
+        self.additional_css = additional_css;
+        self
+    }
+
+    pub fn get_additional_css(&self) -> &[PathBuf] {
+        &self.additional_css
+    }
+
+    /// Sets scripts included at the end of every page, they are copied like the
+    /// [additional stylesheets](#method.set_additional_css)
+
+    pub fn set_additional_js(mut self, additional_js: Vec<PathBuf>) -> Self {
+        self.additional_js = additional_js;
+        self
+    }
+
+    pub fn get_additional_js(&self) -> &[PathBuf] {
+        &self.additional_js
+    }
+
+    /// When enabled, the html output does not depend on other hosts: the build fails when the theme
+    /// loads assets like scripts, stylesheets or fonts from other hosts, and playpens have no run
+    /// button when the playground is on another host.
+
+    pub fn set_self_contained(mut self, self_contained: bool) -> Self {
+        self.self_contained = self_contained;
+        self
+    }
+
+    pub fn get_self_contained(&self) -> bool {
+        self.self_contained
+    }
+
+    /// When enabled, the pages include highlight.js to highlight the code blocks in the languages
+    /// mdBook does not highlight while building the book
+
+    pub fn set_highlight_js(mut self, highlight_js: bool) -> Self {
+        self.highlight_js = highlight_js;
+        self
+    }
+
+    pub fn get_highlight_js(&self) -> bool {
+        self.highlight_js
+    }
+
+    /// Sets the colour schemes listed in the theme picker, by default the built-in `light`, `rust`,
+    /// `coal` and `navy` schemes
+
+    pub fn set_themes(mut self, themes: Vec<ColorScheme>) -> Self {
+        self.themes = themes;
+        self
+    }
+
+    pub fn get_themes(&self) -> &[ColorScheme] {
+        &self.themes
+    }
+
+    /// Sets the id of the colour scheme used until the reader picks one, `light` by default
+
+    pub fn set_default_theme(mut self, default_theme: &str) -> Self {
+        self.default_theme = default_theme.to_owned();
+        self
+    }
+
+    pub fn get_default_theme(&self) -> &str {
+        &self.default_theme
+    }
+
+    /// Sets the id of the colour scheme used instead of the default one when the reader's system
+    /// prefers dark colours, `navy` by default
+
+    pub fn set_preferred_dark_theme(mut self, preferred_dark_theme: &str) -> Self {
+        self.preferred_dark_theme = preferred_dark_theme.to_owned();
+        self
+    }
+
+    pub fn get_preferred_dark_theme(&self) -> &str {
+        &self.preferred_dark_theme
+    }
+
+    /// Reads the structure of the book from `SUMMARY.md`, without building it. `build()` and `test()`
+    /// already do this.
+
+    pub fn parse_summary(&mut self) -> Result<(), Box<Error>> {
         // When append becomes stable, use self.content.append() ...
         self.content = try!(parse::construct_bookitems(&self.src.join("SUMMARY.md")));
         Ok(())
@@ -74,6 +74,8 @@ extern crate serde_json;
 extern crate handlebars;
 extern crate pulldown_cmark;
 extern crate htmlescape;
+extern crate grass;
+extern crate flate2;
 
 #[macro_use] extern crate log;
 #[macro_use] extern crate lazy_static;
@@ -0,0 +1,286 @@
+//! Renders the book to an EPUB 3 file, `book.epub` in the destination directory.
+//!
+//! Every chapter is written as an XHTML file at the same place as its markdown file in the
+//! source directory, under `OEBPS/` in the archive. The theme stylesheets and the local images
+//! used by the chapters are included, and the tables of contents are built from `SUMMARY.md`.
+
+extern crate uuid;
+extern crate zip;
+
+mod package;
+mod xhtml;
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use self::uuid::Uuid;
+use self::zip::CompressionMethod;
+use self::zip::write::{FileOptions, ZipWriter};
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use renderer::html_handlebars::{helpers, links, print};
+use {theme, utils};
+
+use self::package::{ManifestItem, Metadata, TocEntry};
+
+pub struct Epub;
+
+impl Epub {
+    pub fn new() -> Self {
+        Epub
+    }
+}
+
+impl Renderer for Epub {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (epub)");
+
+        let mut theme = theme::Theme::new(book.get_src());
+        // Only the stylesheets of the theme are used, not the other compiled ones
+        try!(theme.compile_stylesheets(book.get_src()));
+        let stylesheets = [("book.css", &theme.css), ("highlight.css", &theme.highlight_css)];
+
+        let pages: BTreeSet<PathBuf> = book.iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(_, ref ch) |
+                BookItem::Affix(ref ch) if ch.path != PathBuf::new() => Some(ch.path.with_extension("html")),
+                _ => None,
+            })
+            .collect();
+
+        let path = book.get_dest().join("book.epub");
+        let mut zip = ZipWriter::new(try!(utils::fs::create_file(&path)));
+
+        // The mimetype has to be the first file of the archive and can not be compressed
+        try!(zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored)));
+        try!(zip.write_all(b"application/epub+zip"));
+        try!(zip.start_file("META-INF/container.xml", FileOptions::default()));
+        try!(zip.write_all(package::CONTAINER.as_bytes()));
+
+        let mut manifest = vec![ManifestItem {
+                                    id: "nav".to_owned(),
+                                    href: "nav.xhtml".to_owned(),
+                                    media_type: "application/xhtml+xml",
+                                    properties: vec!["nav"],
+                                },
+                                ManifestItem {
+                                    id: "ncx".to_owned(),
+                                    href: "toc.ncx".to_owned(),
+                                    media_type: "application/x-dtbncx+xml",
+                                    properties: vec![],
+                                }];
+        let mut spine = vec![];
+        let mut resources = BTreeSet::new();
+
+        for item in book.iter() {
+            let ch = match *item {
+                BookItem::Chapter(_, ref ch) |
+                BookItem::Affix(ref ch) if ch.path != PathBuf::new() => ch,
+                _ => continue,
+            };
+
+            let mut content = try!(super::read_chapter(book, &ch.path));
+            if book.get_svg_diagrams() {
+                content = helpers::nomnoml::render_nomnoml_svg(&content);
+                content = helpers::railroad::render_railroad_svg(&content);
+            }
+
+            // EPUB 3 readers display MathML and SVG but do not run MathJax or the diagram scripts
+            let (html, _) = utils::render_markdown_with_blocks(&content, book.get_math(), true, |info, code| {
+                match info.split_whitespace().next() {
+                    Some("nomnoml") | Some("railroad") if book.get_svg_diagrams() => {
+                        helpers::render_diagram(info, code, true)
+                    },
+                    _ => None,
+                }
+            });
+            let html = rewrite_chapter(&xhtml::to_xhtml(&html), &ch.path, &pages, book.get_src(), &mut resources);
+
+            let root = utils::fs::path_to_root(&ch.path);
+            let links: Vec<String> = stylesheets.iter().map(|&(name, _)| format!("{}{}", root, name)).collect();
+            let mut page = package::xhtml_head(book.get_language(), &ch.name, &links);
+            page.push_str("<body class=\"light\">\n<div id=\"content\" class=\"content\">\n");
+            page.push_str(&html);
+            page.push_str("</div>\n</body>\n</html>\n");
+
+            let href = zip_path(&ch.path.with_extension("xhtml"));
+            try!(zip.start_file(format!("OEBPS/{}", href), FileOptions::default()));
+            try!(zip.write_all(page.as_bytes()));
+            info!("[*] Adding {} to the EPUB ✓", href);
+
+            let mut properties = vec![];
+            if html.contains("<math") {
+                properties.push("mathml");
+            }
+            if html.contains("<svg") {
+                properties.push("svg");
+            }
+
+            let id = format!("chapter-{}", spine.len() + 1);
+            manifest.push(ManifestItem {
+                id: id.clone(),
+                href: href,
+                media_type: "application/xhtml+xml",
+                properties: properties,
+            });
+            spine.push(id);
+        }
+
+        for (i, &(name, css)) in stylesheets.iter().enumerate() {
+            try!(zip.start_file(format!("OEBPS/{}", name), FileOptions::default()));
+            try!(zip.write_all(css));
+            manifest.push(ManifestItem {
+                id: format!("css-{}", i + 1),
+                href: name.to_owned(),
+                media_type: "text/css",
+                properties: vec![],
+            });
+        }
+
+        for (i, resource) in resources.iter().enumerate() {
+            let mut data = vec![];
+            try!(try!(File::open(book.get_src().join(resource))).read_to_end(&mut data));
+
+            let href = zip_path(resource);
+            try!(zip.start_file(format!("OEBPS/{}", href), FileOptions::default()));
+            try!(zip.write_all(&data));
+            manifest.push(ManifestItem {
+                id: format!("resource-{}", i + 1),
+                href: href,
+                media_type: package::media_type(resource).unwrap_or("application/octet-stream"),
+                properties: vec![],
+            });
+        }
+
+        let modified = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let identifier = match book.get_identifier() {
+            Some(identifier) => identifier.to_owned(),
+            None => {
+                info!("[*] No identifier in book.json, the EPUB gets a new one");
+                format!("urn:uuid:{}", Uuid::new_v4().hyphenated())
+            },
+        };
+        let metadata = Metadata {
+            identifier: identifier,
+            title: book.get_title().to_owned(),
+            author: book.get_author().to_owned(),
+            description: book.get_description().to_owned(),
+            language: book.get_language().to_owned(),
+            modified: package::timestamp(modified),
+        };
+        let toc = toc_entries(&book.content);
+
+        try!(zip.start_file("OEBPS/nav.xhtml", FileOptions::default()));
+        try!(zip.write_all(package::nav_xhtml(&metadata, &toc).as_bytes()));
+        try!(zip.start_file("OEBPS/toc.ncx", FileOptions::default()));
+        try!(zip.write_all(package::toc_ncx(&metadata, &toc).as_bytes()));
+        try!(zip.start_file("OEBPS/content.opf", FileOptions::default()));
+        try!(zip.write_all(package::content_opf(&metadata, &manifest, &spine).as_bytes()));
+
+        try!(zip.finish());
+        info!("[*] Creating {:?} ✓", path);
+
+        Ok(())
+    }
+}
+
+/// The table of contents, chapters without a file are only listed for their sub chapters
+fn toc_entries(items: &[BookItem]) -> Vec<TocEntry> {
+    let mut entries = vec![];
+    for item in items {
+        let (label, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (format!("{} {}", s, ch.name), ch),
+            BookItem::Affix(ref ch) => (ch.name.clone(), ch),
+            BookItem::Spacer => continue,
+        };
+
+        let children = toc_entries(&ch.sub_items);
+        let href = if ch.path != PathBuf::new() { Some(zip_path(&ch.path.with_extension("xhtml"))) } else { None };
+        if href.is_some() || !children.is_empty() {
+            entries.push(TocEntry {
+                label: label,
+                href: href,
+                children: children,
+            });
+        }
+    }
+    entries
+}
+
+/// Rewrites the links of a chapter, which are relative to the root of the book, to be relative to
+/// the chapter. Links to chapters point to their XHTML file, `pages` contains the html files of all
+/// the chapters. The local images and other files the chapter uses are added to `resources`.
+fn rewrite_chapter(html: &str,
+                   path: &Path,
+                   pages: &BTreeSet<PathBuf>,
+                   src: &Path,
+                   resources: &mut BTreeSet<PathBuf>)
+                   -> String {
+    let root = utils::fs::path_to_root(path);
+
+    print::rewrite_attributes(html, |name, value| {
+        if (name != "href" && name != "src") || !utils::is_relative_link(value) {
+            return None;
+        }
+
+        let (target, rest) = value.split_at(value.find(|c| c == '#' || c == '?').unwrap_or(value.len()));
+        if target.is_empty() {
+            return None;
+        }
+        let target = match links::normalize(Path::new(target)) {
+            Some(target) => target,
+            None => return None,
+        };
+
+        if pages.contains(&target) {
+            let fragment = rest.find('#').map(|i| &rest[i..]).unwrap_or("");
+            Some(format!("{}{}{}", root, zip_path(&target.with_extension("xhtml")), fragment))
+        } else if package::media_type(&target).is_some() && src.join(&target).is_file() {
+            let link = format!("{}{}", root, zip_path(&target));
+            resources.insert(target);
+            Some(link)
+        } else {
+            None
+        }
+    })
+}
+
+/// The path of a file in the archive, which always uses `/`
+fn zip_path(path: &Path) -> String {
+    path.to_string_lossy().replace("\\", "/")
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_rewrite_chapter() {
+    extern crate tempdir;
+    use std::fs;
+
+    let src = tempdir::TempDir::new("epub").unwrap();
+    fs::create_dir(src.path().join("img")).unwrap();
+    File::create(src.path().join("img/a.png")).unwrap();
+
+    let pages: BTreeSet<PathBuf> = vec![PathBuf::from("intro.html"), PathBuf::from("sub/next.html")]
+        .into_iter()
+        .collect();
+    let html = "<p><a href=\"intro.html#start\">back</a> <a href=\"#details\">here</a> \
+                <a href=\"https://example.com/a.png\">web</a> <img src=\"img/a.png\" alt=\"a\" /> \
+                <img src=\"img/missing.png\" alt=\"b\" /></p>\n";
+    let mut resources = BTreeSet::new();
+
+    assert_eq!(rewrite_chapter(html, Path::new("sub/next.md"), &pages, src.path(), &mut resources),
+               "<p><a href=\"../intro.xhtml#start\">back</a> <a href=\"#details\">here</a> \
+                <a href=\"https://example.com/a.png\">web</a> <img src=\"../img/a.png\" alt=\"a\" /> \
+                <img src=\"img/missing.png\" alt=\"b\" /></p>\n");
+    assert_eq!(resources.into_iter().collect::<Vec<_>>(), vec![PathBuf::from("img/a.png")]);
+}
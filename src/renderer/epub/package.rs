@@ -0,0 +1,312 @@
+//! The documents describing the content of an EPUB: the container pointing to the package
+//! document, the package document (OPF) with the metadata, the manifest and the reading order,
+//! and the tables of contents, the EPUB 3 navigation document and the NCX of EPUB 2 readers.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use htmlescape::encode_minimal;
+
+pub const CONTAINER: &'static str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+    <rootfiles>
+        <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>
+    </rootfiles>
+</container>
+";
+
+pub struct Metadata {
+    pub identifier: String,
+    pub title: String,
+    pub author: String,
+    pub description: String,
+    pub language: String,
+    /// The time of the build, as `2016-01-31T12:00:00Z`
+    pub modified: String,
+}
+
+/// A file of the EPUB, `href` is relative to the package document
+pub struct ManifestItem {
+    pub id: String,
+    pub href: String,
+    pub media_type: &'static str,
+    pub properties: Vec<&'static str>,
+}
+
+/// An entry of the table of contents, `href` is `None` for chapters without a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    pub label: String,
+    pub href: Option<String>,
+    pub children: Vec<TocEntry>,
+}
+
+/// The media type of the files that can be part of an EPUB, from their extension
+pub fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match &*extension {
+        "xhtml" => Some("application/xhtml+xml"),
+        "css" => Some("text/css"),
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time, `2016-01-31T12:00:00Z`
+pub fn timestamp(secs: u64) -> String {
+    let days = secs / 86400;
+    let time = secs % 86400;
+
+    // Converts the days since the epoch to a date of the proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60)
+}
+
+pub fn content_opf(metadata: &Metadata, manifest: &[ManifestItem], spine: &[String]) -> String {
+    let mut opf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    opf.push_str(&format!("<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" \
+                           unique-identifier=\"book-id\" xml:lang=\"{}\">\n",
+                          encode_minimal(&metadata.language)));
+
+    opf.push_str("    <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    opf.push_str(&format!("        <dc:identifier id=\"book-id\">{}</dc:identifier>\n",
+                          encode_minimal(&metadata.identifier)));
+    opf.push_str(&format!("        <dc:title>{}</dc:title>\n", encode_minimal(&metadata.title)));
+    opf.push_str(&format!("        <dc:language>{}</dc:language>\n", encode_minimal(&metadata.language)));
+    if !metadata.author.is_empty() {
+        opf.push_str(&format!("        <dc:creator>{}</dc:creator>\n", encode_minimal(&metadata.author)));
+    }
+    if !metadata.description.is_empty() {
+        opf.push_str(&format!("        <dc:description>{}</dc:description>\n",
+                              encode_minimal(&metadata.description)));
+    }
+    opf.push_str(&format!("        <meta property=\"dcterms:modified\">{}</meta>\n", metadata.modified));
+    opf.push_str("    </metadata>\n");
+
+    opf.push_str("    <manifest>\n");
+    for item in manifest {
+        opf.push_str(&format!("        <item id=\"{}\" href=\"{}\" media-type=\"{}\"",
+                              item.id,
+                              encode_minimal(&item.href),
+                              item.media_type));
+        if !item.properties.is_empty() {
+            opf.push_str(&format!(" properties=\"{}\"", item.properties.join(" ")));
+        }
+        opf.push_str("/>\n");
+    }
+    opf.push_str("    </manifest>\n");
+
+    opf.push_str("    <spine toc=\"ncx\">\n");
+    for id in spine {
+        opf.push_str(&format!("        <itemref idref=\"{}\"/>\n", id));
+    }
+    opf.push_str("    </spine>\n");
+    opf.push_str("</package>\n");
+    opf
+}
+
+/// The navigation document of EPUB 3, `nav.xhtml`
+pub fn nav_xhtml(metadata: &Metadata, toc: &[TocEntry]) -> String {
+    let mut nav = xhtml_head(&metadata.language, &metadata.title, &[]);
+    nav.push_str("<body>\n<nav epub:type=\"toc\" id=\"toc\">\n<h1>Table of Contents</h1>\n");
+    nav_list(toc, &mut nav);
+    nav.push_str("</nav>\n</body>\n</html>\n");
+    nav
+}
+
+fn nav_list(entries: &[TocEntry], nav: &mut String) {
+    nav.push_str("<ol>\n");
+    for entry in entries {
+        nav.push_str("<li>");
+        match entry.href {
+            Some(ref href) => {
+                nav.push_str(&format!("<a href=\"{}\">{}</a>", encode_minimal(href), encode_minimal(&entry.label)))
+            },
+            None => nav.push_str(&format!("<span>{}</span>", encode_minimal(&entry.label))),
+        }
+        if !entry.children.is_empty() {
+            nav.push('\n');
+            nav_list(&entry.children, nav);
+        }
+        nav.push_str("</li>\n");
+    }
+    nav.push_str("</ol>\n");
+}
+
+/// The table of contents of EPUB 2, `toc.ncx`. Chapters without a file point to their first sub
+/// chapter.
+pub fn toc_ncx(metadata: &Metadata, toc: &[TocEntry]) -> String {
+    let mut ncx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ncx.push_str(&format!("<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\" \
+                           xml:lang=\"{}\">\n",
+                          encode_minimal(&metadata.language)));
+    ncx.push_str("<head>\n");
+    ncx.push_str(&format!("<meta name=\"dtb:uid\" content=\"{}\"/>\n", encode_minimal(&metadata.identifier)));
+    ncx.push_str(&format!("<meta name=\"dtb:depth\" content=\"{}\"/>\n", depth(toc)));
+    ncx.push_str("<meta name=\"dtb:totalPageCount\" content=\"0\"/>\n");
+    ncx.push_str("<meta name=\"dtb:maxPageNumber\" content=\"0\"/>\n");
+    ncx.push_str("</head>\n");
+    ncx.push_str(&format!("<docTitle><text>{}</text></docTitle>\n", encode_minimal(&metadata.title)));
+
+    ncx.push_str("<navMap>\n");
+    let mut play_order = HashMap::new();
+    let mut count = 0;
+    nav_points(toc, &mut play_order, &mut count, &mut ncx);
+    ncx.push_str("</navMap>\n</ncx>\n");
+    ncx
+}
+
+fn nav_points(entries: &[TocEntry], play_order: &mut HashMap<String, usize>, count: &mut usize, ncx: &mut String) {
+    for entry in entries {
+        let href = match first_href(entry) {
+            Some(href) => href.to_owned(),
+            None => continue,
+        };
+
+        // Points to the same file share their place in the reading order
+        let next = play_order.len() + 1;
+        let order = *play_order.entry(href.clone()).or_insert(next);
+        *count += 1;
+
+        ncx.push_str(&format!("<navPoint id=\"navpoint-{}\" playOrder=\"{}\">\n", count, order));
+        ncx.push_str(&format!("<navLabel><text>{}</text></navLabel>\n", encode_minimal(&entry.label)));
+        ncx.push_str(&format!("<content src=\"{}\"/>\n", encode_minimal(&href)));
+        nav_points(&entry.children, play_order, count, ncx);
+        ncx.push_str("</navPoint>\n");
+    }
+}
+
+fn first_href(entry: &TocEntry) -> Option<&str> {
+    match entry.href {
+        Some(ref href) => Some(href),
+        None => entry.children.iter().filter_map(first_href).next(),
+    }
+}
+
+fn depth(entries: &[TocEntry]) -> usize {
+    entries.iter().map(|e| 1 + depth(&e.children)).max().unwrap_or(0)
+}
+
+/// The start of an XHTML document up to the end of its head, `stylesheets` are linked from it
+pub fn xhtml_head(language: &str, title: &str, stylesheets: &[String]) -> String {
+    let mut head = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+    head.push_str(&format!("<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" \
+                            lang=\"{0}\" xml:lang=\"{0}\">\n",
+                           encode_minimal(language)));
+    head.push_str("<head>\n<meta charset=\"UTF-8\" />\n");
+    head.push_str(&format!("<title>{}</title>\n", encode_minimal(title)));
+    for stylesheet in stylesheets {
+        head.push_str(&format!("<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\" />\n",
+                               encode_minimal(stylesheet)));
+    }
+    head.push_str("</head>\n");
+    head
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[cfg(test)]
+fn test_metadata() -> Metadata {
+    Metadata {
+        identifier: "urn:mdbook:book".to_owned(),
+        title: "A & B".to_owned(),
+        author: "Me".to_owned(),
+        description: String::new(),
+        language: "en".to_owned(),
+        modified: "2016-01-31T12:00:00Z".to_owned(),
+    }
+}
+
+#[cfg(test)]
+fn test_toc() -> Vec<TocEntry> {
+    vec![TocEntry {
+             label: "Intro".to_owned(),
+             href: Some("intro.xhtml".to_owned()),
+             children: vec![],
+         },
+         TocEntry {
+             label: "1. Draft".to_owned(),
+             href: None,
+             children: vec![TocEntry {
+                                label: "1.1. Sub".to_owned(),
+                                href: Some("dir/sub.xhtml".to_owned()),
+                                children: vec![],
+                            }],
+         }]
+}
+
+#[test]
+fn test_timestamp() {
+    assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+    assert_eq!(timestamp(1454241600), "2016-01-31T12:00:00Z");
+    assert_eq!(timestamp(951782400 + 3661), "2000-02-29T01:01:01Z");
+}
+
+#[test]
+fn test_media_type() {
+    assert_eq!(media_type(Path::new("img/a.PNG")), Some("image/png"));
+    assert_eq!(media_type(Path::new("a.jpeg")), Some("image/jpeg"));
+    assert_eq!(media_type(Path::new("a.pdf")), None);
+}
+
+#[test]
+fn test_content_opf() {
+    let manifest = vec![ManifestItem {
+                            id: "nav".to_owned(),
+                            href: "nav.xhtml".to_owned(),
+                            media_type: "application/xhtml+xml",
+                            properties: vec!["nav"],
+                        },
+                        ManifestItem {
+                            id: "chapter-1".to_owned(),
+                            href: "intro.xhtml".to_owned(),
+                            media_type: "application/xhtml+xml",
+                            properties: vec![],
+                        }];
+    let opf = content_opf(&test_metadata(), &manifest, &["chapter-1".to_owned()]);
+
+    assert!(opf.contains("<dc:title>A &amp; B</dc:title>"));
+    assert!(opf.contains("<dc:creator>Me</dc:creator>"));
+    assert!(!opf.contains("<dc:description>"));
+    assert!(opf.contains("<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" \
+                          properties=\"nav\"/>"));
+    assert!(opf.contains("<spine toc=\"ncx\">\n        <itemref idref=\"chapter-1\"/>\n    </spine>"));
+}
+
+#[test]
+fn test_nav_xhtml() {
+    let nav = nav_xhtml(&test_metadata(), &test_toc());
+    assert!(nav.contains("<ol>\n<li><a href=\"intro.xhtml\">Intro</a></li>\n\
+                          <li><span>1. Draft</span>\n<ol>\n<li><a href=\"dir/sub.xhtml\">1.1. Sub</a></li>\n</ol>\n\
+                          </li>\n</ol>\n"));
+}
+
+#[test]
+fn test_toc_ncx() {
+    let ncx = toc_ncx(&test_metadata(), &test_toc());
+    assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"2\"/>"));
+    assert!(ncx.contains("<navPoint id=\"navpoint-2\" playOrder=\"2\">\n<navLabel><text>1. Draft</text></navLabel>\n\
+                          <content src=\"dir/sub.xhtml\"/>\n\
+                          <navPoint id=\"navpoint-3\" playOrder=\"2\">"));
+}
@@ -0,0 +1,128 @@
+//! Turns the html rendered from markdown into XHTML, which is what the chapters of an EPUB are
+//! written in. The html of pulldown-cmark is already well-formed, this only fixes what is often
+//! found in the raw html of chapters: void elements that are not closed and named entities XML
+//! does not know.
+
+/// The elements that never have content, they are closed as `<br />` in XHTML
+const VOID_ELEMENTS: &'static [&'static str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Named entities of html that are commonly used, with their code point
+const ENTITIES: &'static [(&'static str, u32)] = &[
+    ("nbsp", 160), ("iexcl", 161), ("cent", 162), ("pound", 163), ("yen", 165), ("sect", 167), ("copy", 169),
+    ("laquo", 171), ("reg", 174), ("deg", 176), ("plusmn", 177), ("micro", 181), ("para", 182),
+    ("middot", 183), ("raquo", 187), ("frac12", 189), ("iquest", 191), ("times", 215), ("divide", 247),
+    ("ndash", 8211), ("mdash", 8212), ("lsquo", 8216), ("rsquo", 8217), ("ldquo", 8220), ("rdquo", 8221),
+    ("bull", 8226), ("hellip", 8230), ("prime", 8242), ("euro", 8364), ("trade", 8482), ("larr", 8592),
+    ("uarr", 8593), ("rarr", 8594), ("darr", 8595), ("harr", 8596), ("rArr", 8658), ("hArr", 8660),
+    ("ne", 8800), ("le", 8804), ("ge", 8805),
+];
+
+/// Closes the void elements and replaces the named entities that are not part of XML by numeric
+/// character references. An `&` that does not start an entity and a `<` that does not start a tag
+/// are escaped.
+pub fn to_xhtml(html: &str) -> String {
+    close_void_elements(&replace_entities(html))
+}
+
+fn close_void_elements(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(i) = rest.find('<') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        // Tags, closing tags, comments and processing instructions, like `1 < 2` is text
+        let markup = match rest[1..].chars().next() {
+            Some(c) => c.is_alphabetic() || c == '/' || c == '!' || c == '?',
+            None => false,
+        };
+        let end = match tag_end(rest) {
+            Some(end) if markup => end,
+            _ => {
+                out.push_str("&lt;");
+                rest = &rest[1..];
+                continue;
+            },
+        };
+
+        let name: String = rest[1..].chars().take_while(|c| c.is_alphanumeric()).collect();
+
+        let tag = &rest[..end];
+        if VOID_ELEMENTS.contains(&&*name.to_lowercase()) && !tag.ends_with("/>") {
+            out.push_str(tag[..tag.len() - 1].trim_right());
+            out.push_str(" />");
+        } else {
+            out.push_str(tag);
+        }
+        rest = &rest[end..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Returns the position after the `>` closing the tag at the start of `s`, skipping the `>` in
+/// quoted attribute values
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn replace_entities(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let name_end = rest.find(|c: char| !(c.is_alphanumeric() || c == '#')).unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        let terminated = rest[name_end..].starts_with(';');
+
+        match ENTITIES.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, code)) if terminated => {
+                out.push_str(&format!("&#{};", code));
+                rest = &rest[name_end + 1..];
+            },
+            _ => {
+                let known = ["amp", "lt", "gt", "quot", "apos"].contains(&name) || name.starts_with('#');
+                out.push_str(if known && terminated { "&" } else { "&amp;" });
+            },
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_close_void_elements() {
+    assert_eq!(to_xhtml("<p>a<br>b<BR/><img src=\"a.png\" alt=\"a > b\"></p><hr />"),
+               "<p>a<br />b<BR/><img src=\"a.png\" alt=\"a > b\" /></p><hr />");
+    assert_eq!(to_xhtml("<bring>x</bring> 1 < 2"), "<bring>x</bring> 1 &lt; 2");
+    assert_eq!(to_xhtml("a <- b <"), "a &lt;- b &lt;");
+}
+
+#[test]
+fn test_replace_entities() {
+    assert_eq!(to_xhtml("&copy; &amp; &#169; &#xA9; &nbsp;&lt;"),
+               "&#169; &amp; &#169; &#xA9; &#160;&lt;");
+    assert_eq!(to_xhtml("a & b &unknown; &copy"), "a &amp; b &amp;unknown; &amp;copy");
+}
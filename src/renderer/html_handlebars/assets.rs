@@ -0,0 +1,152 @@
+//! Finds the assets a template or stylesheet loads from other hosts, like fonts from a CDN. Books
+//! using them are not self-contained: they do not work offline and every reader's browser contacts
+//! these hosts.
+//!
+//! Links of `<a>` tags are not assets, nothing is loaded until the reader follows them. Comments
+//! are ignored.
+
+use std::fmt;
+
+/// An asset loaded from another host
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalAsset {
+    pub line: usize,
+    pub url: String,
+}
+
+impl fmt::Display for ExternalAsset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: asset loaded from another host `{}`", self.line, self.url)
+    }
+}
+
+/// Finds the `src` attributes, the `href` of `<link>` tags, the `data` of `<object>` tags and the
+/// `url()` and `@import` of stylesheets pointing to other hosts, in the order they appear
+
+pub fn find_external_assets(source: &str) -> Vec<ExternalAsset> {
+    let source = remove_comments(source);
+    let lower = source.to_ascii_lowercase();
+
+    let mut assets = vec![];
+    let patterns = [("src=", None),
+                    ("href=", Some("link")),
+                    ("data=", Some("object")),
+                    ("url(", None),
+                    ("@import", None)];
+    for &(pattern, tag) in &patterns {
+        for (start, _) in lower.match_indices(pattern) {
+            // Attributes are separated by whitespace, `data-src=` is not `src=`
+            if pattern.ends_with('=') && !lower[..start].ends_with(char::is_whitespace) {
+                continue;
+            }
+            if let Some(tag) = tag {
+                if enclosing_tag(&lower[..start]) != Some(tag) {
+                    continue;
+                }
+            }
+
+            let url = read_value(&source[start + pattern.len()..]);
+            if is_external(url) {
+                assets.push((start, url.to_owned()));
+            }
+        }
+    }
+
+    assets.sort_by_key(|&(start, _)| start);
+    assets.into_iter()
+        .map(|(start, url)| {
+            ExternalAsset {
+                line: source[..start].matches('\n').count() + 1,
+                url: url,
+            }
+        })
+        .collect()
+}
+
+/// Replaces html and css comments by spaces, keeping the line breaks so that lines do not move
+fn remove_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+
+    loop {
+        let next = [("<!--", "-->"), ("/*", "*/")]
+            .iter()
+            .filter_map(|&(open, close)| rest.find(open).map(|start| (start, open, close)))
+            .min_by_key(|&(start, _, _)| start);
+
+        let (start, open, close) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        let end = rest[start + open.len()..]
+            .find(close)
+            .map_or(rest.len(), |end| start + open.len() + end + close.len());
+
+        result.push_str(&rest[..start]);
+        result.extend(rest[start..end].chars().map(|c| if c == '\n' { '\n' } else { ' ' }));
+        rest = &rest[end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// The name of the tag `before` ends in, if it ends inside a tag
+fn enclosing_tag(before: &str) -> Option<&str> {
+    let start = match before.rfind('<') {
+        Some(start) if !before[start..].contains('>') => start + 1,
+        _ => return None,
+    };
+    before[start..].split(|c: char| c.is_whitespace()).next()
+}
+
+/// The value following an `=`, `url(` or `@import`, quoted or not. `@import url()` is found as `url()`.
+fn read_value(after: &str) -> &str {
+    let after = after.trim_left();
+
+    match after.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => {
+            let value = &after[1..];
+            &value[..value.find(quote).unwrap_or(value.len())]
+        },
+        _ => {
+            let end = after.find(|c: char| c.is_whitespace() || c == '>' || c == ')' || c == ';');
+            &after[..end.unwrap_or(after.len())]
+        },
+    }
+}
+
+/// Whether `url` points to another host
+pub fn is_external(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_find_external_assets() {
+    let source = r#"<link rel="stylesheet" href="book.css">
+<link href='https://fonts.example.com/css?family=Open+Sans' rel='stylesheet'>
+<a href="https://example.com">Not an asset</a>
+<img data-src="https://example.com/lazy.png" src=//example.com/logo.png>
+<!-- <script src="https://example.com/commented.js"></script> -->
+<script src="{{ path_to_root }}book.js"></script>
+<style>
+    @import "https://example.com/import.css";
+    /* url(https://example.com/commented.png) */
+    body { background: url( 'http://example.com/background.png' ) }
+</style>"#;
+
+    let assets = find_external_assets(source);
+    let assets: Vec<(usize, &str)> = assets.iter().map(|a| (a.line, a.url.as_str())).collect();
+
+    assert_eq!(assets,
+               vec![(2, "https://fonts.example.com/css?family=Open+Sans"),
+                    (4, "//example.com/logo.png"),
+                    (8, "https://example.com/import.css"),
+                    (10, "http://example.com/background.png")]);
+}
@@ -1,4 +1,7 @@
+use renderer::html_handlebars::assets;
 use renderer::html_handlebars::helpers;
+use renderer::html_handlebars::links::LinkChecker;
+use renderer::html_handlebars::print;
 use renderer::Renderer;
 use book::MDBook;
 use book::bookitem::BookItem;
@@ -6,7 +9,7 @@ use {utils, theme};
 
 extern crate zip;
 
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::fs::{self, File};
 use std::error::Error;
 use std::io::{self, Read, Write};
@@ -32,22 +35,55 @@ impl Renderer for HtmlHandlebars {
         let mut handlebars = Handlebars::new();
 
         // Load theme
-        let theme = theme::Theme::new(book.get_src());
+        let mut theme = theme::Theme::new(book.get_src());
+
+        // SCSS stylesheets of the user's theme, the ones not replacing a stylesheet of the theme are
+        // written where a css file of the same name would be copied
+        let mut compiled_css = vec![];
+        for (css_file, css) in try!(theme.compile_stylesheets(book.get_src())) {
+            let mut f = try!(utils::fs::create_file(&book.get_dest().join(additional_file_path(book, &css_file))));
+            try!(f.write_all(&css));
+            compiled_css.push(css_file);
+        }
+
+        // Assets loaded from other hosts
+        let external_assets = try!(check_external_assets(book, &theme));
+        if external_assets > 0 && book.get_self_contained() {
+            return Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                               format!("Found {} assets loaded from other hosts", external_assets))));
+        }
 
         // Register template
         debug!("[*]: Register handlebars template");
         try!(handlebars.register_template_string("index", try!(String::from_utf8(theme.index))));
 
+        // Partials are templates included by name
+        for (name, partial) in theme.partials {
+            try!(handlebars.register_template_string(&name, try!(String::from_utf8(partial))));
+        }
+
         // Register helpers
         debug!("[*]: Register handlebars helpers");
         handlebars.register_helper("toc", Box::new(helpers::toc::RenderToc));
+        handlebars.register_helper("page_toc", Box::new(helpers::page_toc::RenderPageToc));
         handlebars.register_helper("previous", Box::new(helpers::navigation::previous));
         handlebars.register_helper("next", Box::new(helpers::navigation::next));
+        handlebars.register_helper("parent", Box::new(helpers::navigation::parent));
+        handlebars.register_helper("breadcrumbs", Box::new(helpers::navigation::breadcrumbs));
+        handlebars.register_helper("children", Box::new(helpers::navigation::children));
+        handlebars.register_helper("siblings", Box::new(helpers::navigation::siblings));
 
         let mut data = try!(make_data(book));
+        let navigation = try!(make_navigation(book));
 
         // Print version
-        let mut print_content: String = String::new();
+        let mut print_chapters: Vec<(PathBuf, String)> = vec![];
+
+        // JavaScript engines used by the whole book, the print version includes all of them
+        let mut book_engines = Engines::default();
+
+        // Collects the links and headings of every page, to check the links once everything is rendered
+        let mut link_checker = LinkChecker::new();
 
         // Check if dest directory exists
         debug!("[*]: Check if destination directory exists");
@@ -65,27 +101,12 @@ impl Renderer for HtmlHandlebars {
                 BookItem::Affix(ref ch) => {
                     if ch.path != PathBuf::new() {
 
-                        let path = book.get_src().join(&ch.path);
-
-                        debug!("[*]: Opening file: {:?}", path);
-                        let mut f = try!(File::open(&path));
-                        let mut content: String = String::new();
-
-                        debug!("[*]: Reading file");
-                        try!(f.read_to_string(&mut content));
-
-                        // Parse for playpen links
-                        if let Some(p) = path.parent() {
-                            content = helpers::playpen::render_playpen(&content, p);
-                            content = helpers::mermaid::render_mermaid(&content);
-                            content = helpers::nomnoml::render_nomnoml(&content);
-                            content = helpers::jsxgraph::render_jsxgraph(&content);
-                            content = helpers::railroad::render_railroad(&content);
-                        }
+                        let chapter = try!(render_chapter(book, &ch.path));
+                        let engines = chapter.engines;
+                        book_engines.add(&engines);
 
-                        // Render markdown using the pulldown-cmark crate
-                        content = utils::render_markdown(&content);
-                        print_content.push_str(&content);
+                        link_checker.add_page(book.get_src(), &ch.path, &chapter.source, &chapter.html);
+                        print_chapters.push((ch.path.clone(), chapter.html.clone()));
 
                         // Remove content from previous file and render content for this one
                         data.remove("path");
@@ -102,12 +123,25 @@ impl Renderer for HtmlHandlebars {
 
                         // Remove content from previous file and render content for this one
                         data.remove("content");
-                        data.insert("content".to_owned(), content.to_json());
+                        data.insert("content".to_owned(), chapter.html.to_json());
+
+                        // Outline of this page, for the page table of contents
+                        data.remove("headings");
+                        data.insert("headings".to_owned(), headings_to_json(&chapter.headings));
 
                         // Remove path to root from previous file and render content for this one
                         data.remove("path_to_root");
                         data.insert("path_to_root".to_owned(), utils::fs::path_to_root(&ch.path).to_json());
 
+                        // Chapters surrounding this page
+                        match navigation.get(&ch.path) {
+                            Some(navigation) => navigation.insert_into(&mut data),
+                            None => Navigation::remove_from(&mut data),
+                        }
+
+                        // Only include the scripts this page needs
+                        engines.insert_into(&mut data);
+
                         // Rendere the handlebars template with the data
                         debug!("[*]: Render template");
                         let rendered = try!(handlebars.render("index", &data));
@@ -156,12 +190,20 @@ impl Renderer for HtmlHandlebars {
 
         // Remove content from previous file and render content for this one
         data.remove("content");
-        data.insert("content".to_owned(), print_content.to_json());
+        data.insert("content".to_owned(), print::render_print(book, &print_chapters).to_json());
+
+        // The print version has no page table of contents
+        data.remove("headings");
+
+        // The print version contains every chapter, there is nothing to navigate to
+        Navigation::remove_from(&mut data);
 
         // Remove path to root from previous file and render content for this one
         data.remove("path_to_root");
         data.insert("path_to_root".to_owned(), utils::fs::path_to_root(Path::new("print.md")).to_json());
 
+        book_engines.insert_into(&mut data);
+
         // Rendere the handlebars template with the data
         debug!("[*]: Render template");
         let rendered = try!(handlebars.render("index", &data));
@@ -196,7 +238,7 @@ impl Renderer for HtmlHandlebars {
         };
         try!(favicon_file.write_all(&theme.favicon));
 
-        // JQuery local fallback
+        // JQuery
         let mut jquery = if let Ok(f) = File::create(book.get_dest().join("jquery.js")) {
             f
         } else {
@@ -219,6 +261,7 @@ impl Renderer for HtmlHandlebars {
         };
         try!(tomorrow_night_css.write_all(&theme.tomorrow_night_css));
 
+        // Still written when `highlight-js` is off, for themes that include it themselves
         let mut highlight_js = if let Ok(f) = File::create(book.get_dest().join("highlight.js")) {
             f
         } else {
@@ -226,20 +269,192 @@ impl Renderer for HtmlHandlebars {
         };
         try!(highlight_js.write_all(&theme.highlight_js));
 
+        // Fonts
+        for &(name, font) in theme::FONTS {
+            let mut file = try!(utils::fs::create_file(&book.get_dest().join("fonts").join(name)));
+            try!(file.write_all(font));
+        }
+
         // Copy all remaining files
-        try!(utils::fs::copy_files_except_ext(book.get_src(), book.get_dest(), true, &["md"]));
+        try!(utils::fs::copy_files_except_ext(book.get_src(), book.get_dest(), true, &["md", "scss"]));
+
+        // Asset directories of the theme, like fonts or images
+        for dir in &theme.asset_dirs {
+            if let Some(name) = dir.file_name() {
+                let target = book.get_dest().join(name);
+                try!(fs::create_dir_all(&target));
+                try!(utils::fs::copy_files_except_ext(dir, &target, true, &["scss"]));
+            }
+        }
 
+        // Additional stylesheets and scripts, and the stylesheets of the colour schemes
+        let mut files: Vec<&Path> = book.get_additional_css()
+            .iter()
+            .chain(book.get_additional_js())
+            .map(|file| file.as_path())
+            .collect();
+        for scheme in book.get_themes() {
+            files.extend(scheme.css.as_ref().map(|file| file.as_path()));
+            files.extend(scheme.highlight_css.as_ref().map(|file| file.as_path()));
+        }
+        for file in files {
+            let target = book.get_dest().join(additional_file_path(book, file));
+            if target.as_path() == file || compiled_css.iter().any(|css| css == file) {
+                continue;
+            }
+            if !file.is_file() {
+                return Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                                   format!("Could not find additional file {:?}", file))));
+            }
+            try!(utils::fs::create_file(&target));
+            try!(fs::copy(file, &target));
+            info!("[*] Copying {:?} ✓", target);
+        }
+
+        // Only write the assets of the JavaScript engines the book uses
         write_fontawesome(book)?;
-        write_nomnoml(book)?;
-        write_mermaid(book)?;
-        write_mathjax(book)?;
-        write_jsxgraph(book)?;
-        write_railroad(book)?;
+        if book_engines.nomnoml {
+            write_nomnoml(book)?;
+        }
+        if book_engines.mermaid {
+            write_mermaid(book)?;
+        }
+        if book_engines.mathjax {
+            write_mathjax(book)?;
+        }
+        if book_engines.jsxgraph {
+            write_jsxgraph(book)?;
+        }
+        if book_engines.railroad {
+            write_railroad(book)?;
+        }
+
+        // Check the links between the pages
+        let broken_links = link_checker.check(book.get_dest());
+        for link in &broken_links {
+            warn!("[-] {}", link);
+        }
+        if !broken_links.is_empty() && book.get_strict() {
+            return Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                               format!("Found {} broken links", broken_links.len()))));
+        }
 
         Ok(())
     }
 }
 
+/// A chapter read from its file and rendered to html, like the pages of the book show it
+pub struct RenderedChapter {
+    /// The file as it is written
+    pub source: String,
+    pub front_matter: BTreeMap<String, String>,
+    pub html: String,
+    pub headings: Vec<utils::Heading>,
+    engines: Engines,
+}
+
+/// Reads the chapter at `path`, relative to the source directory, and renders it: the front matter
+/// is split off, the included files and the blocks like playpens are spliced in and the markdown
+/// is rendered with the code blocks of diagrams turned into the html of the diagram.
+
+pub fn render_chapter(book: &MDBook, path: &Path) -> Result<RenderedChapter, Box<Error>> {
+    let file = book.get_src().join(path);
+
+    debug!("[*]: Opening file: {:?}", file);
+    let mut source = String::new();
+    try!(try!(File::open(&file)).read_to_string(&mut source));
+
+    // Splice included files into the chapter, after the front matter
+    let (front_matter, mut content) = {
+        let (front_matter, content) = utils::split_front_matter(&source);
+        (front_matter, helpers::include::render_include(content, &file))
+    };
+
+    // Detect the JavaScript engines the page needs
+    let mut engines = Engines::detect(&content, book);
+
+    // Parse for playpen links
+    if let Some(p) = file.parent() {
+        content = helpers::render_blocks(&content, p, book);
+    }
+
+    engines.mathjax = book.get_math() && !book.get_prerender_math() && utils::math::contains_math(&content);
+    if let Some(list) = book.get_js_engines() {
+        engines = Engines::from_list(list);
+    }
+
+    // Render markdown using the pulldown-cmark crate, the code blocks of diagrams become the html
+    // of the diagram
+    let diagrams = |info: &str, code: &str| helpers::render_diagram(info, code, book.get_svg_diagrams());
+    let (html, headings) =
+        utils::render_markdown_with_blocks(&content, book.get_math(), book.get_prerender_math(), diagrams);
+
+    Ok(RenderedChapter {
+        source: source,
+        front_matter: front_matter,
+        html: html,
+        headings: headings,
+        engines: engines,
+    })
+}
+
+/// The bundled JavaScript engines a page needs. Their scripts are only included in the pages that
+/// use them and their assets are only written when at least one page does.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Engines {
+    mermaid: bool,
+    nomnoml: bool,
+    mathjax: bool,
+    jsxgraph: bool,
+    railroad: bool,
+}
+
+impl Engines {
+    /// Detects the diagram engines used by the markdown source of a page
+    fn detect(content: &str, book: &MDBook) -> Self {
+        Engines {
+            mermaid: helpers::mermaid::uses_mermaid(content),
+            nomnoml: helpers::nomnoml::uses_nomnoml(content, book.get_svg_diagrams()),
+            mathjax: false,
+            jsxgraph: helpers::jsxgraph::uses_jsxgraph(content),
+            railroad: helpers::railroad::uses_railroad(content, book.get_svg_diagrams()),
+        }
+    }
+
+    /// Engines given by the `js-engines` list in `book.json`
+    fn from_list(list: &[String]) -> Self {
+        let mut engines = Engines::default();
+        for name in list {
+            match name.as_str() {
+                "mermaid" => engines.mermaid = true,
+                "nomnoml" => engines.nomnoml = true,
+                "mathjax" => engines.mathjax = true,
+                "jsxgraph" => engines.jsxgraph = true,
+                "railroad" => engines.railroad = true,
+                _ => warn!("[-] Unknown JavaScript engine in js-engines: {}", name),
+            }
+        }
+        engines
+    }
+
+    fn add(&mut self, other: &Engines) {
+        self.mermaid |= other.mermaid;
+        self.nomnoml |= other.nomnoml;
+        self.mathjax |= other.mathjax;
+        self.jsxgraph |= other.jsxgraph;
+        self.railroad |= other.railroad;
+    }
+
+    fn insert_into(&self, data: &mut serde_json::Map<String, serde_json::Value>) {
+        data.insert("mermaid".to_owned(), self.mermaid.to_json());
+        data.insert("nomnoml".to_owned(), self.nomnoml.to_json());
+        data.insert("mathjax".to_owned(), self.mathjax.to_json());
+        data.insert("jsxgraph".to_owned(), self.jsxgraph.to_json());
+        data.insert("railroad".to_owned(), self.railroad.to_json());
+    }
+}
+
 fn write_railroad(book: &MDBook) -> Result<(), Box<Error>> {
     let buf: &[u8] = include_bytes!("railroad.zip");
     if book.get_buildfull() || !book.get_dest().join(Path::new("railroad")).exists() {
@@ -318,14 +533,319 @@ fn write_zip(buf: &[u8], book: &MDBook) -> Result<(), Box<Error>> {
     Ok(())
 }
 
+fn headings_to_json(headings: &[utils::Heading]) -> serde_json::Value {
+    headings.iter()
+        .map(|heading| {
+            let mut h = BTreeMap::new();
+            h.insert("level".to_owned(), heading.level.to_json());
+            h.insert("text".to_owned(), heading.text.to_json());
+            h.insert("id".to_owned(), heading.id.to_json());
+            h
+        })
+        .collect::<Vec<_>>()
+        .to_json()
+}
+
+/// Turns a path to a markdown file into the link to the html file generated for it
+fn html_link(path: &Path) -> Result<String, Box<Error>> {
+    match path.with_extension("html").to_str() {
+        // Hack for windows who tends to use `\` as separator instead of `/`
+        Some(p) => Ok(p.replace("\\", "/")),
+        None => Err(Box::new(io::Error::new(io::ErrorKind::Other, "Could not convert path to str"))),
+    }
+}
+
+/// The chapters of the book as a tree, every entry has its sub chapters in `children`
+fn toc_to_json(items: &[BookItem]) -> Result<serde_json::Value, Box<Error>> {
+    let mut entries = vec![];
+
+    for item in items {
+        let mut entry = BTreeMap::new();
+
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(s), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => {
+                entry.insert("affix".to_owned(), false.to_json());
+                entry.insert("spacer".to_owned(), true.to_json());
+                entry.insert("children".to_owned(), Vec::<serde_json::Value>::new().to_json());
+                entries.push(entry);
+                continue;
+            },
+        };
+
+        if let Some(section) = section {
+            entry.insert("section".to_owned(), section.to_json());
+        }
+        entry.insert("affix".to_owned(), section.is_none().to_json());
+        entry.insert("spacer".to_owned(), false.to_json());
+        entry.insert("name".to_owned(), ch.name.to_json());
+
+        match ch.path.to_str() {
+            Some(p) => entry.insert("path".to_owned(), p.to_json()),
+            None => return Err(Box::new(io::Error::new(io::ErrorKind::Other, "Could not convert path to str"))),
+        };
+        if ch.path != PathBuf::new() {
+            entry.insert("link".to_owned(), try!(html_link(&ch.path)).to_json());
+        }
+
+        entry.insert("children".to_owned(), try!(toc_to_json(&ch.sub_items)));
+        entries.push(entry);
+    }
+
+    Ok(entries.to_json())
+}
+
+/// A chapter as seen from the navigation of another page, `link` is `None` for chapters without
+/// a file
+#[derive(Debug, Clone)]
+struct NavChapter {
+    name: String,
+    section: Option<String>,
+    link: Option<String>,
+}
+
+impl NavChapter {
+    fn new(item: &BookItem) -> Result<Option<NavChapter>, Box<Error>> {
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(s.clone()), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => return Ok(None),
+        };
+
+        Ok(Some(NavChapter {
+            name: ch.name.clone(),
+            section: section,
+            link: if ch.path != PathBuf::new() { Some(try!(html_link(&ch.path))) } else { None },
+        }))
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut c = BTreeMap::new();
+        c.insert("name".to_owned(), self.name.to_json());
+        if let Some(ref section) = self.section {
+            c.insert("section".to_owned(), section.to_json());
+        }
+        if let Some(ref link) = self.link {
+            c.insert("link".to_owned(), link.to_json());
+        }
+        c.to_json()
+    }
+}
+
+/// The chapters surrounding a page: the previous, next and parent chapter with a file, `None` when
+/// there is no such chapter, and the ancestors, children and siblings of the chapter in the tree of
+/// chapters.
+#[derive(Debug, Clone)]
+struct Navigation {
+    chapter: NavChapter,
+    previous: Option<NavChapter>,
+    next: Option<NavChapter>,
+    parent: Option<NavChapter>,
+    ancestors: Vec<NavChapter>,
+    children: Vec<NavChapter>,
+    siblings: Vec<NavChapter>,
+}
+
+impl Navigation {
+    fn insert_into(&self, data: &mut serde_json::Map<String, serde_json::Value>) {
+        Navigation::remove_from(data);
+
+        for &(key, chapter) in &[("previous_chapter", &self.previous),
+                                 ("next_chapter", &self.next),
+                                 ("parent_chapter", &self.parent)] {
+            if let Some(ref chapter) = *chapter {
+                data.insert(key.to_owned(), chapter.to_json());
+            }
+        }
+
+        // The breadcrumbs end with the current chapter
+        let mut breadcrumbs: Vec<_> = self.ancestors.iter().map(|c| c.to_json()).collect();
+        breadcrumbs.push(self.chapter.to_json());
+        data.insert("breadcrumbs".to_owned(), breadcrumbs.to_json());
+
+        for &(key, chapters) in &[("child_chapters", &self.children), ("sibling_chapters", &self.siblings)] {
+            data.insert(key.to_owned(), chapters.iter().map(|c| c.to_json()).collect::<Vec<_>>().to_json());
+        }
+    }
+
+    fn remove_from(data: &mut serde_json::Map<String, serde_json::Value>) {
+        for key in &["previous_chapter", "next_chapter", "parent_chapter", "breadcrumbs", "child_chapters",
+                     "sibling_chapters"] {
+            data.remove(*key);
+        }
+    }
+}
+
+/// Computes the navigation of every page once, keyed by the path of the chapter
+fn make_navigation(book: &MDBook) -> Result<BTreeMap<PathBuf, Navigation>, Box<Error>> {
+    let mut navigation = vec![];
+    try!(collect_navigation(&book.content, &mut vec![], &mut navigation));
+
+    for i in 0..navigation.len() {
+        if i > 0 {
+            navigation[i].1.previous = Some(navigation[i - 1].1.chapter.clone());
+        }
+        if i + 1 < navigation.len() {
+            navigation[i].1.next = Some(navigation[i + 1].1.chapter.clone());
+        }
+    }
+
+    Ok(navigation.into_iter().collect())
+}
+
+/// Walks the tree of chapters depth first, like `MDBook::iter`, adding the path and navigation of
+/// every chapter with a file. `ancestors` are the chapters containing `items`.
+fn collect_navigation(items: &[BookItem], ancestors: &mut Vec<NavChapter>,
+                      navigation: &mut Vec<(PathBuf, Navigation)>)
+                      -> Result<(), Box<Error>> {
+    let mut chapters = vec![];
+    for item in items {
+        chapters.push(try!(NavChapter::new(item)));
+    }
+
+    for (i, item) in items.iter().enumerate() {
+        let ch = match *item {
+            BookItem::Chapter(_, ref ch) |
+            BookItem::Affix(ref ch) => ch,
+            BookItem::Spacer => continue,
+        };
+        let chapter = match chapters[i] {
+            Some(ref chapter) => chapter.clone(),
+            None => continue,
+        };
+
+        if chapter.link.is_some() {
+            let mut children = vec![];
+            for sub_item in &ch.sub_items {
+                if let Some(child) = try!(NavChapter::new(sub_item)) {
+                    children.push(child);
+                }
+            }
+
+            let page = Navigation {
+                chapter: chapter.clone(),
+                previous: None,
+                next: None,
+                parent: ancestors.iter().rev().find(|a| a.link.is_some()).cloned(),
+                ancestors: ancestors.clone(),
+                children: children,
+                siblings: chapters.iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .filter_map(|(_, sibling)| sibling.clone())
+                    .collect(),
+            };
+            navigation.push((ch.path.clone(), page));
+        }
+
+        ancestors.push(chapter);
+        try!(collect_navigation(&ch.sub_items, ancestors, navigation));
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+/// Reports the assets the templates and stylesheets of the theme, the additional stylesheets and
+/// the stylesheets of the colour schemes load from other hosts and returns how many there are
+
+fn check_external_assets(book: &MDBook, theme: &theme::Theme) -> Result<usize, Box<Error>> {
+    let mut files: Vec<&Path> = book.get_additional_css().iter().map(|file| file.as_path()).collect();
+    for scheme in book.get_themes() {
+        files.extend(scheme.css.as_ref().map(|file| file.as_path()));
+        files.extend(scheme.highlight_css.as_ref().map(|file| file.as_path()));
+    }
+    files.sort();
+    files.dedup();
+
+    let mut stylesheets = vec![];
+    for file in files {
+        // Stylesheets compiled from SCSS are only in the destination directory, missing files are
+        // reported when they are copied
+        let path = if file.is_file() {
+            file.to_owned()
+        } else {
+            book.get_dest().join(additional_file_path(book, file))
+        };
+        if let Ok(mut f) = File::open(&path) {
+            let mut css = vec![];
+            try!(f.read_to_end(&mut css));
+            stylesheets.push((file.strip_prefix(book.get_root()).unwrap_or(file).to_string_lossy().into_owned(), css));
+        }
+    }
+
+    let mut sources = vec![("theme/index.hbs".to_owned(), &theme.index[..])];
+    for (name, partial) in &theme.partials {
+        sources.push((format!("theme/partials/{}.hbs", name), &partial[..]));
+    }
+    sources.push(("theme/book.css".to_owned(), &theme.css[..]));
+    sources.push(("theme/highlight.css".to_owned(), &theme.highlight_css[..]));
+    sources.push(("theme/tomorrow-night.css".to_owned(), &theme.tomorrow_night_css[..]));
+    for &(ref file, ref css) in &stylesheets {
+        sources.push((file.clone(), &css[..]));
+    }
+
+    let mut count = 0;
+    for (file, source) in sources {
+        for asset in assets::find_external_assets(&String::from_utf8_lossy(source)) {
+            warn!("[-] {}: {} is loaded from another host", file, asset);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Where an additional stylesheet, script or stylesheet of a colour scheme is copied, relative to the
+/// destination directory.
+/// Files in the source directory keep their place, files elsewhere in the book keep their path
+/// relative to its root and other files are put at the root of the destination directory.
+
+fn additional_file_path(book: &MDBook, file: &Path) -> PathBuf {
+    match file.strip_prefix(book.get_src()).or_else(|_| file.strip_prefix(book.get_root())) {
+        Ok(path) if path.components().all(|c| c != Component::ParentDir) => path.to_owned(),
+        _ => file.file_name().map_or_else(PathBuf::new, PathBuf::from),
+    }
+}
+
+/// The link to an additional file from the pages, which are relative to the root of the book
+fn additional_file_link(book: &MDBook, file: &Path) -> String {
+    additional_file_path(book, file).to_string_lossy().replace("\\", "/")
+}
+
+/// The link to the stylesheet highlighting code in a colour scheme
+fn highlight_css_link(book: &MDBook, scheme: &theme::ColorScheme) -> String {
+    match scheme.highlight_css {
+        Some(ref file) => additional_file_link(book, file),
+        None if scheme.dark => "tomorrow-night.css".to_owned(),
+        None => "highlight.css".to_owned(),
+    }
+}
+
+/// A value as JavaScript for the scripts of the templates, which output it with `{{{ }}}`. Every
+/// `<` is escaped so a string like `</script>` can not end the script.
+fn script_value(value: &serde_json::Value) -> Result<String, Box<Error>> {
+    Ok(try!(serde_json::to_string(value)).replace("<", "\\u003c"))
+}
+
 fn make_data(book: &MDBook) -> Result<serde_json::Map<String, serde_json::Value>, Box<Error>> {
     debug!("[fn]: make_data");
 
     let mut data = serde_json::Map::new();
-    data.insert("language".to_owned(), "en".to_json());
+    data.insert("language".to_owned(), book.get_language().to_json());
     data.insert("title".to_owned(), book.get_title().to_json());
     data.insert("description".to_owned(), book.get_description().to_json());
     data.insert("favicon".to_owned(), "favicon.png".to_json());
+    if !book.get_self_contained() || !assets::is_external(book.get_playground()) {
+        // Inserted in a script as a javascript string, `</` can not end the script element
+        let playground = try!(serde_json::to_string(&book.get_playground())).replace("</", "<\\/");
+        data.insert("playground".to_owned(), playground.to_json());
+    }
+    if book.get_highlight_js() {
+        data.insert("highlight_js".to_owned(), true.to_json());
+    }
+    if let Some(fold_level) = book.get_fold_level() {
+        data.insert("fold_level".to_owned(), fold_level.to_json());
+    }
     if let Some(livereload) = book.get_livereload() {
         data.insert("livereload".to_owned(), livereload.to_json());
     }
@@ -366,7 +886,170 @@ fn make_data(book: &MDBook) -> Result<serde_json::Map<String, serde_json::Value>
     }
 
     data.insert("chapters".to_owned(), chapters.to_json());
+    data.insert("toc".to_owned(), try!(toc_to_json(&book.content)));
+
+    let additional_links = |files: &[PathBuf]| -> Vec<String> {
+        files.iter().map(|file| additional_file_link(book, file)).collect()
+    };
+    data.insert("additional_css".to_owned(), additional_links(book.get_additional_css()).to_json());
+    data.insert("additional_js".to_owned(), additional_links(book.get_additional_js()).to_json());
+
+    // Colour schemes of the theme picker
+    let mut themes = vec![];
+    for scheme in book.get_themes() {
+        let mut theme = BTreeMap::new();
+        theme.insert("id".to_owned(), scheme.id.to_json());
+        theme.insert("name".to_owned(), scheme.name.to_json());
+        theme.insert("highlight_css".to_owned(), highlight_css_link(book, scheme).to_json());
+        if let Some(ref css) = scheme.css {
+            theme.insert("css".to_owned(), additional_file_link(book, css).to_json());
+        }
+        if scheme.id == book.get_default_theme() {
+            data.insert("highlight_css".to_owned(), highlight_css_link(book, scheme).to_json());
+        }
+        themes.push(theme);
+    }
+    for id in &[book.get_default_theme(), book.get_preferred_dark_theme()] {
+        if !book.get_themes().iter().any(|scheme| scheme.id == *id) {
+            return Err(Box::new(io::Error::new(io::ErrorKind::Other, format!("Unknown theme `{}`", id))));
+        }
+    }
+    data.insert("themes_script".to_owned(), try!(script_value(&themes.to_json())).to_json());
+    data.insert("themes".to_owned(), themes.to_json());
+    data.insert("default_theme".to_owned(), book.get_default_theme().to_json());
+    data.insert("default_theme_script".to_owned(), try!(script_value(&book.get_default_theme().to_json())).to_json());
+    data.insert("preferred_dark_theme".to_owned(), book.get_preferred_dark_theme().to_json());
+    data.insert("preferred_dark_theme_script".to_owned(),
+                try!(script_value(&book.get_preferred_dark_theme().to_json())).to_json());
 
     debug!("[*]: JSON constructed");
     Ok(data)
 }
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_additional_file_path() {
+    let book = MDBook::new(Path::new("/book"), true);
+
+    assert_eq!(additional_file_path(&book, Path::new("/book/src/css/custom.css")),
+               PathBuf::from("css/custom.css"));
+    assert_eq!(additional_file_path(&book, Path::new("/book/theme/custom.css")),
+               PathBuf::from("theme/custom.css"));
+    assert_eq!(additional_file_path(&book, Path::new("/book/../shared/custom.js")),
+               PathBuf::from("custom.js"));
+    assert_eq!(additional_file_path(&book, Path::new("/usr/share/custom.js")), PathBuf::from("custom.js"));
+}
+
+#[test]
+fn test_script_value() {
+    let mut theme = BTreeMap::new();
+    theme.insert("name".to_owned(), "\"Dark\" </script><script>alert(1)</script>".to_json());
+
+    assert_eq!(script_value(&vec![theme].to_json()).unwrap(),
+               "[{\"name\":\"\\\"Dark\\\" \\u003c/script>\\u003cscript>alert(1)\\u003c/script>\"}]");
+}
+
+#[test]
+fn test_check_external_assets() {
+    extern crate tempdir;
+
+    let tmp = tempdir::TempDir::new("mdbook").expect("Could not create a temp dir");
+    utils::fs::create_file(&tmp.path().join("src/extra.css"))
+        .unwrap()
+        .write_all(b"@import url(https://fonts.example.com/font.css);")
+        .unwrap();
+    utils::fs::create_file(&tmp.path().join("src/sepia.css"))
+        .unwrap()
+        .write_all(b".sepia { background: url(\"https://example.com/paper.png\"); }")
+        .unwrap();
+
+    let book = MDBook::new(tmp.path(), true);
+    let theme = theme::Theme::new(book.get_src());
+    let builtin = check_external_assets(&book, &theme).unwrap();
+
+    let (extra, sepia_css) = (book.get_src().join("extra.css"), book.get_src().join("sepia.css"));
+    let mut sepia = theme::ColorScheme::new("sepia", "Sepia", false);
+    sepia.css = Some(sepia_css.clone());
+    let book = book.set_additional_css(vec![extra, sepia_css]).set_themes(vec![sepia]);
+    assert_eq!(check_external_assets(&book, &theme).unwrap(), builtin + 2);
+}
+
+#[test]
+fn test_collect_navigation() {
+    use book::bookitem::Chapter;
+
+    let chapter = |name: &str, path: &str, sub_items: Vec<BookItem>| {
+        let mut ch = Chapter::new(name.to_owned(), PathBuf::from(path));
+        ch.sub_items = sub_items;
+        ch
+    };
+    let idea = BookItem::Chapter("1.2.1.".to_owned(), chapter("Idea", "idea.md", vec![]));
+    let drafts = BookItem::Chapter("1.2.".to_owned(), chapter("Drafts", "", vec![idea]));
+    let install = BookItem::Chapter("1.1.".to_owned(), chapter("Install", "guide/install.md", vec![]));
+    let items = vec![BookItem::Affix(chapter("Introduction", "intro.md", vec![])),
+                     BookItem::Chapter("1.".to_owned(), chapter("Guide", "guide/index.md", vec![install, drafts])),
+                     BookItem::Spacer,
+                     BookItem::Chapter("2.".to_owned(), chapter("Reference", "reference.md", vec![]))];
+
+    let mut book = MDBook::new(Path::new("/book"), true);
+    book.content = items;
+    let navigation = make_navigation(&book).unwrap();
+
+    let names = |chapters: &[NavChapter]| chapters.iter().map(|c| c.name.clone()).collect::<Vec<String>>();
+    let parent = |nav: &Navigation| nav.parent.as_ref().map(|p| p.name.clone());
+    let name = |chapter: &Option<NavChapter>| chapter.as_ref().map(|c| c.name.clone());
+
+    // Only chapters with a file have a page
+    let paths: Vec<&str> = navigation.keys().filter_map(|p| p.to_str()).collect();
+    assert_eq!(paths, vec!["guide/index.md", "guide/install.md", "idea.md", "intro.md", "reference.md"]);
+
+    // A top-level chapter with children
+    let guide = &navigation[Path::new("guide/index.md")];
+    assert_eq!(guide.chapter.link, Some("guide/index.html".to_owned()));
+    assert_eq!(name(&guide.previous), Some("Introduction".to_owned()));
+    assert_eq!(name(&guide.next), Some("Install".to_owned()));
+    assert_eq!(parent(guide), None);
+    assert!(guide.ancestors.is_empty());
+    assert_eq!(names(&guide.children), vec!["Install", "Drafts"]);
+    assert_eq!(names(&guide.siblings), vec!["Introduction", "Reference"]);
+
+    // A leaf, its sibling has no file
+    let install = &navigation[Path::new("guide/install.md")];
+    assert_eq!(parent(install), Some("Guide".to_owned()));
+    assert_eq!(names(&install.ancestors), vec!["Guide"]);
+    assert!(install.children.is_empty());
+    assert_eq!(names(&install.siblings), vec!["Drafts"]);
+
+    // The parent is the closest ancestor with a file, the breadcrumbs have all ancestors
+    let idea = &navigation[Path::new("idea.md")];
+    assert_eq!(parent(idea), Some("Guide".to_owned()));
+    assert_eq!(names(&idea.ancestors), vec!["Guide", "Drafts"]);
+    assert_eq!(idea.ancestors[1].link, None);
+    assert!(idea.siblings.is_empty());
+
+    // The data of the helpers, the breadcrumbs end with the page itself
+    let mut data = serde_json::Map::new();
+    idea.insert_into(&mut data);
+    let breadcrumbs: Vec<&str> = data["breadcrumbs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|c| c.find("name").and_then(|n| n.as_str()))
+        .collect();
+    assert_eq!(breadcrumbs, vec!["Guide", "Drafts", "Idea"]);
+    assert_eq!(data["parent_chapter"].find("link").and_then(|l| l.as_str()), Some("guide/index.html"));
+    assert!(data["child_chapters"].as_array().unwrap().is_empty());
+
+    // A top-level chapter without children
+    let reference = &navigation[Path::new("reference.md")];
+    assert_eq!(name(&reference.previous), Some("Idea".to_owned()));
+    assert!(reference.next.is_none());
+    assert_eq!(parent(reference), None);
+    assert!(reference.children.is_empty());
+    assert_eq!(names(&reference.siblings), vec!["Introduction", "Guide"]);
+    assert_eq!(reference.chapter.section, Some("2.".to_owned()));
+}
@@ -0,0 +1,379 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::Read;
+
+/// Maximum depth of nested includes, as a safety net on top of the cycle detection
+const MAX_DEPTH: usize = 10;
+
+
+/// Replaces every `{{#include path}}` by the raw content of the file, before the markdown is
+/// rendered. `chapter` is the file `s` was read from, the path is relative to its directory and
+/// can be followed by a line range or an anchor name, see [`Selection`](enum.Selection.html).
+///
+/// Included files can include other files, paths are then relative to the included file.
+/// Includes that would include a file that is already being included, or the chapter itself,
+/// are left out.
+
+pub fn render_include(s: &str, chapter: &Path) -> String {
+    let (dir, mut stack) = start(chapter);
+    render_include_nested(s, &dir, &mut stack)
+}
+
+/// The directory of the chapter and the include stack, seeded with the chapter itself
+fn start(chapter: &Path) -> (PathBuf, Vec<PathBuf>) {
+    let dir = chapter.parent().unwrap_or(Path::new("")).to_owned();
+    (dir, vec![chapter.canonicalize().unwrap_or(chapter.to_owned())])
+}
+
+fn render_include_nested(s: &str, path: &Path, stack: &mut Vec<PathBuf>) -> String {
+    let mut previous_end_index = 0;
+    let mut replaced = String::new();
+
+    for include in find_includes(s, path) {
+
+        if include.escaped {
+            replaced.push_str(&s[previous_end_index..include.start_index - 1]);
+            replaced.push_str(&s[include.start_index..include.end_index]);
+            previous_end_index = include.end_index;
+            continue;
+        }
+
+        // Check if the file exists
+        if !include.file.exists() || !include.file.is_file() {
+            warn!("[-] No file exists for {{{{#include }}}}\n    {}", include.file.to_string_lossy());
+            continue;
+        }
+
+        let canonical = include.file.canonicalize().unwrap_or(include.file.clone());
+        if stack.contains(&canonical) || stack.len() >= MAX_DEPTH {
+            warn!("[-] Recursive {{{{#include }}}} of {} is left out", include.file.to_string_lossy());
+            replaced.push_str(&s[previous_end_index..include.start_index]);
+            previous_end_index = include.end_index;
+            continue;
+        }
+
+        // Open file & read file
+        let mut file = if let Ok(f) = File::open(&include.file) {
+            f
+        } else {
+            continue;
+        };
+        let mut file_content = String::new();
+        if let Err(_) = file.read_to_string(&mut file_content) {
+            continue;
+        };
+
+        let content = include.selection.extract(&file_content);
+
+        // Expand the includes of the included file, relative to that file
+        stack.push(canonical);
+        let parent = include.file.parent().unwrap_or(Path::new("")).to_owned();
+        let content = render_include_nested(&content, &parent, stack);
+        stack.pop();
+
+        replaced.push_str(&s[previous_end_index..include.start_index]);
+        replaced.push_str(&content);
+        previous_end_index = include.end_index;
+    }
+
+    replaced.push_str(&s[previous_end_index..]);
+
+    replaced
+}
+
+/// Calls `f` with every file `s` includes, directly or through other included files, and the
+/// included lines with their line number in that file. `chapter` is the file `s` was read from,
+/// like in `render_include`.
+
+pub fn visit_includes<F>(s: &str, chapter: &Path, f: &mut F)
+    where F: FnMut(&Path, &[(usize, &str)])
+{
+    let (dir, mut stack) = start(chapter);
+    visit_includes_nested(s, &dir, f, &mut stack)
+}
+
+fn visit_includes_nested<F>(s: &str, path: &Path, f: &mut F, stack: &mut Vec<PathBuf>)
+    where F: FnMut(&Path, &[(usize, &str)])
+{
+    for include in find_includes(s, path) {
+        if include.escaped || !include.file.is_file() {
+            continue;
+        }
+
+        let canonical = include.file.canonicalize().unwrap_or(include.file.clone());
+        if stack.contains(&canonical) || stack.len() >= MAX_DEPTH {
+            continue;
+        }
+
+        let mut file_content = String::new();
+        if File::open(&include.file).and_then(|mut file| file.read_to_string(&mut file_content)).is_err() {
+            continue;
+        }
+
+        let lines = include.selection.lines(&file_content);
+        f(&include.file, &lines);
+
+        stack.push(canonical);
+        let parent = include.file.parent().unwrap_or(Path::new("")).to_owned();
+        visit_includes_nested(&join_lines(lines.iter().map(|&(_, line)| line)), &parent, f, stack);
+        stack.pop();
+    }
+}
+
+#[derive(PartialEq, Debug)]
+struct Include {
+    start_index: usize,
+    end_index: usize,
+    file: PathBuf,
+    selection: Selection,
+    escaped: bool,
+}
+
+fn find_includes(s: &str, base_path: &Path) -> Vec<Include> {
+    let mut includes = vec![];
+    for (i, _) in s.match_indices("{{#include") {
+        debug!("[*]: find_include");
+
+        let escaped = i > 0 && s[..i].ends_with('\\');
+
+        let end_i = if let Some(n) = s[i..].find("}}") {
+            n
+        } else {
+            continue;
+        } + i + 2;
+
+        // {{#include}} needs a path, {{#includefoo}} is not an include
+        let params = &s[i + 10..end_i - 2];
+        if params.trim().is_empty() || !params.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        let (file, selection) = Selection::parse(params.trim());
+
+        includes.push(Include {
+            start_index: i,
+            end_index: end_i,
+            file: base_path.join(PathBuf::from(file)),
+            selection: selection,
+            escaped: escaped,
+        })
+    }
+
+    includes
+}
+
+
+/// The part of a file to include, given after the file name as in `file.rs:10:20` or
+/// `file.rs:name`.
+///
+/// Line numbers start at 1 and the end is inclusive, `file.rs:10` only includes line 10,
+/// `file.rs:10:` everything from line 10 and `file.rs::20` everything up to line 20.
+///
+/// A name selects the lines between the `ANCHOR: name` and `ANCHOR_END: name` comments. The
+/// lines containing anchors are never included.
+
+#[derive(PartialOrd, PartialEq, Debug, Clone)]
+pub enum Selection {
+    Lines(LineRange),
+    Anchor(String),
+}
+
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl LineRange {
+    pub fn all() -> Self {
+        LineRange {
+            start: 1,
+            end: None,
+        }
+    }
+}
+
+impl Selection {
+    pub fn all() -> Self {
+        Selection::Lines(LineRange::all())
+    }
+
+    /// Splits `file.rs:10:20` or `file.rs:name` in the file and the selection
+    pub fn parse(s: &str) -> (&str, Selection) {
+        let mut parts = s.splitn(3, ':');
+        let file = parts.next().unwrap_or("");
+
+        let start = match parts.next() {
+            Some(start) => start,
+            None => return (file, Selection::all()),
+        };
+        let end = parts.next();
+
+        // An anchor name
+        if end.is_none() && !start.is_empty() && !start.chars().all(|c| c.is_digit(10)) {
+            return (file, Selection::Anchor(start.to_owned()));
+        }
+
+        let start_line = if start.is_empty() { Ok(1) } else { start.parse::<usize>() };
+        let end_line = match end {
+            // `file.rs:10` only includes line 10
+            None => start_line.clone().map(Some),
+            Some("") => Ok(None),
+            Some(end) => end.parse::<usize>().map(Some),
+        };
+
+        match (start_line, end_line) {
+            (Ok(start), Ok(end)) => {
+                (file,
+                 Selection::Lines(LineRange {
+                     start: if start == 0 { 1 } else { start },
+                     end: end,
+                 }))
+            },
+            _ => {
+                warn!("[-] Invalid line range in {}, including the whole file", s);
+                (file, Selection::all())
+            },
+        }
+    }
+
+    pub fn extract(&self, content: &str) -> String {
+        if *self == Selection::all() {
+            return content.to_owned();
+        }
+        join_lines(self.lines(content).into_iter().map(|(_, line)| line))
+    }
+
+    /// The selected lines of `content`, with their line number
+    pub fn lines<'a>(&self, content: &'a str) -> Vec<(usize, &'a str)> {
+        let lines = content.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        match *self {
+            Selection::Lines(range) => {
+                let skip = range.start - 1;
+                let take = match range.end {
+                    Some(end) if end >= range.start => end - skip,
+                    Some(_) => 0,
+                    None => usize::max_value(),
+                };
+
+                lines.skip(skip).take(take).collect()
+            },
+            Selection::Anchor(ref name) => {
+                let lines: Vec<(usize, &str)> = lines.skip_while(|&(_, line)| anchor(line, "ANCHOR:") != Some(name))
+                    .skip(1)
+                    .take_while(|&(_, line)| anchor(line, "ANCHOR_END:") != Some(name))
+                    .filter(|&(_, line)| !line.contains("ANCHOR:") && !line.contains("ANCHOR_END:"))
+                    .collect();
+
+                if lines.is_empty() {
+                    warn!("[-] No lines found for anchor {}", name);
+                }
+
+                lines
+            },
+        }
+    }
+}
+
+/// Returns the name of the anchor on this line, if there is one of the given kind
+fn anchor<'a>(line: &'a str, kind: &str) -> Option<&'a str> {
+    line.find(kind).map(|i| {
+        let name = line[i + kind.len()..].trim_left();
+        let end = name.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).unwrap_or(name.len());
+        &name[..end]
+    })
+}
+
+fn join_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> String {
+    let mut s = String::new();
+    for line in lines {
+        s.push_str(line);
+        s.push('\n');
+    }
+    s
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_find_includes() {
+    let s = "{{#include a.md}} {{#includes}} \\{{#include b.md:10:20}} {{#include c.rs:main}}";
+    let includes = find_includes(s, Path::new("dir"));
+
+    assert!(includes ==
+            vec![Include {
+                     start_index: 0,
+                     end_index: 17,
+                     file: PathBuf::from("dir/a.md"),
+                     selection: Selection::all(),
+                     escaped: false,
+                 },
+                 Include {
+                     start_index: 33,
+                     end_index: 56,
+                     file: PathBuf::from("dir/b.md"),
+                     selection: Selection::Lines(LineRange { start: 10, end: Some(20) }),
+                     escaped: true,
+                 },
+                 Include {
+                     start_index: 57,
+                     end_index: 79,
+                     file: PathBuf::from("dir/c.rs"),
+                     selection: Selection::Anchor("main".to_owned()),
+                     escaped: false,
+                 }]);
+}
+
+#[test]
+fn test_selection_parse() {
+    assert!(Selection::parse("file.rs") == ("file.rs", Selection::all()));
+    assert!(Selection::parse("file.rs:3") == ("file.rs", Selection::Lines(LineRange { start: 3, end: Some(3) })));
+    assert!(Selection::parse("file.rs:3:") == ("file.rs", Selection::Lines(LineRange { start: 3, end: None })));
+    assert!(Selection::parse("file.rs::5") == ("file.rs", Selection::Lines(LineRange { start: 1, end: Some(5) })));
+    assert!(Selection::parse("file.rs:x:y") == ("file.rs", Selection::all()));
+}
+
+#[test]
+fn test_selection_extract() {
+    let content = "one\ntwo\n// ANCHOR: part\nthree\n// ANCHOR: inner\nfour\n// ANCHOR_END: inner\n// ANCHOR_END: \
+                   part\nfive\n";
+
+    assert!(Selection::Lines(LineRange { start: 1, end: Some(2) }).extract(content) == "one\ntwo\n");
+    assert!(Selection::Anchor("part".to_owned()).extract(content) == "three\nfour\n");
+    assert!(Selection::Anchor("inner".to_owned()).extract(content) == "four\n");
+    assert!(Selection::Anchor("missing".to_owned()).extract(content) == "");
+}
+
+#[test]
+fn test_render_include_nested_and_recursive() {
+    extern crate tempdir;
+    use std::io::Write;
+    use std::fs;
+
+    let tmp = tempdir::TempDir::new("").expect("Could not create a temp dir");
+    fs::create_dir(tmp.path().join("sub")).unwrap();
+    File::create(tmp.path().join("sub/a.md")).unwrap().write_all(b"A {{#include b.md}}").unwrap();
+    File::create(tmp.path().join("sub/b.md")).unwrap().write_all(b"B {{#include a.md}}").unwrap();
+
+    let result = render_include("Start {{#include sub/a.md}} end", &tmp.path().join("chapter.md"));
+    assert!(result == "Start A B  end");
+}
+
+#[test]
+fn test_render_include_back_to_chapter() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("").expect("Could not create a temp dir");
+    let chapter = tmp.path().join("chapter.md");
+    let content = "Start {{#include b.md}} end";
+    File::create(&chapter).unwrap().write_all(content.as_bytes()).unwrap();
+    File::create(tmp.path().join("b.md")).unwrap().write_all(b"B {{#include chapter.md}}").unwrap();
+
+    let result = render_include(content, &chapter);
+    assert!(result == "Start B  end");
+}
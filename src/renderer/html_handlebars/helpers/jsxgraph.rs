@@ -8,3 +8,14 @@ pub fn render_jsxgraph(s: &str) -> String {
     BLOCK_RE.replace_all(s, "\n<script type=\"text/javascript\">\n$spec\n</script>\n")
 }
 
+/// The html of a ```` ```jsxgraph ```` code block
+
+pub fn render_fence(spec: &str) -> String {
+    format!("<script type=\"text/javascript\">\n{}</script>\n", spec)
+}
+
+/// Whether the page contains any jsxgraph diagrams, so the jsxgraph script has to be loaded
+
+pub fn uses_jsxgraph(s: &str) -> bool {
+    BLOCK_RE.is_match(s) || !super::code_blocks(s, "jsxgraph").is_empty()
+}
@@ -5,7 +5,19 @@ lazy_static! {
     }
 
 pub fn render_mermaid(s: &str) -> String {
-    BLOCK_RE.replace_all(s, "\n<div class=\"mermaid\" style=\"display:inline;\">\n$spec\n</div>\n")
+    BLOCK_RE.replace_all(s, "\n<div class=\"mermaid\">\n$spec\n</div>\n")
+}
+
+/// The html of a ```` ```mermaid ```` code block
+
+pub fn render_fence(spec: &str) -> String {
+    format!("<div class=\"mermaid\">\n{}</div>\n", spec)
+}
+
+/// Whether the page contains any mermaid diagrams, so the mermaid script has to be loaded
+
+pub fn uses_mermaid(s: &str) -> bool {
+    BLOCK_RE.is_match(s) || !super::code_blocks(s, "mermaid").is_empty()
 }
 
 // ---------------------------------------------------------------------------------
@@ -15,7 +27,7 @@ pub fn render_mermaid(s: &str) -> String {
 #[test]
 fn test_oneline_replacement() {
     let s = "Some random text with {{#mermaid part1}}...";
-    let result: String = render_nomnoml(s);
+    let result: String = render_mermaid(s);
     println!("result:{}", result);
     assert!(result.as_str() == "Some random text with \n<div class=\"mermaid\">\npart1\n</div>\n...");
 }
@@ -23,7 +35,38 @@ fn test_oneline_replacement() {
 #[test]
 fn test_twoline_replacement() {
     let s = "Some random text with {{#mermaid part1\npart2\n}}...";
-    let result: String = render_nomnoml(s);
+    let result: String = render_mermaid(s);
     println!("result:{}", result);
     assert!(result.as_str() == "Some random text with \n<div class=\"mermaid\">\npart1\npart2\n\n</div>\n...");
 }
+
+#[test]
+fn test_fenced_replacement() {
+    use utils;
+
+    let s = "Some text\n\n~~~mermaid\ngraph TD;\n    A-->B;\n~~~\n\n  ```mermaid\n  graph LR;\n  ```\n";
+    let (result, _) = utils::render_markdown_with_blocks(s, false, false, |info, code| {
+        super::render_diagram(info, code, false)
+    });
+    assert!(result.as_str() ==
+            "<p>Some text</p>\n<div class=\"mermaid\">\ngraph TD;\n    A-->B;\n</div>\n<div class=\"mermaid\">\ngraph LR;\n</div>\n");
+}
+
+#[test]
+fn test_other_fences_untouched() {
+    use utils;
+
+    let s = "````markdown\n```mermaid\ngraph TD;\n```\n````\n";
+    let (result, _) = utils::render_markdown_with_blocks(s, false, false, |info, code| {
+        super::render_diagram(info, code, false)
+    });
+    assert!(!result.contains("<div class=\"mermaid\">"));
+}
+
+#[test]
+fn test_uses_mermaid() {
+    assert!(uses_mermaid("```mermaid\ngraph TD;\n```\n"));
+    assert!(uses_mermaid("~~~ mermaid\ngraph TD;\n~~~\n"));
+    assert!(!uses_mermaid("```rust\nfn main() {}\n```"));
+    assert!(!uses_mermaid("````markdown\n```mermaid\ngraph TD;\n```\n````\n"));
+}
@@ -1,8 +1,76 @@
 
 pub mod navigation;
 pub mod toc;
+pub mod page_toc;
 pub mod playpen;
+pub mod include;
 pub mod mermaid;
 pub mod nomnoml;
 pub mod jsxgraph;
-pub mod railroad;
\ No newline at end of file
+pub mod railroad;
+
+use std::path::Path;
+
+use pulldown_cmark::{Parser, Event, Tag};
+
+use book::MDBook;
+
+/// Replaces the playpens and diagrams of a chapter by their html, the files of playpens are read
+/// relative to `dir`, the directory of the chapter
+pub fn render_blocks(content: &str, dir: &Path, book: &MDBook) -> String {
+    let mut content = playpen::render_playpen(content, dir);
+    content = mermaid::render_mermaid(&content);
+    content = jsxgraph::render_jsxgraph(&content);
+
+    if book.get_svg_diagrams() {
+        content = nomnoml::render_nomnoml_svg(&content);
+        railroad::render_railroad_svg(&content)
+    } else {
+        content = nomnoml::render_nomnoml(&content);
+        railroad::render_railroad(&content)
+    }
+}
+
+/// Renders a code block of a diagram, like ```` ```mermaid ````, to the html of the diagram, other
+/// code blocks give `None`. When `svg` is true, the nomnoml and railroad diagrams that can be are
+/// rendered to SVG.
+pub fn render_diagram(info: &str, spec: &str, svg: bool) -> Option<String> {
+    Some(match language(info) {
+        "mermaid" => mermaid::render_fence(spec),
+        "jsxgraph" => jsxgraph::render_fence(spec),
+        "nomnoml" if svg => nomnoml::render_fence_svg(spec),
+        "nomnoml" => nomnoml::render_fence(spec),
+        "railroad" if svg => railroad::render_fence_svg(spec),
+        "railroad" => railroad::render_fence(spec),
+        _ => return None,
+    })
+}
+
+/// The code of every code block of the markdown `s` in `lang`, the first word of the info string
+pub fn code_blocks(s: &str, lang: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut code: Option<String> = None;
+
+    for event in Parser::new(s) {
+        match event {
+            Event::Start(Tag::CodeBlock(ref info)) if language(info) == lang => code = Some(String::new()),
+            Event::Text(ref text) => {
+                if let Some(ref mut code) = code {
+                    code.push_str(text);
+                }
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some(code) = code.take() {
+                    blocks.push(code);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    blocks
+}
+
+fn language(info: &str) -> &str {
+    info.split_whitespace().next().unwrap_or("")
+}
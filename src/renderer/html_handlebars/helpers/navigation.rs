@@ -1,198 +1,104 @@
-use std::path::Path;
 use std::collections::BTreeMap;
 
-use serde_json;
+use serde_json::Value;
 use serde_json::value::ToJson;
 use handlebars::{Handlebars, RenderError, RenderContext, Helper, Context, Renderable};
 
-// Handlebars helper for navigation
+// Handlebars helpers for navigation
+//
+// The chapters surrounding every page are computed once by the renderer and passed in the
+// `previous_chapter`, `next_chapter`, `parent_chapter`, `breadcrumbs`, `child_chapters` and
+// `sibling_chapters` properties.
 
-pub fn previous(c: &Context, _h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+pub fn previous(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
     debug!("[fn]: previous (handlebars helper)");
-
-    debug!("[*]: Get data from context");
-    // get value from context data
-    // rc.get_path() is current json parent path, you should always use it like this
-    // param is the key of value you want to display
-    let chapters = c.navigate(rc.get_path(), "chapters");
-
-    let current = c.navigate(rc.get_path(), "path")
-        .to_string()
-        .replace("\"", "");
-
-
-    debug!("[*]: Decode chapters from JSON");
-    // Decode json format
-    let decoded: Vec<BTreeMap<String, String>> = match serde_json::from_str(&chapters.to_string()) {
-        Ok(data) => data,
-        Err(_) => return Err(RenderError::new("Could not decode the JSON data")),
-    };
-    let mut previous: Option<BTreeMap<String, String>> = None;
-
-
-    debug!("[*]: Search for current Chapter");
-    // Search for current chapter and return previous entry
-    for item in decoded {
-
-        match item.get("path") {
-            Some(path) if !path.is_empty() => {
-                if path == &current {
-
-                    debug!("[*]: Found current chapter");
-                    if let Some(previous) = previous {
-
-                        debug!("[*]: Creating BTreeMap to inject in context");
-                        // Create new BTreeMap to extend the context: 'title' and 'link'
-                        let mut previous_chapter = BTreeMap::new();
-
-                        // Chapter title
-                        match previous.get("name") {
-                            Some(n) => {
-                                debug!("[*]: Inserting title: {}", n);
-                                previous_chapter.insert("title".to_owned(), n.to_json())
-                            },
-                            None => {
-                                debug!("[*]: No title found for chapter");
-                                return Err(RenderError::new("No title found for chapter in JSON data"));
-                            },
-                        };
-
-                        // Chapter link
-
-                        match previous.get("path") {
-                            Some(p) => {
-                                // Hack for windows who tends to use `\` as separator instead of `/`
-                                let path = Path::new(p).with_extension("html");
-                                debug!("[*]: Inserting link: {:?}", path);
-
-                                match path.to_str() {
-                                    Some(p) => {
-                                        previous_chapter.insert("link".to_owned(), p.replace("\\", "/").to_json());
-                                    },
-                                    None => return Err(RenderError::new("Link could not be converted to str")),
-                                }
-                            },
-                            None => return Err(RenderError::new("No path found for chapter in JSON data")),
-                        }
-
-                        debug!("[*]: Inject in context");
-                        // Inject in current context
-                        let updated_context = c.extend(&previous_chapter);
-
-                        debug!("[*]: Render template");
-                        // Render template
-                        match _h.template() {
-                            Some(t) => {
-                                try!(t.render(&updated_context, r, rc));
-                            },
-                            None => return Err(RenderError::new("Error with the handlebars template")),
-                        }
-
-                    }
-
-                    break;
-                } else {
-                    previous = Some(item.clone());
-                }
-            },
-            _ => continue,
-
-        }
-
-    }
-
-    Ok(())
+    render_chapter_link(c, h, r, rc, "previous_chapter")
 }
 
-
-
-
-pub fn next(c: &Context, _h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+pub fn next(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
     debug!("[fn]: next (handlebars helper)");
+    render_chapter_link(c, h, r, rc, "next_chapter")
+}
 
-    debug!("[*]: Get data from context");
-    // get value from context data
-    // rc.get_path() is current json parent path, you should always use it like this
-    // param is the key of value you want to display
-    let chapters = c.navigate(rc.get_path(), "chapters");
-
-    let current = c.navigate(rc.get_path(), "path")
-        .to_string()
-        .replace("\"", "");
-
-    debug!("[*]: Decode chapters from JSON");
-    // Decode json format
-    let decoded: Vec<BTreeMap<String, String>> = match serde_json::from_str(&chapters.to_string()) {
-        Ok(data) => data,
-        Err(_) => return Err(RenderError::new("Could not decode the JSON data")),
-    };
-    let mut previous: Option<BTreeMap<String, String>> = None;
-
-    debug!("[*]: Search for current Chapter");
-    // Search for current chapter and return previous entry
-    for item in decoded {
-
-        match item.get("path") {
-
-            Some(path) if !path.is_empty() => {
-
-                if let Some(previous) = previous {
-
-                    let previous_path = match previous.get("path") {
-                        Some(p) => p,
-                        None => return Err(RenderError::new("No path found for chapter in JSON data")),
-                    };
-
-                    if previous_path == &current {
+pub fn parent(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    debug!("[fn]: parent (handlebars helper)");
+    render_chapter_link(c, h, r, rc, "parent_chapter")
+}
 
-                        debug!("[*]: Found current chapter");
-                        debug!("[*]: Creating BTreeMap to inject in context");
-                        // Create new BTreeMap to extend the context: 'title' and 'link'
-                        let mut next_chapter = BTreeMap::new();
+pub fn breadcrumbs(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    debug!("[fn]: breadcrumbs (handlebars helper)");
+    render_chapter_list(c, h, r, rc, "breadcrumbs")
+}
 
-                        match item.get("name") {
-                            Some(n) => {
-                                debug!("[*]: Inserting title: {}", n);
-                                next_chapter.insert("title".to_owned(), n.to_json());
-                            },
-                            None => return Err(RenderError::new("No title found for chapter in JSON data")),
-                        }
+pub fn children(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    debug!("[fn]: children (handlebars helper)");
+    render_chapter_list(c, h, r, rc, "child_chapters")
+}
 
+pub fn siblings(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    debug!("[fn]: siblings (handlebars helper)");
+    render_chapter_list(c, h, r, rc, "sibling_chapters")
+}
 
-                        let link = Path::new(path).with_extension("html");
-                        debug!("[*]: Inserting link: {:?}", link);
+/// Renders the inner template with the `title` and `link` of the chapter in `key`, nothing is
+/// rendered when the page has no such chapter.
+fn render_chapter_link(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext, key: &str)
+                       -> Result<(), RenderError> {
+    let chapter = c.navigate(rc.get_path(), key);
+    let chapter = match chapter.as_object() {
+        Some(chapter) => chapter,
+        None => return Ok(()),
+    };
 
-                        match link.to_str() {
-                            Some(l) => {
-                                // Hack for windows who tends to use `\` as separator instead of `/`
-                                next_chapter.insert("link".to_owned(), l.replace("\\", "/").to_json());
-                            },
-                            None => return Err(RenderError::new("Link could not converted to str")),
-                        }
+    let name = chapter.get("name").and_then(|n| n.as_str());
+    let link = chapter.get("link").and_then(|l| l.as_str());
+    let (name, link) = match (name, link) {
+        (Some(name), Some(link)) => (name, link),
+        _ => return Err(RenderError::new("No title or link found for chapter in JSON data")),
+    };
 
-                        debug!("[*]: Inject in context");
-                        // Inject in current context
-                        let updated_context = c.extend(&next_chapter);
+    debug!("[*]: Inject in context: {} ({})", name, link);
+    // Create new BTreeMap to extend the context: 'title' and 'link'
+    let mut data = BTreeMap::new();
+    data.insert("title".to_owned(), name.to_json());
+    data.insert("link".to_owned(), link.to_json());
+    let updated_context = c.extend(&data);
+
+    debug!("[*]: Render template");
+    match h.template() {
+        Some(t) => t.render(&updated_context, r, rc),
+        None => Err(RenderError::new("Error with the handlebars template")),
+    }
+}
 
-                        debug!("[*]: Render template");
+/// Renders the inner template once for every chapter of the list in `key`, with the `title`,
+/// `section` and `link` of the chapter and `first` and `last` set for the first and last chapter.
+/// `section` and `link` are empty when the chapter has none.
+fn render_chapter_list(c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext, key: &str)
+                       -> Result<(), RenderError> {
+    let chapters = c.navigate(rc.get_path(), key);
+    let chapters = match chapters.as_array() {
+        Some(chapters) => chapters,
+        None => return Ok(()),
+    };
 
-                        // Render template
-                        match _h.template() {
-                            Some(t) => {
-                                try!(t.render(&updated_context, r, rc));
-                            },
-                            None => return Err(RenderError::new("Error with the handlebars template")),
-                        }
+    let template = match h.template() {
+        Some(t) => t,
+        None => return Err(RenderError::new("Error with the handlebars template")),
+    };
 
-                        break;
-                    }
-                }
+    for (i, chapter) in chapters.iter().enumerate() {
+        let field = |name: &str| chapter.as_object().and_then(|o| o.get(name)).cloned().unwrap_or(Value::Null);
 
-                previous = Some(item.clone());
-            },
+        let mut data = BTreeMap::new();
+        data.insert("title".to_owned(), field("name"));
+        data.insert("section".to_owned(), field("section"));
+        data.insert("link".to_owned(), field("link"));
+        data.insert("first".to_owned(), (i == 0).to_json());
+        data.insert("last".to_owned(), (i + 1 == chapters.len()).to_json());
 
-            _ => continue,
-        }
+        try!(template.render(&c.extend(&data), r, rc));
     }
+
     Ok(())
 }
@@ -1,5 +1,7 @@
 extern crate regex;
 
+use renderer::html_handlebars::svg;
+
 lazy_static! {
         static ref BLOCK_RE: regex::Regex = regex::Regex::new(r"(?ms)\{\{#nomnoml\s+(?P<spec>.*?)\}\}").unwrap();
     }
@@ -8,6 +10,48 @@ pub fn render_nomnoml(s: &str) -> String {
     BLOCK_RE.replace_all(s, "\n<script class=\"nomnoml-text\" type=\"text/plain\">\n$spec\n</script>\n")
 }
 
+/// Same as [`render_nomnoml`](fn.render_nomnoml.html), but the diagrams are rendered to inline SVG.
+/// Diagrams that can not be rendered at build time are left to the browser.
+
+pub fn render_nomnoml_svg(s: &str) -> String {
+    BLOCK_RE.replace_all(s, |caps: &regex::Captures| {
+        let spec = caps.name("spec").unwrap_or("");
+        match svg::nomnoml::render(spec) {
+            Some(svg) => format!("\n<div class=\"nomnoml-svg\">\n{}</div>\n", svg),
+            None => format!("\n<script class=\"nomnoml-text\" type=\"text/plain\">\n{}\n</script>\n", spec),
+        }
+    })
+}
+
+/// The html of a ```` ```nomnoml ```` code block
+
+pub fn render_fence(spec: &str) -> String {
+    format!("<script class=\"nomnoml-text\" type=\"text/plain\">\n{}</script>\n", spec)
+}
+
+/// Same as [`render_fence`](fn.render_fence.html), but the diagram is rendered to inline SVG if it
+/// can be.
+
+pub fn render_fence_svg(spec: &str) -> String {
+    match svg::nomnoml::render(spec) {
+        Some(svg) => format!("<div class=\"nomnoml-svg\">\n{}</div>\n", svg),
+        None => render_fence(spec),
+    }
+}
+
+/// Whether the page contains any nomnoml diagrams that have to be drawn by the nomnoml script.
+/// When `svg` is true, diagrams that can be rendered to SVG at build time don't need the script.
+
+pub fn uses_nomnoml(s: &str, svg: bool) -> bool {
+    let fences = super::code_blocks(s, "nomnoml");
+    if !svg {
+        return !fences.is_empty() || BLOCK_RE.is_match(s);
+    }
+
+    let blocks = BLOCK_RE.captures_iter(s).map(|caps| caps.name("spec").unwrap_or("").to_owned());
+    fences.into_iter().chain(blocks).any(|spec| svg::nomnoml::render(&spec).is_none())
+}
+
 // ---------------------------------------------------------------------------------
 //      Tests
 //
@@ -27,3 +71,35 @@ fn test_twoline_replacement() {
     println!("result:{}", result);
     assert!(result.as_str() == "Some random text with \n<script class=\"nomnoml-text\" type=\"text/plain\">\npart1\npart2\n\n</script>\n...");
 }
+
+#[test]
+fn test_fenced_replacement() {
+    use utils;
+
+    let s = "Some text\n\n   ~~~nomnoml\n   [A]->[B]\n   ~~~\n";
+    let (result, _) = utils::render_markdown_with_blocks(s, false, false, |info, code| {
+        super::render_diagram(info, code, false)
+    });
+    assert!(result.as_str() == "<p>Some text</p>\n<script class=\"nomnoml-text\" type=\"text/plain\">\n[A]->[B]\n</script>\n");
+}
+
+#[test]
+fn test_svg_replacement() {
+    assert!(render_fence_svg("[A]->[B]\n").starts_with("<div class=\"nomnoml-svg\">\n<svg "));
+    assert!(render_fence_svg("[A|[B]]\n") == render_fence("[A|[B]]\n"));
+}
+
+#[test]
+fn test_svg_fallback() {
+    let s = "Some random text with {{#nomnoml [A|[B]]}}...";
+    assert!(render_nomnoml_svg(s) == render_nomnoml(s));
+}
+
+#[test]
+fn test_uses_nomnoml() {
+    assert!(!uses_nomnoml("Just prose", false));
+    assert!(uses_nomnoml("```nomnoml\n[A]->[B]\n```\n", false));
+    assert!(!uses_nomnoml("```nomnoml\n[A]->[B]\n```\n", true));
+    assert!(uses_nomnoml("> ```nomnoml\n> [A|[B]]\n> ```\n", true));
+    assert!(uses_nomnoml("{{#nomnoml [A|[B]]}}", true));
+}
@@ -0,0 +1,150 @@
+use std::path::Path;
+
+use serde_json;
+use handlebars::{Handlebars, HelperDef, RenderError, RenderContext, Helper, Context};
+use htmlescape::encode_minimal;
+
+/// The heading levels shown when the template does not give `min_level` and `max_level`, the first
+/// heading of a page usually is its title
+const DEFAULT_MIN_LEVEL: i64 = 2;
+const DEFAULT_MAX_LEVEL: i64 = 4;
+
+// Handlebars helper to construct the table of contents of the current page
+#[derive(Clone, Copy)]
+pub struct RenderPageToc;
+
+impl HelperDef for RenderPageToc {
+    fn call(&self, c: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+        debug!("[fn]: page_toc (handlebars helper)");
+
+        let level = |name, default| h.hash_get(name).and_then(|v| v.value().as_i64()).unwrap_or(default);
+        let (min_level, max_level) = (level("min_level", DEFAULT_MIN_LEVEL), level("max_level", DEFAULT_MAX_LEVEL));
+
+        let current = c.navigate(rc.get_path(), "path").to_string().replace("\"", "");
+        let headings = page_headings(c.navigate(rc.get_path(), "headings"), min_level, max_level);
+
+        try!(rc.writer.write(render_page_toc(&headings, &current).as_bytes()));
+        Ok(())
+    }
+}
+
+/// The level, text and id of the headings between `min_level` and `max_level`. Pages without
+/// headings, like the print version, have none.
+fn page_headings(headings: &serde_json::Value, min_level: i64, max_level: i64) -> Vec<(i64, &str, &str)> {
+    let headings = match headings.as_array() {
+        Some(headings) => headings,
+        None => return vec![],
+    };
+
+    headings.iter()
+        .filter_map(|heading| {
+            match (heading.find("level").and_then(|l| l.as_i64()),
+                   heading.find("text").and_then(|t| t.as_str()),
+                   heading.find("id").and_then(|i| i.as_str())) {
+                (Some(level), Some(text), Some(id)) => Some((level, text, id)),
+                _ => None,
+            }
+        })
+        .filter(|&(level, _, _)| level >= min_level && level <= max_level)
+        .collect()
+}
+
+/// Renders the headings as nested lists, the links include the page because of the `<base>` of
+/// the page.
+fn render_page_toc(headings: &[(i64, &str, &str)], current: &str) -> String {
+    let base = match headings.first() {
+        Some(&(level, _, _)) => level,
+        None => return String::new(),
+    };
+
+    // Hack for windows who tends to use `\` as separator instead of `/`
+    let page = Path::new(current).with_extension("html").to_string_lossy().replace("\\", "/");
+
+    let mut toc = String::from("<ul class=\"page-toc\">");
+    let mut current_level = base;
+
+    for (i, &(level, text, id)) in headings.iter().enumerate() {
+        // A heading is at most one level deeper than the previous one, every list is in an item
+        let level = if level < base {
+            base
+        } else if level > current_level + 1 {
+            current_level + 1
+        } else {
+            level
+        };
+
+        if i > 0 {
+            if level > current_level {
+                while level > current_level {
+                    toc.push_str("<ul>");
+                    current_level += 1;
+                }
+            } else {
+                toc.push_str("</li>");
+                while level < current_level {
+                    toc.push_str("</ul></li>");
+                    current_level -= 1;
+                }
+            }
+        }
+
+        toc.push_str(&format!("<li><a href=\"{}#{}\" data-id=\"{}\">{}</a>", page, id, id, encode_minimal(text)));
+    }
+
+    toc.push_str("</li>");
+    while current_level > base {
+        toc.push_str("</ul></li>");
+        current_level -= 1;
+    }
+    toc.push_str("</ul>");
+
+    toc
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_page_headings() {
+    let json = "[{\"level\": 1, \"text\": \"Title\", \"id\": \"title\"}, \
+                {\"level\": 2, \"text\": \"Install\", \"id\": \"install\"}, \
+                {\"level\": 5, \"text\": \"Deep\", \"id\": \"deep\"}]";
+    let headings: serde_json::Value = serde_json::from_str(json).unwrap();
+
+    assert_eq!(page_headings(&headings, DEFAULT_MIN_LEVEL, DEFAULT_MAX_LEVEL), vec![(2, "Install", "install")]);
+    assert_eq!(page_headings(&headings, 1, 6).len(), 3);
+    assert!(page_headings(&serde_json::Value::Null, 1, 6).is_empty());
+}
+
+#[test]
+fn test_render_page_toc_empty() {
+    assert!(render_page_toc(&[], "intro.md") == "");
+}
+
+#[test]
+fn test_render_page_toc_nested() {
+    let headings = [(2, "Install", "install"), (3, "On <Linux>", "linux"), (3, "On Windows", "windows"),
+                    (2, "Usage", "usage")];
+    let toc = render_page_toc(&headings, "guide/start.md");
+
+    assert!(toc ==
+            "<ul class=\"page-toc\"><li><a href=\"guide/start.html#install\" data-id=\"install\">Install</a>\
+             <ul><li><a href=\"guide/start.html#linux\" data-id=\"linux\">On &lt;Linux&gt;</a></li>\
+             <li><a href=\"guide/start.html#windows\" data-id=\"windows\">On Windows</a></li></ul></li>\
+             <li><a href=\"guide/start.html#usage\" data-id=\"usage\">Usage</a></li></ul>");
+}
+
+#[test]
+fn test_render_page_toc_jumping_levels() {
+    let headings = [(2, "Install", "install"), (4, "On Linux", "linux"), (3, "From source", "source"),
+                    (2, "Usage", "usage")];
+    let toc = render_page_toc(&headings, "start.md");
+
+    assert!(toc ==
+            "<ul class=\"page-toc\"><li><a href=\"start.html#install\" data-id=\"install\">Install</a>\
+             <ul><li><a href=\"start.html#linux\" data-id=\"linux\">On Linux</a></li>\
+             <li><a href=\"start.html#source\" data-id=\"source\">From source</a></li></ul></li>\
+             <li><a href=\"start.html#usage\" data-id=\"usage\">Usage</a></li></ul>");
+}
@@ -2,8 +2,34 @@ use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Read;
 
+use htmlescape::encode_minimal;
+
+use super::include::Selection;
+use utils::highlight;
+
 
 pub fn render_playpen(s: &str, path: &Path) -> String {
+    replace_playpens(s, path, |code, editable| {
+        let class = if editable { "language-rust editable hljs" } else { "language-rust hljs" };
+        let code = highlight::highlight_html("rust", code).unwrap_or_else(|| encode_minimal(code));
+        String::new() + "<pre class=\"playpen\"><code class=\"" + class + "\">" + &code + "</code></pre>"
+    })
+}
+
+/// Replaces the playpens by a markdown code block with the content of the file, for the
+/// renderers that do not output html
+pub fn render_playpen_markdown(s: &str, path: &Path) -> String {
+    replace_playpens(s, path, |code, _| {
+        let fence = if code.contains("```") { "~~~~" } else { "```" };
+        format!("{}rust\n{}\n{}", fence, code.trim_right_matches('\n'), fence)
+    })
+}
+
+/// Calls `render` with the code and the `editable` flag of every playpen and replaces the playpen
+/// by the result
+fn replace_playpens<F>(s: &str, path: &Path, render: F) -> String
+    where F: Fn(&str, bool) -> String
+{
     // When replacing one thing in a string by something with a different length, the indices
     // after that will not correspond, we therefore have to store the difference to correct this
     let mut previous_end_index = 0;
@@ -35,8 +61,7 @@ pub fn render_playpen(s: &str, path: &Path) -> String {
             continue;
         };
 
-        let replacement = String::new() + "<pre class=\"playpen\"><code class=\"language-rust\">" + &file_content +
-                          "</code></pre>";
+        let replacement = render(&playpen.selection.extract(&file_content), playpen.editable);
 
         replaced.push_str(&s[previous_end_index..playpen.start_index]);
         replaced.push_str(&replacement);
@@ -55,6 +80,7 @@ struct Playpen {
     start_index: usize,
     end_index: usize,
     rust_file: PathBuf,
+    selection: Selection,
     editable: bool,
     escaped: bool,
 }
@@ -104,10 +130,13 @@ fn find_playpens(s: &str, base_path: &Path) -> Vec<Playpen> {
             };
         }
 
+        let (file, selection) = Selection::parse(params[0]);
+
         playpens.push(Playpen {
             start_index: i,
             end_index: end_i,
-            rust_file: base_path.join(PathBuf::from(params[0])),
+            rust_file: base_path.join(PathBuf::from(file)),
+            selection: selection,
             editable: editable,
             escaped: escaped,
         })
@@ -152,6 +181,7 @@ fn test_find_playpens_simple_playpen() {
                      start_index: 22,
                      end_index: 42,
                      rust_file: PathBuf::from("file.rs"),
+                     selection: Selection::all(),
                      editable: false,
                      escaped: false,
                  },
@@ -159,6 +189,7 @@ fn test_find_playpens_simple_playpen() {
                      start_index: 47,
                      end_index: 68,
                      rust_file: PathBuf::from("test.rs"),
+                     selection: Selection::all(),
                      editable: false,
                      escaped: false,
                  }]);
@@ -175,6 +206,7 @@ fn test_find_playpens_complex_playpen() {
                      start_index: 22,
                      end_index: 51,
                      rust_file: PathBuf::from("dir/file.rs"),
+                     selection: Selection::all(),
                      editable: true,
                      escaped: false,
                  },
@@ -182,6 +214,7 @@ fn test_find_playpens_complex_playpen() {
                      start_index: 56,
                      end_index: 86,
                      rust_file: PathBuf::from("dir/test.rs"),
+                     selection: Selection::all(),
                      editable: true,
                      escaped: false,
                  }]);
@@ -195,6 +228,46 @@ fn test_find_playpens_escaped_playpen() {
 
     assert!(find_playpens(s, Path::new("")) ==
             vec![
-        Playpen{start_index: 39, end_index: 68, rust_file: PathBuf::from("file.rs"), editable: true, escaped: true},
+        Playpen{start_index: 39, end_index: 68, rust_file: PathBuf::from("file.rs"), selection: Selection::all(), editable: true, escaped: true},
     ]);
 }
+
+#[test]
+fn test_find_playpens_selection() {
+    use super::include::LineRange;
+
+    let s = "{{#playpen file.rs:10:20}} {{#playpen file.rs:main editable}}";
+    let playpens = find_playpens(s, Path::new(""));
+
+    assert!(playpens.iter().all(|p| p.rust_file == PathBuf::from("file.rs")));
+    assert!(playpens[0].selection == Selection::Lines(LineRange { start: 10, end: Some(20) }));
+    assert!(playpens[1].selection == Selection::Anchor("main".to_owned()));
+    assert!(playpens[1].editable);
+}
+
+#[test]
+fn test_render_playpen_escapes_and_marks_editable() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("").expect("Could not create a temp dir");
+    File::create(tmp.path().join("lt.rs")).unwrap().write_all(b"fn main() { let a = 1 < 2; }\n").unwrap();
+
+    let result = render_playpen("{{#playpen lt.rs editable}}", tmp.path());
+    assert!(result ==
+            "<pre class=\"playpen\"><code class=\"language-rust editable hljs\"><span class=\"hljs-keyword\">fn</span> \
+             <span class=\"hljs-title\">main</span>() { <span class=\"hljs-keyword\">let</span> a = \
+             <span class=\"hljs-number\">1</span> &lt; <span class=\"hljs-number\">2</span>; }\n</code></pre>");
+}
+
+#[test]
+fn test_render_playpen_markdown() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("").expect("Could not create a temp dir");
+    File::create(tmp.path().join("main.rs")).unwrap().write_all(b"fn main() {}\n").unwrap();
+
+    let result = render_playpen_markdown("Run it:\n\n{{#playpen main.rs}}\n", tmp.path());
+    assert!(result == "Run it:\n\n```rust\nfn main() {}\n```\n");
+}
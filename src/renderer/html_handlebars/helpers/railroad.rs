@@ -1,5 +1,7 @@
 extern crate regex;
 
+use renderer::html_handlebars::svg;
+
 lazy_static! {
         static ref BLOCK_RE: regex::Regex = regex::Regex::new(r"(?ms)\{\{#railroad\s+(?P<spec>.*?)\}\}").unwrap();
     }
@@ -8,3 +10,45 @@ pub fn render_railroad(s: &str) -> String {
     BLOCK_RE.replace_all(s, "\n<script type=\"text/javascript\">\n$spec\n</script>\n")
 }
 
+/// Same as [`render_railroad`](fn.render_railroad.html), but the diagrams are rendered to inline SVG.
+/// Diagrams that can not be rendered at build time are left to the browser.
+
+pub fn render_railroad_svg(s: &str) -> String {
+    BLOCK_RE.replace_all(s, |caps: &regex::Captures| {
+        let spec = caps.name("spec").unwrap_or("");
+        match svg::railroad::render(spec) {
+            Some(svg) => format!("\n<div class=\"railroad-svg\">\n{}</div>\n", svg),
+            None => format!("\n<script type=\"text/javascript\">\n{}\n</script>\n", spec),
+        }
+    })
+}
+
+/// The html of a ```` ```railroad ```` code block
+
+pub fn render_fence(spec: &str) -> String {
+    format!("<script type=\"text/javascript\">\n{}</script>\n", spec)
+}
+
+/// Same as [`render_fence`](fn.render_fence.html), but the diagram is rendered to inline SVG if it
+/// can be.
+
+pub fn render_fence_svg(spec: &str) -> String {
+    match svg::railroad::render(spec) {
+        Some(svg) => format!("<div class=\"railroad-svg\">\n{}</div>\n", svg),
+        None => render_fence(spec),
+    }
+}
+
+/// Whether the page contains any railroad diagrams that have to be drawn by the railroad script.
+/// When `svg` is true, diagrams that can be rendered to SVG at build time don't need the script.
+
+pub fn uses_railroad(s: &str, svg: bool) -> bool {
+    let fences = super::code_blocks(s, "railroad");
+    if !svg {
+        return !fences.is_empty() || BLOCK_RE.is_match(s);
+    }
+
+    let blocks = BLOCK_RE.captures_iter(s).map(|caps| caps.name("spec").unwrap_or("").to_owned());
+    fences.into_iter().chain(blocks).any(|spec| svg::railroad::render(&spec).is_none())
+}
+
@@ -1,7 +1,4 @@
-use std::path::Path;
-use std::collections::BTreeMap;
-
-use serde_json;
+use serde_json::Value;
 use handlebars::{Handlebars, HelperDef, RenderError, RenderContext, Helper, Context};
 use pulldown_cmark::{Parser, html, Event, Tag};
 
@@ -15,124 +12,184 @@ impl HelperDef for RenderToc {
         // get value from context data
         // rc.get_path() is current json parent path, you should always use it like this
         // param is the key of value you want to display
-        let chapters = c.navigate(rc.get_path(), "chapters");
-        let current = c.navigate(rc.get_path(), "path").to_string().replace("\"", "");
-        try!(rc.writer.write("<ul class=\"chapter\">".as_bytes()));
+        let toc = c.navigate(rc.get_path(), "toc");
+        let current = c.navigate(rc.get_path(), "path").as_str().unwrap_or("");
+        let fold_level = c.navigate(rc.get_path(), "fold_level").as_u64().map(|l| l as usize);
+
+        let items = match toc.as_array() {
+            Some(items) => items,
+            None => return Err(RenderError::new("No table of contents found in the JSON data")),
+        };
 
-        // Decode json format
-        let decoded: Vec<BTreeMap<String, String>> = serde_json::from_str(&chapters.to_string()).unwrap();
+        let mut out = String::new();
+        render_toc(items, current, fold_level, 1, &mut out);
 
-        let mut current_level = 1;
+        try!(rc.writer.write(out.as_bytes()));
+        Ok(())
+    }
+}
 
-        for item in decoded {
+/// Renders the entries of the `toc` tree as nested lists, the sub chapters of an entry are in a
+/// `<ul class="section">` following it. `depth` is 1 for the top level chapters.
+///
+/// Entries with sub chapters get a toggle and the `expanded` or `collapsed` class: sections nested
+/// deeper than `fold_level` are collapsed, unless they lead to the current chapter. Returns true
+/// when the current chapter is among `items` or their sub chapters.
+fn render_toc(items: &[Value], current: &str, fold_level: Option<usize>, depth: usize, out: &mut String) -> bool {
+    let mut contains_current = false;
 
-            // Spacer
-            if let Some(_) = item.get("spacer") {
-                try!(rc.writer.write("<li class=\"spacer\"></li>".as_bytes()));
-                continue;
-            }
+    out.push_str(if depth == 1 { "<ul class=\"chapter\">" } else { "<ul class=\"section\">" });
 
-            let level = if let Some(s) = item.get("section") {
-                s.len() / 2
-            } else {
-                1
-            };
-
-            if level > current_level {
-                while level > current_level {
-                    try!(rc.writer.write("<li>".as_bytes()));
-                    try!(rc.writer.write("<ul class=\"section\">".as_bytes()));
-                    current_level += 1;
-                }
-                try!(rc.writer.write("<li>".as_bytes()));
-            } else if level < current_level {
-                while level < current_level {
-                    try!(rc.writer.write("</ul>".as_bytes()));
-                    try!(rc.writer.write("</li>".as_bytes()));
-                    current_level -= 1;
-                }
-                try!(rc.writer.write("<li>".as_bytes()));
-            } else {
-                try!(rc.writer.write("<li".as_bytes()));
-                if let None = item.get("section") {
-                    try!(rc.writer.write(" class=\"affix\"".as_bytes()));
-                }
-                try!(rc.writer.write(">".as_bytes()));
-            }
+    for item in items {
+        let field = |key: &str| item.as_object().and_then(|o| o.get(key));
 
-            // Link
-            let path_exists = if let Some(path) = item.get("path") {
-                if !path.is_empty() {
-                    try!(rc.writer.write("<a href=\"".as_bytes()));
-
-                    // Add link
-                    try!(rc.writer.write(Path::new(item.get("path")
-                                                       .expect("Error: path should be Some(_)"))
-                                             .with_extension("html")
-                                             .to_str()
-                                             .unwrap()
-                                             // Hack for windows who tends to use `\` as separator instead of `/`
-                                             .replace("\\", "/")
-                                             .as_bytes()));
-
-                    try!(rc.writer.write("\"".as_bytes()));
-
-                    if path == &current {
-                        try!(rc.writer.write(" class=\"active\"".as_bytes()));
-                    }
-
-                    try!(rc.writer.write(">".as_bytes()));
-                    true
-                } else {
-                    false
-                }
-            } else {
-                false
-            };
-
-            // Section does not necessarily exist
-            if let Some(section) = item.get("section") {
-                try!(rc.writer.write("<strong>".as_bytes()));
-                try!(rc.writer.write(section.as_bytes()));
-                try!(rc.writer.write("</strong> ".as_bytes()));
-            }
+        // Spacer
+        if field("spacer").and_then(|s| s.as_bool()).unwrap_or(false) {
+            out.push_str("<li class=\"spacer\"></li>");
+            continue;
+        }
 
-            if let Some(name) = item.get("name") {
-                // Render only inline code blocks
-
-                // filter all events that are not inline code blocks
-                let parser = Parser::new(&name).filter(|event| {
-                    match event {
-                        &Event::Start(Tag::Code) |
-                        &Event::End(Tag::Code) => true,
-                        &Event::InlineHtml(_) => true,
-                        &Event::Text(_) => true,
-                        _ => false,
-                    }
-                });
-
-                // render markdown to html
-                let mut markdown_parsed_name = String::with_capacity(name.len() * 3 / 2);
-                html::push_html(&mut markdown_parsed_name, parser);
-
-                // write to the handlebars template
-                try!(rc.writer.write(markdown_parsed_name.as_bytes()));
+        let link = field("link").and_then(|l| l.as_str());
+        let section = field("section").and_then(|s| s.as_str());
+        let active = field("path").and_then(|p| p.as_str()) == Some(current);
+        contains_current = contains_current || active;
+
+        // Render the sub chapters first, to know whether they lead to the current chapter
+        let mut children = String::new();
+        let mut open = false;
+        if let Some(items) = field("children").and_then(|c| c.as_array()) {
+            if !items.is_empty() {
+                let leads_to_current = render_toc(items, current, fold_level, depth + 1, &mut children);
+                contains_current = contains_current || leads_to_current;
+                open = active || leads_to_current || fold_level.map(|l| depth < l).unwrap_or(true);
             }
+        }
+
+        let mut classes = vec![];
+        if field("affix").and_then(|a| a.as_bool()).unwrap_or(false) {
+            classes.push("affix");
+        }
+        if !children.is_empty() {
+            classes.push(if open { "expanded" } else { "collapsed" });
+        }
+
+        if classes.is_empty() {
+            out.push_str("<li>");
+        } else {
+            out.push_str(&format!("<li class=\"{}\">", classes.join(" ")));
+        }
 
-            if path_exists {
-                try!(rc.writer.write("</a>".as_bytes()));
+        // Link, chapters without a file have none
+        if let Some(link) = link {
+            out.push_str(&format!("<a href=\"{}\"", link));
+            if active {
+                out.push_str(" class=\"active\"");
             }
+            out.push_str(">");
+        }
 
-            try!(rc.writer.write("</li>".as_bytes()));
+        // Section does not necessarily exist
+        if let Some(section) = section {
+            out.push_str(&format!("<strong>{}</strong> ", section));
+        }
 
+        if let Some(name) = field("name").and_then(|n| n.as_str()) {
+            out.push_str(&render_name(name));
         }
-        while current_level > 1 {
-            try!(rc.writer.write("</ul>".as_bytes()));
-            try!(rc.writer.write("</li>".as_bytes()));
-            current_level -= 1;
+
+        if link.is_some() {
+            out.push_str("</a>");
         }
 
-        try!(rc.writer.write("</ul>".as_bytes()));
-        Ok(())
+        // The toggle remembers the state of the section under the link or section number
+        if !children.is_empty() {
+            let key = link.or(section).unwrap_or("");
+            out.push_str(&format!("<a class=\"toggle\" data-key=\"{}\"><i class=\"fa fa-angle-right\"></i></a>", key));
+        }
+        out.push_str("</li>");
+
+        if !children.is_empty() {
+            out.push_str("<li>");
+            out.push_str(&children);
+            out.push_str("</li>");
+        }
     }
+
+    out.push_str("</ul>");
+    contains_current
+}
+
+/// Renders the name of a chapter, only inline code is rendered as markdown
+fn render_name(name: &str) -> String {
+    // filter all events that are not inline code blocks
+    let parser = Parser::new(name).filter(|event| {
+        match event {
+            &Event::Start(Tag::Code) |
+            &Event::End(Tag::Code) => true,
+            &Event::InlineHtml(_) => true,
+            &Event::Text(_) => true,
+            _ => false,
+        }
+    });
+
+    // render markdown to html
+    let mut rendered = String::with_capacity(name.len() * 3 / 2);
+    html::push_html(&mut rendered, parser);
+    rendered
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_render_toc() {
+    use serde_json;
+
+    let toc: Value = serde_json::from_str(r#"[
+        {"affix": true, "spacer": false, "name": "Intro", "path": "intro.md", "link": "intro.html", "children": []},
+        {"affix": false, "spacer": false, "section": "1.", "name": "The `Book`", "path": "book.md",
+         "link": "book.html", "children": [
+            {"affix": false, "spacer": false, "section": "1.1.", "name": "Draft", "path": "", "children": []}
+        ]},
+        {"affix": false, "spacer": true, "children": []}
+    ]"#).unwrap();
+
+    let mut out = String::new();
+    assert!(render_toc(toc.as_array().unwrap(), "book.md", None, 1, &mut out));
+
+    assert_eq!(out,
+               "<ul class=\"chapter\"><li class=\"affix\"><a href=\"intro.html\">Intro</a></li>\
+                <li class=\"expanded\"><a href=\"book.html\" class=\"active\"><strong>1.</strong> The <code>Book</code></a>\
+                <a class=\"toggle\" data-key=\"book.html\"><i class=\"fa fa-angle-right\"></i></a></li>\
+                <li><ul class=\"section\"><li><strong>1.1.</strong> Draft</li></ul></li>\
+                <li class=\"spacer\"></li></ul>");
+}
+
+#[test]
+fn test_render_toc_folded() {
+    use serde_json;
+
+    let toc: Value = serde_json::from_str(r#"[
+        {"section": "1.", "name": "One", "path": "one.md", "link": "one.html", "children": [
+            {"section": "1.1.", "name": "Sub", "path": "one/sub.md", "link": "one/sub.html", "children": [
+                {"section": "1.1.1.", "name": "Deep", "path": "one/deep.md", "link": "one/deep.html", "children": []}
+            ]}
+        ]},
+        {"section": "2.", "name": "Two", "path": "two.md", "link": "two.html", "children": [
+            {"section": "2.1.", "name": "Other", "path": "two/other.md", "link": "two/other.html", "children": []}
+        ]}
+    ]"#).unwrap();
+
+    // The ancestors of the current chapter are expanded, the other sections are collapsed
+    let mut out = String::new();
+    assert!(render_toc(toc.as_array().unwrap(), "one/deep.md", Some(1), 1, &mut out));
+    assert_eq!(out.matches("class=\"expanded\"").count(), 2);
+    assert!(out.contains("<li class=\"collapsed\"><a href=\"two.html\">"));
+
+    let mut out = String::new();
+    assert!(!render_toc(toc.as_array().unwrap(), "intro.md", Some(2), 1, &mut out));
+    assert!(out.contains("<li class=\"expanded\"><a href=\"one.html\">"));
+    assert!(out.contains("<li class=\"collapsed\"><a href=\"one/sub.html\">"));
 }
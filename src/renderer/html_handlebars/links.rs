@@ -0,0 +1,337 @@
+//! Checks that the links between the pages of the book point to pages, files and headings that
+//! exist.
+//!
+//! Pages set `<base href="{{path_to_root}}">`, so relative links are resolved from the root of the
+//! book, not from the directory of the page.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::{Parser, Event, Tag, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+
+use renderer::html_handlebars::helpers::include;
+use utils;
+
+/// A link found in the markdown source of a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub dest: String,
+    pub line: usize,
+}
+
+/// A link pointing to a file or heading that does not exist
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    pub source: PathBuf,
+    pub line: usize,
+    pub dest: String,
+    pub reason: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: broken link to `{}`: {}", self.source.display(), self.line, self.dest, self.reason)
+    }
+}
+
+struct Page {
+    html: PathBuf,
+    links: Vec<(PathBuf, Link)>,
+}
+
+pub struct LinkChecker {
+    pages: Vec<Page>,
+    ids: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl LinkChecker {
+    pub fn new() -> Self {
+        LinkChecker {
+            pages: vec![],
+            ids: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a page, `source` is the path of the markdown file relative to `src`, the source
+    /// directory, `markdown` its content as written and `html` the rendered content.
+    pub fn add_page(&mut self, src: &Path, source: &Path, markdown: &str, html: &str) {
+        let html_path = source.with_extension("html");
+
+        self.ids.insert(html_path.clone(), html_ids(html).into_iter().collect());
+
+        self.pages.push(Page {
+            html: html_path,
+            links: find_chapter_links(src, source, markdown),
+        });
+    }
+
+    /// Checks all links of the registered pages. Links to other files than the pages are checked
+    /// against the files in `dest`, the rendered book.
+    pub fn check(&self, dest: &Path) -> Vec<BrokenLink> {
+        let mut broken = vec![];
+
+        for page in &self.pages {
+            for &(ref source, ref link) in &page.links {
+                if let Err(reason) = self.check_link(page, &link.dest, dest) {
+                    broken.push(BrokenLink {
+                        source: source.clone(),
+                        line: link.line,
+                        dest: link.dest.clone(),
+                        reason: reason,
+                    });
+                }
+            }
+        }
+
+        broken
+    }
+
+    fn check_link(&self, page: &Page, link: &str, dest: &Path) -> Result<(), String> {
+        if !utils::is_relative_link(link) {
+            return Ok(());
+        }
+
+        let link = utils::md_link_to_html(link).unwrap_or(link.to_owned());
+        let (path, fragment) = match link.find('#') {
+            Some(i) => (&link[..i], Some(&link[i + 1..])),
+            None => (&link[..], None),
+        };
+        let path = &path[..path.find('?').unwrap_or(path.len())];
+
+        let target = if path.is_empty() {
+            page.html.clone()
+        } else {
+            match normalize(Path::new(&percent_decode(path))) {
+                Some(target) => target,
+                None => return Err("points outside of the book".to_owned()),
+            }
+        };
+
+        match self.ids.get(&target) {
+            Some(ids) => {
+                match fragment {
+                    Some(fragment) if !fragment.is_empty() && !ids.contains(&percent_decode(fragment)) => {
+                        Err(format!("there is no heading `{}` in {}", fragment, target.display()))
+                    },
+                    _ => Ok(()),
+                }
+            },
+            None => {
+                let file = dest.join(&target);
+                if file.is_file() || file.join("index.html").is_file() {
+                    Ok(())
+                } else {
+                    Err(format!("{} does not exist", target.display()))
+                }
+            },
+        }
+    }
+}
+
+/// Resolves `.` and `..` in a relative path, returns `None` if the path leaves the book
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(p) => normalized.push(p),
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            },
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let high = (bytes[i + 1] as char).to_digit(16);
+            let low = (bytes[i + 2] as char).to_digit(16);
+            if let (Some(high), Some(low)) = (high, low) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn markdown_options() -> Options {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    opts
+}
+
+/// Finds the links and images of a chapter, `path` is the chapter file relative to `src` and
+/// `markdown` its content as written. Links are given with the file they are in, relative to `src`
+/// as well: the chapter or a file it includes with `{{#include}}`, and the line in that file.
+pub fn find_chapter_links(src: &Path, path: &Path, markdown: &str) -> Vec<(PathBuf, Link)> {
+    // The front matter is not rendered, but counts for the line numbers
+    let body = utils::split_front_matter(markdown).1;
+    let front_matter_lines = markdown[..markdown.len() - body.len()].matches('\n').count();
+
+    let mut links: Vec<(PathBuf, Link)> = find_links(body)
+        .into_iter()
+        .map(|link| {
+            (path.to_owned(),
+             Link {
+                 dest: link.dest,
+                 line: link.line + front_matter_lines,
+             })
+        })
+        .collect();
+
+    include::visit_includes(body, &src.join(path), &mut |file, lines| {
+        let file = file.strip_prefix(src).ok().and_then(normalize).unwrap_or(file.to_owned());
+        let text = lines.iter().map(|&(_, line)| line).collect::<Vec<&str>>().join("\n");
+
+        for link in find_links(&text) {
+            let line = lines.get(link.line - 1).map(|&(line, _)| line).unwrap_or(1);
+            links.push((file.clone(),
+                        Link {
+                            dest: link.dest,
+                            line: line,
+                        }));
+        }
+    });
+
+    links
+}
+
+/// Finds the links and images in markdown, with the line they are on
+pub fn find_links(markdown: &str) -> Vec<Link> {
+    let mut links = vec![];
+    let mut offset = 0;
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        let dest = match event {
+            Event::Start(Tag::Link(dest, _)) |
+            Event::Start(Tag::Image(dest, _)) => dest,
+            _ => continue,
+        };
+        if dest.is_empty() {
+            continue;
+        }
+
+        // The parser does not tell where the link is, so look for the destination where it is
+        // written: `](dest`, `<dest>` or the definition `[name]: dest` of a reference link
+        let position = link_position(&markdown[offset..], &dest)
+            .map(|i| i + offset)
+            .or_else(|| link_position(markdown, &dest));
+        let line = match position {
+            Some(position) => {
+                offset = position;
+                markdown[..position].matches('\n').count() + 1
+            },
+            None => 1,
+        };
+
+        links.push(Link {
+            dest: dest.into_owned(),
+            line: line,
+        });
+    }
+
+    links
+}
+
+/// The position of the first destination `dest` of a link in `markdown`
+fn link_position(markdown: &str, dest: &str) -> Option<usize> {
+    markdown.match_indices(dest)
+        .map(|(i, _)| i)
+        .find(|&i| {
+            let before = markdown[..i].trim_right_matches('<').trim_right();
+            before.ends_with("](") || before.ends_with("]:") ||
+            (markdown[..i].ends_with('<') && markdown[i + dest.len()..].starts_with('>'))
+        })
+}
+
+/// The `id` and `name` attributes in html, which can be the target of a link as well
+pub fn html_ids(html: &str) -> Vec<String> {
+    let mut ids = vec![];
+    for attribute in &[" id=\"", " name=\""] {
+        for (i, _) in html.match_indices(attribute) {
+            let start = i + attribute.len();
+            if let Some(end) = html[start..].find('"') {
+                ids.push(html[start..start + end].to_owned());
+            }
+        }
+    }
+    ids
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_find_links() {
+    let markdown = "# Title\n\nSee [the intro](intro.md) and\n[the end](end.md#last \"The end\").\n\n\
+                    ![image](img/a.png)\n\n[intro]: intro.md";
+    assert_eq!(find_links(markdown),
+               vec![Link { dest: "intro.md".to_owned(), line: 3 },
+                    Link { dest: "end.md#last".to_owned(), line: 4 },
+                    Link { dest: "img/a.png".to_owned(), line: 6 }]);
+
+    // The destination written as text before the link is not the link
+    let markdown = "Read a.md first\n\nthen [a](a.md) and <https://example.com>\n\nthen [c][c]\n\n[c]: <c.md>";
+    assert_eq!(find_links(markdown),
+               vec![Link { dest: "a.md".to_owned(), line: 3 },
+                    Link { dest: "https://example.com".to_owned(), line: 3 },
+                    Link { dest: "c.md".to_owned(), line: 7 }]);
+}
+
+#[test]
+fn test_find_chapter_links() {
+    extern crate tempdir;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("").expect("Could not create a temp dir");
+    fs::create_dir(tmp.path().join("sub")).unwrap();
+    File::create(tmp.path().join("sub/part.md"))
+        .unwrap()
+        .write_all(b"one\n// ANCHOR: part\nSee [two](two.md)\n// ANCHOR_END: part\n[three](three.md)\n")
+        .unwrap();
+
+    let markdown = "---\nname: chapter\n---\n# Chapter\n\n{{#include part.md:part}}\n\n[four](four.md)";
+    assert_eq!(find_chapter_links(tmp.path(), Path::new("sub/chapter.md"), markdown),
+               vec![(PathBuf::from("sub/chapter.md"), Link { dest: "four.md".to_owned(), line: 8 }),
+                    (PathBuf::from("sub/part.md"), Link { dest: "two.md".to_owned(), line: 3 })]);
+}
+
+#[test]
+fn test_check_links() {
+    let mut checker = LinkChecker::new();
+    checker.add_page(Path::new("/nonexistent"),
+                     Path::new("intro.md"),
+                     "# Intro\n\n[next](sub/next.md#details) [missing](sub/next.md#nope)\n\n[self](#intro)",
+                     "<h1 id=\"intro\">Intro</h1>");
+    checker.add_page(Path::new("/nonexistent"),
+                     Path::new("sub/next.md"),
+                     "# Details\n\n[back](intro.md)\n\n[up](../../outside.md)\n\n[gone](gone.md)\n\n\
+                      [web](https://example.com)",
+                     "<h1 id=\"details\">Details</h1>");
+
+    let broken = checker.check(Path::new("/nonexistent"));
+    let broken: Vec<String> = broken.iter().map(|b| b.to_string()).collect();
+
+    assert_eq!(broken,
+               vec!["intro.md:3: broken link to `sub/next.md#nope`: there is no heading `nope` in sub/next.html"
+                        .to_owned(),
+                    "sub/next.md:5: broken link to `../../outside.md`: points outside of the book".to_owned(),
+                    "sub/next.md:7: broken link to `gone.md`: gone.html does not exist".to_owned()]);
+}
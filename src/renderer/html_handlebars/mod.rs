@@ -1,4 +1,8 @@
-pub use self::hbs_renderer::HtmlHandlebars;
+pub use self::hbs_renderer::{HtmlHandlebars, render_chapter};
 
+pub mod assets;
 mod hbs_renderer;
-mod helpers;
+pub mod helpers;
+pub mod links;
+pub mod print;
+mod svg;
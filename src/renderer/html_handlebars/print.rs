@@ -0,0 +1,241 @@
+//! Builds the content of `print.html`, the whole book on a single page meant to be printed.
+//!
+//! Every chapter gets a unique anchor derived from its path. The ids of the headings of a chapter
+//! are prefixed with that anchor and a `.`, which is never part of an anchor, so they do not collide
+//! with the headings of other chapters or with the anchors of chapters, and links to other chapters
+//! point to their anchor on the print page.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use htmlescape::encode_minimal;
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::html_handlebars::links;
+use utils;
+
+/// Renders the cover, the table of contents and every chapter, `chapters` holds the path of the
+/// markdown file and the rendered html of the chapters in the order of the book.
+pub fn render_print(book: &MDBook, chapters: &[(PathBuf, String)]) -> String {
+    let anchors = chapter_anchors(chapters.iter().map(|&(ref path, _)| &**path));
+
+    let mut content = render_cover(book.get_title(), book.get_author(), book.get_description());
+    content.push_str(&render_toc(&book.content, &anchors));
+
+    for &(ref path, ref html) in chapters {
+        content.push_str("<div class=\"page-break\"></div>\n");
+        content.push_str(&format!("<div class=\"print-chapter\" id=\"{}\">\n", anchors[&path.with_extension("html")]));
+        content.push_str(&rewrite_chapter(html, path, &anchors));
+        content.push_str("</div>\n");
+    }
+
+    content
+}
+
+/// The anchors of the chapters on the print page, by the html file of the chapter. `dir/chapter.md`
+/// becomes `dir-chapter`, or `dir-chapter-1`, `dir-chapter-2`, ... when an earlier chapter, like
+/// `dir-chapter.md`, already has that anchor.
+pub fn chapter_anchors<'a, I>(paths: I) -> BTreeMap<PathBuf, String>
+    where I: IntoIterator<Item = &'a Path>
+{
+    let mut ids = HashSet::new();
+    let mut anchors = BTreeMap::new();
+    for path in paths {
+        let page = path.with_extension("html");
+        if !anchors.contains_key(&page) {
+            let anchor = utils::unique_id(chapter_anchor(path), &mut ids);
+            anchors.insert(page, anchor);
+        }
+    }
+    anchors
+}
+
+/// The anchor of a chapter before it is made unique, `dir/chapter.md` becomes `dir-chapter`
+fn chapter_anchor(path: &Path) -> String {
+    let path = path.with_extension("").to_string_lossy().replace("\\", "/");
+    utils::normalize_id(&path.replace("/", " "))
+}
+
+/// The anchor of the heading, or footnote, `id` in the chapter with the anchor `chapter`
+pub fn heading_anchor(chapter: &str, id: &str) -> String {
+    format!("{}.{}", chapter, id)
+}
+
+/// The title page of the book
+fn render_cover(title: &str, author: &str, description: &str) -> String {
+    let mut cover = String::from("<div class=\"print-cover\">\n");
+    cover.push_str(&format!("<h1 class=\"print-title\">{}</h1>\n", encode_minimal(title)));
+    if !description.is_empty() {
+        cover.push_str(&format!("<p class=\"print-description\">{}</p>\n", encode_minimal(description)));
+    }
+    if !author.is_empty() {
+        cover.push_str(&format!("<p class=\"print-author\">{}</p>\n", encode_minimal(author)));
+    }
+    cover.push_str("</div>\n");
+    cover
+}
+
+/// The table of contents, linking to the anchors of the chapters
+fn render_toc(items: &[BookItem], anchors: &BTreeMap<PathBuf, String>) -> String {
+    let mut toc = String::from("<div class=\"page-break\"></div>\n<nav class=\"print-toc\">\n");
+    toc.push_str("<h1>Table of Contents</h1>\n");
+    render_toc_items(items, anchors, &mut toc);
+    toc.push_str("</nav>\n");
+    toc
+}
+
+fn render_toc_items(items: &[BookItem], anchors: &BTreeMap<PathBuf, String>, toc: &mut String) {
+    toc.push_str("<ul>");
+    for item in items {
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(s), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => continue,
+        };
+
+        toc.push_str("<li>");
+        if let Some(section) = section {
+            toc.push_str(&format!("<strong>{}</strong> ", section));
+        }
+        match anchors.get(&ch.path.with_extension("html")) {
+            Some(anchor) if ch.path != PathBuf::new() => {
+                toc.push_str(&format!("<a href=\"#{}\">{}</a>", anchor, encode_minimal(&ch.name)));
+            },
+            _ => toc.push_str(&encode_minimal(&ch.name)),
+        }
+        if !ch.sub_items.is_empty() {
+            render_toc_items(&ch.sub_items, anchors, toc);
+        }
+        toc.push_str("</li>");
+    }
+    toc.push_str("</ul>\n");
+}
+
+/// Prefixes the ids of a chapter with its anchor and rewrites links to chapters of the book to
+/// their anchor. `anchors` contains the anchors of all the chapters, from
+/// [`chapter_anchors`](fn.chapter_anchors.html).
+pub fn rewrite_chapter(html: &str, path: &Path, anchors: &BTreeMap<PathBuf, String>) -> String {
+    let page = path.with_extension("html");
+    let anchor = anchors.get(&page).cloned().unwrap_or_else(|| chapter_anchor(path));
+
+    rewrite_attributes(html, |name, value| {
+        match name {
+            "id" | "name" => Some(heading_anchor(&anchor, value)),
+            "href" => rewrite_link(value, &page, anchors),
+            _ => None,
+        }
+    })
+}
+
+/// Rewrites a link to a chapter, or to a heading in a chapter, to the matching anchor on the print
+/// page. Other links are left as they are, they are relative to the root of the book like on the
+/// pages of the chapters.
+pub fn rewrite_link(link: &str, page: &Path, anchors: &BTreeMap<PathBuf, String>) -> Option<String> {
+    if !utils::is_relative_link(link) {
+        return None;
+    }
+
+    let (path, fragment) = match link.find('#') {
+        Some(i) => (&link[..i], Some(&link[i + 1..])),
+        None => (link, None),
+    };
+    let path = &path[..path.find('?').unwrap_or(path.len())];
+
+    let target = if path.is_empty() {
+        page.to_owned()
+    } else {
+        match links::normalize(Path::new(path)) {
+            Some(target) => target,
+            None => return None,
+        }
+    };
+
+    let anchor = match anchors.get(&target) {
+        Some(anchor) => anchor,
+        None => return None,
+    };
+    match fragment {
+        Some(fragment) if !fragment.is_empty() => Some(format!("#{}", heading_anchor(anchor, fragment))),
+        _ => Some(format!("#{}", anchor)),
+    }
+}
+
+/// Calls `rewrite` with the name and value of every `name="value"` attribute in html and replaces
+/// the value by the one returned, if any
+pub fn rewrite_attributes<F>(html: &str, mut rewrite: F) -> String
+    where F: FnMut(&str, &str) -> Option<String>
+{
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(i) = rest.find("=\"") {
+        let value_start = i + 2;
+        let value_end = match rest[value_start..].find('"') {
+            Some(end) => value_start + end,
+            None => break,
+        };
+
+        // The name of the attribute is the word before `=`, preceded by whitespace
+        let before = &rest[..i];
+        let name_start = before.char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '-')
+            .last()
+            .map(|(j, _)| j)
+            .unwrap_or(i);
+        let name = &before[name_start..];
+        let is_attribute = !name.is_empty() && before[..name_start].ends_with(char::is_whitespace);
+
+        let value = &rest[value_start..value_end];
+        let rewritten = if is_attribute { rewrite(name, value) } else { None };
+
+        out.push_str(&rest[..value_start]);
+        out.push_str(rewritten.as_ref().map(|v| &v[..]).unwrap_or(value));
+        out.push('"');
+        rest = &rest[value_end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_chapter_anchor() {
+    assert_eq!(chapter_anchor(Path::new("intro.md")), "intro");
+    assert_eq!(chapter_anchor(Path::new("format/theme/index-hbs.md")), "format-theme-index-hbs");
+}
+
+#[test]
+fn test_chapter_anchors_are_unique() {
+    let paths = vec![Path::new("a/b.md"), Path::new("a-b.md"), Path::new("intro.md"), Path::new("intro-x.md")];
+    let anchors = chapter_anchors(paths);
+
+    assert_eq!(anchors[Path::new("a/b.html")], "a-b");
+    assert_eq!(anchors[Path::new("a-b.html")], "a-b-1");
+
+    // A heading `x` in the intro does not get the anchor of `intro-x.md`
+    let html = rewrite_chapter("<h1 id=\"x\">X</h1>", Path::new("intro.md"), &anchors);
+    assert_eq!(html, "<h1 id=\"intro.x\">X</h1>");
+    assert_eq!(anchors[Path::new("intro-x.html")], "intro-x");
+}
+
+#[test]
+fn test_rewrite_chapter() {
+    let anchors = chapter_anchors(vec![Path::new("intro.md"), Path::new("sub/next.md")]);
+    let html = "<h1 id=\"details\"><a class=\"header\" href=\"#details\">Details</a></h1>\n\
+                <p><a href=\"intro.html\">back</a> <a href=\"sub/./next.html#details\">again</a> \
+                <a href=\"https://example.com/#x\">web</a> <img src=\"img/a.png\" alt=\"a\" /> \
+                <code>x=\"y\"</code></p>\n";
+
+    assert_eq!(rewrite_chapter(html, Path::new("sub/next.md"), &anchors),
+               "<h1 id=\"sub-next.details\"><a class=\"header\" href=\"#sub-next.details\">Details</a></h1>\n\
+                <p><a href=\"#intro\">back</a> <a href=\"#sub-next.details\">again</a> \
+                <a href=\"https://example.com/#x\">web</a> <img src=\"img/a.png\" alt=\"a\" /> \
+                <code>x=\"y\"</code></p>\n");
+}
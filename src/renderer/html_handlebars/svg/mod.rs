@@ -0,0 +1,56 @@
+//! Build-time rendering of diagram specifications to inline SVG.
+//!
+//! Only a subset of what the bundled JavaScript engines understand is supported here. When a spec
+//! can not be rendered, the functions return `None` and the caller falls back to the markup
+//! that lets the browser render the diagram.
+
+pub mod railroad;
+pub mod nomnoml;
+
+
+/// Formats a coordinate without trailing zeros, so that `10.0` becomes `10` and `10.50` `10.5`
+
+fn num(n: f64) -> String {
+    let s = format!("{:.2}", n);
+    let s = s.trim_right_matches('0').trim_right_matches('.');
+    if s == "-0" {
+        "0".to_owned()
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Escapes the characters that can not appear literally in SVG text or attribute values
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_num() {
+    assert_eq!(num(10.0), "10");
+    assert_eq!(num(10.5), "10.5");
+    assert_eq!(num(-0.0), "0");
+    assert_eq!(num(0.25), "0.25");
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+}
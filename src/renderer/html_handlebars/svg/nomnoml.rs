@@ -0,0 +1,638 @@
+//! A small nomnoml renderer.
+//!
+//! It understands classifiers with compartments (`[<abstract> Name|field;field|method()]`) and
+//! associations between them (`[A] label -> [B]`), one statement per line. The classifiers are
+//! ranked top to bottom following the associations, the bundled `dagre` layout engine is not
+//! reproduced. Nested classifiers and directives that change the layout are not supported.
+
+use std::collections::HashMap;
+
+use super::{num, escape};
+
+const PADDING: f64 = 8.0;
+const LINE_HEIGHT: f64 = 18.0;
+const CHAR_WIDTH: f64 = 7.5;
+const SPACING: f64 = 40.0;
+const GUTTER: f64 = 10.0;
+const ARROW_SIZE: f64 = 10.0;
+
+const FILL: &'static str = "#eee8d5";
+const STROKE: &'static str = "#33322E";
+const FONT: &'static str = "font-family=\"Calibri, Helvetica, sans-serif\" font-size=\"12\"";
+
+
+/// Renders a nomnoml spec to an inline `<svg>` element.
+/// Returns `None` if the spec uses something that is not supported at build time.
+
+pub fn render(spec: &str) -> Option<String> {
+    match parse(spec) {
+        Ok(ref graph) if !graph.classifiers.is_empty() => Some(graph.to_svg()),
+        Ok(_) => None,
+        Err(e) => {
+            debug!("[*]: Could not render nomnoml diagram at build time: {}", e);
+            None
+        },
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+struct Classifier {
+    kind: String,
+    name: String,
+    compartments: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Association {
+    start: usize,
+    end: usize,
+    assoc: String,
+    start_label: String,
+    end_label: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Graph {
+    classifiers: Vec<Classifier>,
+    associations: Vec<Association>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+
+impl Classifier {
+    fn width(&self) -> f64 {
+        let longest = self.compartments
+            .iter()
+            .flat_map(|c| c.iter())
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        (longest as f64 * CHAR_WIDTH + 2.0 * PADDING).max(3.0 * PADDING)
+    }
+
+    fn height(&self) -> f64 {
+        self.compartments
+            .iter()
+            .map(|c| c.len().max(1) as f64 * LINE_HEIGHT + PADDING)
+            .fold(0.0, |a, b| a + b)
+    }
+}
+
+
+impl Graph {
+    /// Ranks the classifiers following the direction of the associations, every classifier
+    /// is placed at least one rank below the classifiers pointing to it
+    fn ranks(&self) -> Vec<usize> {
+        let mut ranks = vec![0; self.classifiers.len()];
+
+        // Bounded, so that cycles can not make this loop forever
+        for _ in 0..self.classifiers.len() {
+            let mut changed = false;
+            for a in &self.associations {
+                if a.start != a.end && ranks[a.end] < ranks[a.start] + 1 {
+                    ranks[a.end] = ranks[a.start] + 1;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        ranks
+    }
+
+    fn layout(&self) -> (Vec<Rect>, f64, f64) {
+        let ranks = self.ranks();
+        let rank_count = ranks.iter().cloned().max().unwrap_or(0) + 1;
+
+        let mut rows: Vec<Vec<usize>> = vec![vec![]; rank_count];
+        for (i, &rank) in ranks.iter().enumerate() {
+            rows[rank].push(i);
+        }
+
+        let row_widths: Vec<f64> = rows.iter()
+            .map(|row| {
+                row.iter().map(|&i| self.classifiers[i].width()).fold(0.0, |a, b| a + b) +
+                SPACING * (row.len().max(1) - 1) as f64
+            })
+            .collect();
+        let total_width = row_widths.iter().fold(0.0, |a: f64, &b| a.max(b));
+
+        let mut rects = vec![Rect { x: 0.0, y: 0.0, width: 0.0, height: 0.0 }; self.classifiers.len()];
+        let mut y = GUTTER;
+
+        for (row, row_width) in rows.iter().zip(row_widths.iter()) {
+            let mut x = GUTTER + (total_width - row_width) / 2.0;
+            let row_height = row.iter().map(|&i| self.classifiers[i].height()).fold(0.0, |a: f64, b| a.max(b));
+
+            for &i in row {
+                let classifier = &self.classifiers[i];
+                rects[i] = Rect {
+                    x: x,
+                    y: y,
+                    width: classifier.width(),
+                    height: classifier.height(),
+                };
+                x += classifier.width() + SPACING;
+            }
+
+            y += row_height + SPACING;
+        }
+
+        (rects, total_width + 2.0 * GUTTER, y - SPACING + GUTTER)
+    }
+
+    fn to_svg(&self) -> String {
+        let (rects, width, height) = self.layout();
+        let mut out = format!("<svg class=\"nomnoml-svg\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+                               height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+                              w = num(width),
+                              h = num(height));
+
+        for a in &self.associations {
+            out.push_str(&association_svg(a, &rects[a.start], &rects[a.end]));
+        }
+
+        for (classifier, rect) in self.classifiers.iter().zip(rects.iter()) {
+            out.push_str(&classifier_svg(classifier, rect));
+        }
+
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+
+fn classifier_svg(classifier: &Classifier, rect: &Rect) -> String {
+    let dashed = if classifier.kind == "abstract" { " stroke-dasharray=\"4,4\"" } else { "" };
+    let mut out = format!("<g class=\"classifier {}\">\n", escape(&classifier.kind));
+
+    out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\"{}></rect>\n",
+                          num(rect.x),
+                          num(rect.y),
+                          num(rect.width),
+                          num(rect.height),
+                          FILL,
+                          STROKE,
+                          dashed));
+
+    let mut y = rect.y;
+    for (i, compartment) in classifier.compartments.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&format!("<path d=\"M{} {}h{}\" stroke=\"{}\"></path>\n",
+                                  num(rect.x),
+                                  num(y),
+                                  num(rect.width),
+                                  STROKE));
+        }
+
+        let mut line_y = y + PADDING / 2.0;
+        for line in compartment {
+            line_y += LINE_HEIGHT;
+            // The first compartment holds the centered name of the classifier
+            let (x, anchor, weight) = if i == 0 {
+                (rect.x + rect.width / 2.0, "middle", " font-weight=\"bold\"")
+            } else {
+                (rect.x + PADDING, "start", "")
+            };
+            out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"{}\" {}{} fill=\"{}\">{}</text>\n",
+                                  num(x),
+                                  num(line_y - 5.0),
+                                  anchor,
+                                  FONT,
+                                  weight,
+                                  STROKE,
+                                  escape(line)));
+        }
+
+        y += compartment.len().max(1) as f64 * LINE_HEIGHT + PADDING;
+    }
+
+    out.push_str("</g>\n");
+    out
+}
+
+fn association_svg(a: &Association, from: &Rect, to: &Rect) -> String {
+    let mut out = String::from("<g class=\"association\">\n");
+
+    if a.start == a.end {
+        // Self association: a loop on the right side of the classifier
+        let x = from.x + from.width;
+        let y = from.y + from.height / 2.0;
+        out.push_str(&format!("<path d=\"M{} {}h{}v{}h{}\" fill=\"none\" stroke=\"{}\"{}></path>\n",
+                              num(x),
+                              num(y - 8.0),
+                              num(SPACING / 2.0),
+                              num(16.0),
+                              num(-SPACING / 2.0),
+                              STROKE,
+                              dash(&a.assoc)));
+        out.push_str("</g>\n");
+        return out;
+    }
+
+    let start = boundary_point(from, center(to));
+    let end = boundary_point(to, center(from));
+
+    out.push_str(&format!("<path d=\"M{} {}L{} {}\" fill=\"none\" stroke=\"{}\"{}></path>\n",
+                          num(start.0),
+                          num(start.1),
+                          num(end.0),
+                          num(end.1),
+                          STROKE,
+                          dash(&a.assoc)));
+
+    let (start_marker, end_marker) = markers(&a.assoc);
+    out.push_str(&marker_svg(start_marker, start, end));
+    out.push_str(&marker_svg(end_marker, end, start));
+    out.push_str(&label_svg(&a.start_label, start, end));
+    out.push_str(&label_svg(&a.end_label, end, start));
+
+    out.push_str("</g>\n");
+    out
+}
+
+fn dash(assoc: &str) -> &'static str {
+    if assoc.contains("--") {
+        " stroke-dasharray=\"6,4\""
+    } else {
+        ""
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Marker {
+    None,
+    Arrow,
+    Triangle,
+    Diamond,
+    FilledDiamond,
+}
+
+/// Reads the decorations on both ends of an association like `<:-`, `o->` or `-->`
+fn markers(assoc: &str) -> (Marker, Marker) {
+    let head = &assoc[..assoc.len() - assoc.trim_left_matches(|c| c != '-').len()];
+    let tail = assoc.trim_left_matches(|c| c != '-').trim_left_matches(|c| c == '-' || c == '/');
+
+    let start = match head {
+        "<" => Marker::Arrow,
+        "<:" => Marker::Triangle,
+        "o" => Marker::Diamond,
+        "+" => Marker::FilledDiamond,
+        _ => Marker::None,
+    };
+    let end = match tail {
+        ">" => Marker::Arrow,
+        ":>" => Marker::Triangle,
+        "o" => Marker::Diamond,
+        "+" => Marker::FilledDiamond,
+        _ => Marker::None,
+    };
+
+    (start, end)
+}
+
+fn marker_svg(marker: Marker, tip: (f64, f64), from: (f64, f64)) -> String {
+    let (dx, dy) = (tip.0 - from.0, tip.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return String::new();
+    }
+
+    // Unit vector along the line and its normal
+    let (ux, uy) = (dx / length, dy / length);
+    let (nx, ny) = (-uy, ux);
+    let s = ARROW_SIZE;
+
+    let point = |along: f64, across: f64| -> String {
+        format!("{} {}", num(tip.0 - ux * along + nx * across), num(tip.1 - uy * along + ny * across))
+    };
+
+    match marker {
+        Marker::None => String::new(),
+        Marker::Arrow => {
+            format!("<path d=\"M{}L{} {}L{}\" fill=\"none\" stroke=\"{}\"></path>\n",
+                    point(s, s / 2.0),
+                    num(tip.0),
+                    num(tip.1),
+                    point(s, -s / 2.0),
+                    STROKE)
+        },
+        Marker::Triangle => {
+            format!("<path d=\"M{} {}L{}L{}Z\" fill=\"#fff\" stroke=\"{}\"></path>\n",
+                    num(tip.0),
+                    num(tip.1),
+                    point(s, s / 2.0),
+                    point(s, -s / 2.0),
+                    STROKE)
+        },
+        Marker::Diamond | Marker::FilledDiamond => {
+            let fill = if marker == Marker::Diamond { "#fff" } else { STROKE };
+            format!("<path d=\"M{} {}L{}L{}L{}Z\" fill=\"{}\" stroke=\"{}\"></path>\n",
+                    num(tip.0),
+                    num(tip.1),
+                    point(s, s / 2.0),
+                    point(2.0 * s, 0.0),
+                    point(s, -s / 2.0),
+                    fill,
+                    STROKE)
+        },
+    }
+}
+
+fn label_svg(label: &str, at: (f64, f64), towards: (f64, f64)) -> String {
+    if label.is_empty() {
+        return String::new();
+    }
+
+    let (dx, dy) = (towards.0 - at.0, towards.1 - at.1);
+    let length = (dx * dx + dy * dy).sqrt().max(1.0);
+
+    format!("<text x=\"{}\" y=\"{}\" {} fill=\"{}\">{}</text>\n",
+            num(at.0 + dx / length * 2.0 * ARROW_SIZE + 4.0),
+            num(at.1 + dy / length * 2.0 * ARROW_SIZE),
+            FONT,
+            STROKE,
+            escape(label))
+}
+
+fn center(r: &Rect) -> (f64, f64) {
+    (r.x + r.width / 2.0, r.y + r.height / 2.0)
+}
+
+/// The point where the line from the center of `r` to `towards` leaves the rectangle
+fn boundary_point(r: &Rect, towards: (f64, f64)) -> (f64, f64) {
+    let (cx, cy) = center(r);
+    let (dx, dy) = (towards.0 - cx, towards.1 - cy);
+
+    if dx == 0.0 && dy == 0.0 {
+        return (cx, cy);
+    }
+
+    let scale_x = if dx != 0.0 { (r.width / 2.0) / dx.abs() } else { ::std::f64::INFINITY };
+    let scale_y = if dy != 0.0 { (r.height / 2.0) / dy.abs() } else { ::std::f64::INFINITY };
+    let scale = scale_x.min(scale_y);
+
+    (cx + dx * scale, cy + dy * scale)
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Parser
+//
+
+fn parse(spec: &str) -> Result<Graph, String> {
+    let mut graph = Graph {
+        classifiers: vec![],
+        associations: vec![],
+    };
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for line in spec.lines() {
+        let line = line.trim();
+
+        // Skip empty lines, comments and directives
+        if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+
+        let mut rest = line;
+        let mut previous: Option<usize> = None;
+        let mut pending_assoc: Option<&str> = None;
+
+        while !rest.is_empty() {
+            if !rest.starts_with('[') {
+                return Err(format!("Expected a classifier in line: {}", line));
+            }
+
+            let close = try!(closing_bracket(rest).ok_or(format!("Unclosed classifier in line: {}", line)));
+            let classifier = try!(parse_classifier(&rest[1..close]));
+            let id = add_classifier(&mut graph, &mut index, classifier);
+
+            if let (Some(start), Some(assoc)) = (previous, pending_assoc) {
+                graph.associations.push(try!(parse_association(start, id, assoc)));
+            }
+
+            rest = &rest[close + 1..];
+            match rest.find('[') {
+                Some(next) => {
+                    pending_assoc = Some(&rest[..next]);
+                    rest = &rest[next..];
+                },
+                None => {
+                    if !rest.trim().is_empty() {
+                        return Err(format!("Unexpected text after classifier: {}", rest));
+                    }
+                    rest = "";
+                },
+            }
+            previous = Some(id);
+        }
+    }
+
+    Ok(graph)
+}
+
+fn closing_bracket(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ']' => return Some(i),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn parse_classifier(s: &str) -> Result<Classifier, String> {
+    if s.contains('[') {
+        return Err("Nested classifiers are not supported".to_owned());
+    }
+
+    let mut compartments: Vec<Vec<String>> = s.split('|')
+        .map(|c| {
+            c.split(';')
+                .map(|l| l.trim().to_owned())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .collect();
+
+    let mut kind = "class".to_owned();
+    let mut name = compartments[0].get(0).cloned().unwrap_or(String::new());
+
+    if name.starts_with('<') {
+        if let Some(end) = name.find('>') {
+            kind = name[1..end].to_owned();
+            name = name[end + 1..].trim().to_owned();
+        }
+    }
+
+    if name.is_empty() {
+        return Err("Classifier without a name".to_owned());
+    }
+
+    if compartments[0].is_empty() {
+        compartments[0].push(name.clone());
+    } else {
+        compartments[0][0] = name.clone();
+    }
+
+    Ok(Classifier {
+        kind: kind,
+        name: name,
+        compartments: compartments,
+    })
+}
+
+/// Adds the classifier to the graph, unless a classifier with the same name exists.
+/// Like nomnoml, the definition with the most compartments wins.
+fn add_classifier(graph: &mut Graph, index: &mut HashMap<String, usize>, classifier: Classifier) -> usize {
+    if let Some(&id) = index.get(&classifier.name) {
+        if classifier.compartments.len() > graph.classifiers[id].compartments.len() {
+            graph.classifiers[id] = classifier;
+        }
+        return id;
+    }
+
+    let id = graph.classifiers.len();
+    index.insert(classifier.name.clone(), id);
+    graph.classifiers.push(classifier);
+    id
+}
+
+fn parse_association(start: usize, end: usize, s: &str) -> Result<Association, String> {
+    let s = s.trim();
+    let first = try!(s.find('-').ok_or(format!("Invalid association: {}", s)));
+    let last = s.rfind('-').unwrap();
+
+    // Extend the association over the decorations on both ends
+    let assoc_start = s[..first]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !"<:o+".contains(c))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    let assoc_end = s[last + 1..].find(|c| !":o+>".contains(c)).map(|i| last + 1 + i).unwrap_or(s.len());
+
+    Ok(Association {
+        start: start,
+        end: end,
+        assoc: s[assoc_start..assoc_end].to_owned(),
+        start_label: s[..assoc_start].trim().to_owned(),
+        end_label: s[assoc_end..].trim().to_owned(),
+    })
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_parse_classifier() {
+    let classifier = parse_classifier("<abstract> Shape|area: f64|draw()").unwrap();
+    assert_eq!(classifier,
+               Classifier {
+                   kind: "abstract".to_owned(),
+                   name: "Shape".to_owned(),
+                   compartments: vec![vec!["Shape".to_owned()], vec!["area: f64".to_owned()], vec!["draw()".to_owned()]],
+               });
+}
+
+#[test]
+fn test_parse_associations_with_non_ascii_labels() {
+    let graph = parse("[A] é-> [B]\n[A] 使用-> ü [B]").unwrap();
+    assert_eq!(graph.associations,
+               vec![Association {
+                        start: 0,
+                        end: 1,
+                        assoc: "->".to_owned(),
+                        start_label: "é".to_owned(),
+                        end_label: String::new(),
+                    },
+                    Association {
+                        start: 0,
+                        end: 1,
+                        assoc: "->".to_owned(),
+                        start_label: "使用".to_owned(),
+                        end_label: "ü".to_owned(),
+                    }]);
+}
+
+#[test]
+fn test_parse_associations() {
+    let graph = parse("#direction: down\n[A] uses -> 1..* [B]\n[B]<:-[C]-->[A]\n").unwrap();
+    assert_eq!(graph.classifiers.len(), 3);
+    assert_eq!(graph.associations,
+               vec![Association {
+                        start: 0,
+                        end: 1,
+                        assoc: "->".to_owned(),
+                        start_label: "uses".to_owned(),
+                        end_label: "1..*".to_owned(),
+                    },
+                    Association {
+                        start: 1,
+                        end: 2,
+                        assoc: "<:-".to_owned(),
+                        start_label: String::new(),
+                        end_label: String::new(),
+                    },
+                    Association {
+                        start: 2,
+                        end: 0,
+                        assoc: "-->".to_owned(),
+                        start_label: String::new(),
+                        end_label: String::new(),
+                    }]);
+}
+
+#[test]
+fn test_markers() {
+    assert_eq!(markers("->"), (Marker::None, Marker::Arrow));
+    assert_eq!(markers("<:-"), (Marker::Triangle, Marker::None));
+    assert_eq!(markers("o-->"), (Marker::Diamond, Marker::Arrow));
+    assert_eq!(markers("+-"), (Marker::FilledDiamond, Marker::None));
+}
+
+#[test]
+fn test_ranks() {
+    let graph = parse("[A]->[B]\n[B]->[C]\n[A]->[C]\n[C]->[A]").unwrap();
+    // Cycles must terminate
+    assert_eq!(graph.ranks().len(), 3);
+
+    let graph = parse("[A]->[B]\n[B]->[C]\n[A]->[C]").unwrap();
+    assert_eq!(graph.ranks(), vec![0, 1, 2]);
+}
+
+#[test]
+fn test_unsupported() {
+    assert!(render("[A|[B]]").is_none());
+    assert!(render("not a diagram").is_none());
+}
+
+#[test]
+fn test_render_snapshot() {
+    let svg = render("[A]").unwrap();
+    assert_eq!(svg,
+               "<svg class=\"nomnoml-svg\" xmlns=\"http://www.w3.org/2000/svg\" width=\"44\" height=\"46\" \
+                viewBox=\"0 0 44 46\">\n\
+                <g class=\"classifier class\">\n\
+                <rect x=\"10\" y=\"10\" width=\"24\" height=\"26\" fill=\"#eee8d5\" stroke=\"#33322E\"></rect>\n\
+                <text x=\"22\" y=\"27\" text-anchor=\"middle\" font-family=\"Calibri, Helvetica, sans-serif\" \
+                font-size=\"12\" font-weight=\"bold\" fill=\"#33322E\">A</text>\n\
+                </g>\n\
+                </svg>\n");
+}
@@ -0,0 +1,781 @@
+//! A port of the layout algorithm of [railroad-diagrams](https://github.com/tabatkins/railroad-diagrams),
+//! the library that is bundled in `railroad.zip`.
+//!
+//! The diagram is described with the same JavaScript-like syntax that the library uses,
+//! for example `Diagram(Terminal('if'), NonTerminal('expr'), Optional('else'))`.
+
+use std::str::Chars;
+use std::iter::Peekable;
+
+use super::{num, escape};
+
+const VERTICAL_SEPARATION: f64 = 8.0;
+const ARC_RADIUS: f64 = 10.0;
+
+
+/// Renders every diagram found in `spec` to an inline `<svg>` element.
+/// Returns `None` if the spec uses something that is not supported at build time.
+
+pub fn render(spec: &str) -> Option<String> {
+    let diagrams = match parse(spec) {
+        Ok(d) => d,
+        Err(e) => {
+            debug!("[*]: Could not render railroad diagram at build time: {}", e);
+            return None;
+        },
+    };
+
+    if diagrams.is_empty() {
+        return None;
+    }
+
+    Some(diagrams.iter().map(|d| d.to_svg()).collect::<Vec<String>>().join("\n"))
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Start(bool),
+    End(bool),
+    Terminal(String),
+    NonTerminal(String),
+    Comment(String),
+    Skip,
+    Sequence(Vec<Node>),
+    Choice(usize, Vec<Node>),
+    OneOrMore(Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Diagram {
+    items: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Dims {
+    width: f64,
+    height: f64,
+    up: f64,
+    down: f64,
+    needs_space: bool,
+}
+
+
+impl Diagram {
+    fn to_svg(&self) -> String {
+        let (padding_top, padding_right, padding_bottom, padding_left) = (20.0, 20.0, 20.0, 20.0);
+        let dims = sequence_dims(&self.items, false);
+
+        let mut x = padding_left;
+        let mut y = padding_top + dims.up;
+        let mut g = String::new();
+
+        for item in &self.items {
+            let d = item.dims();
+            if d.needs_space {
+                g.push_str(&Path::new(x, y).h(10.0).end());
+                x += 10.0;
+            }
+            g.push_str(&item.format(x, y, d.width));
+            x += d.width;
+            y += d.height;
+            if d.needs_space {
+                g.push_str(&Path::new(x, y).h(10.0).end());
+                x += 10.0;
+            }
+        }
+
+        let width = dims.width + padding_left + padding_right;
+        let height = dims.up + dims.height + dims.down + padding_top + padding_bottom;
+
+        format!("<svg class=\"railroad-diagram\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+                 height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n<g transform=\"translate(.5 .5)\">\n{g}</g>\n</svg>\n",
+                w = num(width),
+                h = num(height),
+                g = g)
+    }
+}
+
+
+impl Node {
+    fn dims(&self) -> Dims {
+        match *self {
+            Node::Start(_) | Node::End(_) => Dims { width: 20.0, height: 0.0, up: 10.0, down: 10.0, needs_space: false },
+            Node::Terminal(ref text) | Node::NonTerminal(ref text) => {
+                Dims {
+                    // Assume that each char is .5em, and that the em is 16px
+                    width: text.chars().count() as f64 * 8.0 + 20.0,
+                    height: 0.0,
+                    up: 11.0,
+                    down: 11.0,
+                    needs_space: true,
+                }
+            },
+            Node::Comment(ref text) => {
+                Dims {
+                    width: text.chars().count() as f64 * 7.0 + 10.0,
+                    height: 0.0,
+                    up: 11.0,
+                    down: 11.0,
+                    needs_space: true,
+                }
+            },
+            Node::Skip => Dims { width: 0.0, height: 0.0, up: 0.0, down: 0.0, needs_space: false },
+            Node::Sequence(ref items) => sequence_dims(items, true),
+            Node::Choice(normal, ref items) => {
+                let dims: Vec<Dims> = items.iter().map(|i| i.dims()).collect();
+                let last = dims.len() - 1;
+
+                let mut up = dims[0].up;
+                for i in 0..normal {
+                    let arcs = if i == normal - 1 { ARC_RADIUS * 2.0 } else { ARC_RADIUS };
+                    up += arcs.max(dims[i].height + dims[i].down + VERTICAL_SEPARATION + dims[i + 1].up);
+                }
+
+                let mut down = dims[last].down;
+                for i in normal + 1..last + 1 {
+                    let arcs = if i == normal + 1 { ARC_RADIUS * 2.0 } else { ARC_RADIUS };
+                    down += arcs.max(dims[i - 1].height + dims[i - 1].down + VERTICAL_SEPARATION + dims[i].up);
+                }
+                // Already counted in height
+                down -= dims[normal].height;
+
+                Dims {
+                    width: dims.iter().fold(0.0, |m: f64, d| m.max(d.width)) + ARC_RADIUS * 4.0,
+                    height: dims[normal].height,
+                    up: up,
+                    down: down,
+                    needs_space: false,
+                }
+            },
+            Node::OneOrMore(ref item, ref rep) => {
+                let item = item.dims();
+                let rep = rep.dims();
+                Dims {
+                    width: item.width.max(rep.width) + ARC_RADIUS * 2.0,
+                    height: item.height,
+                    up: item.up,
+                    down: (ARC_RADIUS * 2.0).max(item.down + VERTICAL_SEPARATION + rep.up + rep.height + rep.down),
+                    needs_space: true,
+                }
+            },
+        }
+    }
+
+    fn format(&self, x: f64, y: f64, width: f64) -> String {
+        let dims = self.dims();
+        let mut out = String::new();
+
+        match *self {
+            Node::Start(complex) => {
+                if complex {
+                    out.push_str(&format!("<path d=\"M {} {} v 20 m 0 -10 h 20.5\"></path>\n", num(x), num(y - 10.0)));
+                } else {
+                    out.push_str(&format!("<path d=\"M {} {} v 20 m 10 -20 v 20 m -10 -10 h 20.5\"></path>\n",
+                                          num(x),
+                                          num(y - 10.0)));
+                }
+            },
+            Node::End(complex) => {
+                if complex {
+                    out.push_str(&format!("<path d=\"M {} {} h 20 m 0 -10 v 20\"></path>\n", num(x), num(y)));
+                } else {
+                    out.push_str(&format!("<path d=\"M {} {} h 20 m -10 -10 v 20 m 10 -20 v 20\"></path>\n",
+                                          num(x),
+                                          num(y)));
+                }
+            },
+            Node::Terminal(ref text) | Node::NonTerminal(ref text) => {
+                let (class, rounded) = match *self {
+                    Node::Terminal(_) => ("terminal", " rx=\"10\" ry=\"10\""),
+                    _ => ("non-terminal", ""),
+                };
+                let (left, right) = gaps(width, dims.width);
+
+                out.push_str(&format!("<g class=\"{}\">\n", class));
+                out.push_str(&Path::new(x, y).h(left).end());
+                out.push_str(&Path::new(x + left + dims.width, y).h(right).end());
+                let x = x + left;
+                out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}></rect>\n",
+                                      num(x),
+                                      num(y - 11.0),
+                                      num(dims.width),
+                                      num(dims.up + dims.down),
+                                      rounded));
+                out.push_str(&format!("<text x=\"{}\" y=\"{}\">{}</text>\n",
+                                      num(x + dims.width / 2.0),
+                                      num(y + 4.0),
+                                      escape(text)));
+                out.push_str("</g>\n");
+            },
+            Node::Comment(ref text) => {
+                let (left, right) = gaps(width, dims.width);
+
+                out.push_str("<g>\n");
+                out.push_str(&Path::new(x, y).h(left).end());
+                out.push_str(&Path::new(x + left + dims.width, y + dims.height).h(right).end());
+                let x = x + left;
+                out.push_str(&format!("<text x=\"{}\" y=\"{}\" class=\"comment\">{}</text>\n",
+                                      num(x + dims.width / 2.0),
+                                      num(y + 5.0),
+                                      escape(text)));
+                out.push_str("</g>\n");
+            },
+            Node::Skip => {
+                out.push_str("<g>\n");
+                out.push_str(&Path::new(x, y).right(width).end());
+                out.push_str("</g>\n");
+            },
+            Node::Sequence(ref items) => {
+                let (left, right) = gaps(width, dims.width);
+
+                out.push_str("<g>\n");
+                out.push_str(&Path::new(x, y).h(left).end());
+                out.push_str(&Path::new(x + left + dims.width, y + dims.height).h(right).end());
+
+                let mut x = x + left;
+                let mut y = y;
+                let last = items.len() - 1;
+                for (i, item) in items.iter().enumerate() {
+                    let d = item.dims();
+                    if d.needs_space && i > 0 {
+                        out.push_str(&Path::new(x, y).h(10.0).end());
+                        x += 10.0;
+                    }
+                    out.push_str(&item.format(x, y, d.width));
+                    x += d.width;
+                    y += d.height;
+                    if d.needs_space && i < last {
+                        out.push_str(&Path::new(x, y).h(10.0).end());
+                        x += 10.0;
+                    }
+                }
+                out.push_str("</g>\n");
+            },
+            Node::Choice(normal, ref items) => {
+                let (left, right) = gaps(width, dims.width);
+                let item_dims: Vec<Dims> = items.iter().map(|i| i.dims()).collect();
+                let last = items.len() - 1;
+                let inner_width = dims.width - ARC_RADIUS * 4.0;
+
+                out.push_str("<g>\n");
+                out.push_str(&Path::new(x, y).h(left).end());
+                out.push_str(&Path::new(x + left + dims.width, y + dims.height).h(right).end());
+                let x = x + left;
+
+                // Do the elements that curve above
+                let mut distance_from_y = 0.0;
+                for i in (0..normal).rev() {
+                    let item = &item_dims[i];
+                    if i == normal - 1 {
+                        distance_from_y = (ARC_RADIUS * 2.0)
+                            .max(item_dims[normal].up + VERTICAL_SEPARATION + item.down + item.height);
+                    }
+                    out.push_str(&Path::new(x, y)
+                        .arc("se")
+                        .up(distance_from_y - ARC_RADIUS * 2.0)
+                        .arc("wn")
+                        .end());
+                    out.push_str(&items[i].format(x + ARC_RADIUS * 2.0, y - distance_from_y, inner_width));
+                    out.push_str(&Path::new(x + ARC_RADIUS * 2.0 + inner_width, y - distance_from_y + item.height)
+                        .arc("ne")
+                        .down(distance_from_y - item.height + dims.height - ARC_RADIUS * 2.0)
+                        .arc("ws")
+                        .end());
+                    let previous = if i == 0 {
+                        0.0
+                    } else {
+                        item_dims[i - 1].down + item_dims[i - 1].height
+                    };
+                    distance_from_y += ARC_RADIUS.max(item.up + VERTICAL_SEPARATION + previous);
+                }
+
+                // Do the straight-line path
+                out.push_str(&Path::new(x, y).right(ARC_RADIUS * 2.0).end());
+                out.push_str(&items[normal].format(x + ARC_RADIUS * 2.0, y, inner_width));
+                out.push_str(&Path::new(x + ARC_RADIUS * 2.0 + inner_width, y + dims.height)
+                    .right(ARC_RADIUS * 2.0)
+                    .end());
+
+                // Do the elements that curve below
+                for i in normal + 1..last + 1 {
+                    let item = &item_dims[i];
+                    if i == normal + 1 {
+                        distance_from_y = (ARC_RADIUS * 2.0)
+                            .max(dims.height + item_dims[normal].down + VERTICAL_SEPARATION + item.up);
+                    }
+                    out.push_str(&Path::new(x, y)
+                        .arc("ne")
+                        .down(distance_from_y - ARC_RADIUS * 2.0)
+                        .arc("ws")
+                        .end());
+                    out.push_str(&items[i].format(x + ARC_RADIUS * 2.0, y + distance_from_y, inner_width));
+                    out.push_str(&Path::new(x + ARC_RADIUS * 2.0 + inner_width, y + distance_from_y + item.height)
+                        .arc("se")
+                        .up(distance_from_y - ARC_RADIUS * 2.0 + item.height - dims.height)
+                        .arc("wn")
+                        .end());
+                    let next = if i == last { 0.0 } else { item_dims[i + 1].up };
+                    distance_from_y += ARC_RADIUS.max(item.height + item.down + VERTICAL_SEPARATION + next);
+                }
+                out.push_str("</g>\n");
+            },
+            Node::OneOrMore(ref item, ref rep) => {
+                let (left, right) = gaps(width, dims.width);
+                let item_dims = item.dims();
+                let rep_dims = rep.dims();
+
+                out.push_str("<g>\n");
+                out.push_str(&Path::new(x, y).h(left).end());
+                out.push_str(&Path::new(x + left + dims.width, y + dims.height).h(right).end());
+                let x = x + left;
+
+                // Draw item
+                out.push_str(&Path::new(x, y).right(ARC_RADIUS).end());
+                out.push_str(&item.format(x + ARC_RADIUS, y, dims.width - ARC_RADIUS * 2.0));
+                out.push_str(&Path::new(x + dims.width - ARC_RADIUS, y + dims.height).right(ARC_RADIUS).end());
+
+                // Draw repeat arc
+                let distance_from_y = (ARC_RADIUS * 2.0)
+                    .max(item_dims.height + item_dims.down + VERTICAL_SEPARATION + rep_dims.up);
+                out.push_str(&Path::new(x + ARC_RADIUS, y)
+                    .arc("nw")
+                    .down(distance_from_y - ARC_RADIUS * 2.0)
+                    .arc("ws")
+                    .end());
+                out.push_str(&rep.format(x + ARC_RADIUS, y + distance_from_y, dims.width - ARC_RADIUS * 2.0));
+                out.push_str(&Path::new(x + dims.width - ARC_RADIUS, y + distance_from_y + rep_dims.height)
+                    .arc("se")
+                    .up(distance_from_y - ARC_RADIUS * 2.0 + rep_dims.height - item_dims.height)
+                    .arc("en")
+                    .end());
+                out.push_str("</g>\n");
+            },
+        }
+
+        out
+    }
+}
+
+
+fn sequence_dims(items: &[Node], trim: bool) -> Dims {
+    let mut dims = Dims { width: 0.0, height: 0.0, up: 0.0, down: 0.0, needs_space: true };
+
+    for item in items {
+        let d = item.dims();
+        dims.width += d.width + if d.needs_space { 20.0 } else { 0.0 };
+        dims.up = dims.up.max(d.up - dims.height);
+        dims.height += d.height;
+        dims.down = (dims.down - d.height).max(d.down);
+    }
+
+    if trim {
+        if let Some(first) = items.first() {
+            if first.dims().needs_space {
+                dims.width -= 10.0;
+            }
+        }
+        if let Some(last) = items.last() {
+            if last.dims().needs_space {
+                dims.width -= 10.0;
+            }
+        }
+    }
+
+    dims
+}
+
+/// Splits the space that is left around an item in two, the item is centered
+fn gaps(outer: f64, inner: f64) -> (f64, f64) {
+    let diff = outer - inner;
+    (diff / 2.0, diff / 2.0)
+}
+
+
+struct Path {
+    d: String,
+}
+
+impl Path {
+    fn new(x: f64, y: f64) -> Self {
+        Path { d: format!("M{} {}", num(x), num(y)) }
+    }
+
+    fn h(mut self, val: f64) -> Self {
+        self.d.push_str(&format!("h{}", num(val)));
+        self
+    }
+
+    fn right(self, val: f64) -> Self {
+        self.h(val.max(0.0))
+    }
+
+    fn v(mut self, val: f64) -> Self {
+        self.d.push_str(&format!("v{}", num(val)));
+        self
+    }
+
+    fn down(self, val: f64) -> Self {
+        self.v(val.max(0.0))
+    }
+
+    fn up(self, val: f64) -> Self {
+        self.v(-val.max(0.0))
+    }
+
+    fn arc(mut self, sweep: &str) -> Self {
+        let mut x = ARC_RADIUS;
+        let mut y = ARC_RADIUS;
+        let sweep = sweep.as_bytes();
+
+        if sweep[0] == b'e' || sweep[1] == b'w' {
+            x *= -1.0;
+        }
+        if sweep[0] == b's' || sweep[1] == b'n' {
+            y *= -1.0;
+        }
+        let cw = match sweep {
+            b"ne" | b"es" | b"sw" | b"wn" => 1,
+            _ => 0,
+        };
+
+        self.d.push_str(&format!("a{r} {r} 0 0 {} {} {}", cw, num(x), num(y), r = num(ARC_RADIUS)));
+        self
+    }
+
+    fn end(self) -> String {
+        format!("<path d=\"{}\"></path>\n", self.d)
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Parser
+//
+// Understands a sequence of calls like `Diagram(Terminal('a'), 'b').addTo();` where
+// string literals are turned into terminals, like the JavaScript library does.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Semicolon,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Call(String, Vec<Value>),
+    Str(String),
+    Num(f64),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<Chars> = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            ',' => tokens.push(Token::Comma),
+            '.' => tokens.push(Token::Dot),
+            ';' => tokens.push(Token::Semicolon),
+            '\'' | '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            if let Some(escaped) = chars.next() {
+                                string.push(escaped);
+                            }
+                        },
+                        Some(ch) if ch == c => break,
+                        Some(ch) => string.push(ch),
+                        None => return Err("Unterminated string literal".to_owned()),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            },
+            c if c.is_whitespace() => {},
+            c if c.is_digit(10) || c == '-' => {
+                let mut number = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_digit(10) || next == '.' {
+                        number.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                match number.parse() {
+                    Ok(n) => tokens.push(Token::Num(n)),
+                    Err(_) => return Err(format!("Invalid number: {}", number)),
+                }
+            },
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let mut ident = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == '$' {
+                        ident.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            },
+            c => return Err(format!("Unexpected character '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref t) if *t == expected => Ok(()),
+            t => Err(format!("Expected {:?}, found {:?}", expected, t)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::Num(n)) => Ok(Value::Num(n)),
+            Some(Token::Ident(ref new)) if new == "new" => self.value(),
+            Some(Token::Ident(name)) => {
+                let args = try!(self.arguments());
+
+                // Skip method calls like `.addTo()` or `.format()`
+                while let Some(&Token::Dot) = self.peek() {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Ident(_)) => {},
+                        t => return Err(format!("Expected method name, found {:?}", t)),
+                    }
+                    try!(self.arguments());
+                }
+
+                Ok(Value::Call(name, args))
+            },
+            t => Err(format!("Unexpected token {:?}", t)),
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Value>, String> {
+        try!(self.expect(Token::LParen));
+        let mut args = vec![];
+
+        if let Some(&Token::RParen) = self.peek() {
+            self.next();
+            return Ok(args);
+        }
+
+        loop {
+            args.push(try!(self.value()));
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => break,
+                t => return Err(format!("Expected ',' or ')', found {:?}", t)),
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+fn parse(spec: &str) -> Result<Vec<Diagram>, String> {
+    let mut parser = Parser {
+        tokens: try!(tokenize(spec)),
+        pos: 0,
+    };
+    let mut diagrams = vec![];
+
+    while parser.peek().is_some() {
+        if let Some(&Token::Semicolon) = parser.peek() {
+            parser.next();
+            continue;
+        }
+
+        match try!(parser.value()) {
+            Value::Call(ref name, ref args) if name == "Diagram" || name == "ComplexDiagram" => {
+                let complex = name == "ComplexDiagram";
+                let mut items = vec![Node::Start(complex)];
+                for arg in args {
+                    items.push(try!(to_node(arg)));
+                }
+                items.push(Node::End(complex));
+                diagrams.push(Diagram { items: items });
+            },
+            v => return Err(format!("Expected a Diagram, found {:?}", v)),
+        }
+    }
+
+    Ok(diagrams)
+}
+
+fn to_node(value: &Value) -> Result<Node, String> {
+    let (name, args) = match *value {
+        Value::Str(ref s) => return Ok(Node::Terminal(s.clone())),
+        Value::Num(n) => return Err(format!("Unexpected number {}", n)),
+        Value::Call(ref name, ref args) => (name.as_str(), args),
+    };
+
+    let text = |args: &[Value]| -> Result<String, String> {
+        match args.first() {
+            Some(&Value::Str(ref s)) => Ok(s.clone()),
+            _ => Err(format!("{}() expects a string as first argument", name)),
+        }
+    };
+    let nodes = |args: &[Value]| -> Result<Vec<Node>, String> {
+        let mut nodes = vec![];
+        for arg in args {
+            nodes.push(try!(to_node(arg)));
+        }
+        Ok(nodes)
+    };
+
+    match name {
+        "Terminal" => Ok(Node::Terminal(try!(text(args)))),
+        "NonTerminal" => Ok(Node::NonTerminal(try!(text(args)))),
+        "Comment" => Ok(Node::Comment(try!(text(args)))),
+        "Skip" => Ok(Node::Skip),
+        "Sequence" => {
+            if args.is_empty() {
+                return Err("Sequence() must have at least one child".to_owned());
+            }
+            Ok(Node::Sequence(try!(nodes(args))))
+        },
+        "Choice" => {
+            let normal = match args.first() {
+                Some(&Value::Num(n)) if n >= 0.0 && n == n.floor() => n as usize,
+                _ => return Err("The first argument of Choice() must be an integer".to_owned()),
+            };
+            let items = try!(nodes(&args[1..]));
+            if normal >= items.len() {
+                return Err("The first argument of Choice() must be an index for one of the items".to_owned());
+            }
+            Ok(Node::Choice(normal, items))
+        },
+        "Optional" => {
+            let item = match args.first() {
+                Some(arg) => try!(to_node(arg)),
+                None => return Err("Optional() expects an item".to_owned()),
+            };
+            Ok(optional(item, try!(skip_argument(args.get(1)))))
+        },
+        "OneOrMore" | "ZeroOrMore" => {
+            let item = match args.first() {
+                Some(arg) => try!(to_node(arg)),
+                None => return Err(format!("{}() expects an item", name)),
+            };
+            let rep = match args.get(1) {
+                Some(arg) => try!(to_node(arg)),
+                None => Node::Skip,
+            };
+            let one_or_more = Node::OneOrMore(Box::new(item), Box::new(rep));
+
+            if name == "ZeroOrMore" {
+                Ok(optional(one_or_more, try!(skip_argument(args.get(2)))))
+            } else {
+                Ok(one_or_more)
+            }
+        },
+        _ => Err(format!("{}() is not supported", name)),
+    }
+}
+
+fn skip_argument(arg: Option<&Value>) -> Result<bool, String> {
+    match arg {
+        None => Ok(false),
+        Some(&Value::Str(ref s)) if s == "skip" => Ok(true),
+        Some(v) => Err(format!("Unknown value for Optional()'s 'skip' argument: {:?}", v)),
+    }
+}
+
+fn optional(item: Node, skip: bool) -> Node {
+    if skip {
+        Node::Choice(0, vec![Node::Skip, item])
+    } else {
+        Node::Choice(1, vec![Node::Skip, item])
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_parse_nested() {
+    let diagrams = parse("Diagram(Terminal('a'), Optional(NonTerminal(\"b\")), 'c').addTo();").unwrap();
+    assert_eq!(diagrams,
+               vec![Diagram {
+                        items: vec![Node::Start(false),
+                                    Node::Terminal("a".to_owned()),
+                                    Node::Choice(1, vec![Node::Skip, Node::NonTerminal("b".to_owned())]),
+                                    Node::Terminal("c".to_owned()),
+                                    Node::End(false)],
+                    }]);
+}
+
+#[test]
+fn test_parse_unsupported() {
+    assert!(render("Diagram(Stack('a', 'b'))").is_none());
+    assert!(render("var x = 5;").is_none());
+    assert!(render("").is_none());
+}
+
+#[test]
+fn test_terminal_dims() {
+    let dims = Node::Terminal("abc".to_owned()).dims();
+    assert_eq!(dims.width, 44.0);
+    assert_eq!(dims.up, 11.0);
+    assert_eq!(dims.down, 11.0);
+}
+
+#[test]
+fn test_arc() {
+    assert_eq!(Path::new(0.0, 0.0).arc("se").end(), "<path d=\"M0 0a10 10 0 0 0 10 -10\"></path>\n");
+    assert_eq!(Path::new(0.0, 0.0).arc("ne").end(), "<path d=\"M0 0a10 10 0 0 1 10 10\"></path>\n");
+}
+
+#[test]
+fn test_render_snapshot() {
+    let svg = render("Diagram(Terminal('a'))").unwrap();
+    assert_eq!(svg,
+               "<svg class=\"railroad-diagram\" xmlns=\"http://www.w3.org/2000/svg\" width=\"128\" height=\"62\" \
+                viewBox=\"0 0 128 62\">\n<g transform=\"translate(.5 .5)\">\n\
+                <path d=\"M 20 21 v 20 m 10 -20 v 20 m -10 -10 h 20.5\"></path>\n\
+                <path d=\"M40 31h10\"></path>\n\
+                <g class=\"terminal\">\n\
+                <path d=\"M50 31h0\"></path>\n\
+                <path d=\"M78 31h0\"></path>\n\
+                <rect x=\"50\" y=\"20\" width=\"28\" height=\"22\" rx=\"10\" ry=\"10\"></rect>\n\
+                <text x=\"64\" y=\"35\">a</text>\n\
+                </g>\n\
+                <path d=\"M78 31h10\"></path>\n\
+                <path d=\"M 88 31 h 20 m -10 -10 v 20 m 10 -20 v 20\"></path>\n\
+                </g>\n</svg>\n");
+}
@@ -0,0 +1,215 @@
+//! Exports the book as JSON, `book.json` in the destination directory, for tools that need the
+//! structure and content of the book without reading the generated html.
+//!
+//! The document has a `schema` version, which changes whenever a field is removed or changes
+//! meaning, the configuration of the book and its items in the nested order of `SUMMARY.md`.
+//! Chapters with a file also have their markdown, front matter, rendered html, headings and links.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use serde_json::value::{ToJson, Value};
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use renderer::html_handlebars::{self, links};
+use utils;
+
+/// The version of the structure of `book.json`
+pub const SCHEMA_VERSION: u64 = 1;
+
+pub struct Json;
+
+impl Json {
+    pub fn new() -> Self {
+        Json
+    }
+}
+
+impl Renderer for Json {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (json)");
+
+        let mut data = BTreeMap::new();
+        data.insert("schema".to_owned(), SCHEMA_VERSION.to_json());
+        data.insert("generator".to_owned(), format!("mdbook {}", env!("CARGO_PKG_VERSION")).to_json());
+        data.insert("config".to_owned(), config_to_json(book));
+        data.insert("items".to_owned(), try!(items_to_json(book, &book.content)));
+
+        let path = book.get_dest().join("book.json");
+        let mut file = try!(utils::fs::create_file(&path));
+        try!(file.write_all(try!(serde_json::to_string_pretty(&data)).as_bytes()));
+        info!("[*] Creating {:?} ✓", path);
+
+        Ok(())
+    }
+}
+
+/// The settings of the book, directories are relative to the root of the book when they are in it
+fn config_to_json(book: &MDBook) -> Value {
+    let relative = |path: &Path| path.strip_prefix(book.get_root()).unwrap_or(path).to_string_lossy().into_owned();
+
+    let mut config = BTreeMap::new();
+    config.insert("title".to_owned(), book.get_title().to_json());
+    config.insert("author".to_owned(), book.get_author().to_json());
+    config.insert("description".to_owned(), book.get_description().to_json());
+    config.insert("language".to_owned(), book.get_language().to_json());
+    config.insert("identifier".to_owned(), book.get_identifier().to_json());
+    config.insert("src".to_owned(), relative(book.get_src()).to_json());
+    config.insert("dest".to_owned(), relative(book.get_dest()).to_json());
+    config.insert("svg-diagrams".to_owned(), book.get_svg_diagrams().to_json());
+    config.insert("math".to_owned(), book.get_math().to_json());
+    config.insert("prerender-math".to_owned(), book.get_prerender_math().to_json());
+    config.insert("js-engines".to_owned(), book.get_js_engines().to_json());
+    config.insert("playground".to_owned(), book.get_playground().to_json());
+    config.insert("fold-level".to_owned(), book.get_fold_level().to_json());
+    config.insert("markdown-toc".to_owned(), book.get_markdown_toc().to_json());
+    config.insert("man-pages".to_owned(), book.get_man_pages().to_json());
+    config.insert("man-section".to_owned(), book.get_man_section().to_json());
+    config.insert("additional-css".to_owned(),
+                  book.get_additional_css().iter().map(|p| relative(p)).collect::<Vec<_>>().to_json());
+    config.insert("additional-js".to_owned(),
+                  book.get_additional_js().iter().map(|p| relative(p)).collect::<Vec<_>>().to_json());
+    config.insert("self-contained".to_owned(), book.get_self_contained().to_json());
+    config.insert("highlight-js".to_owned(), book.get_highlight_js().to_json());
+
+    let themes: Vec<BTreeMap<String, Value>> = book.get_themes()
+        .iter()
+        .map(|scheme| {
+            let mut theme = BTreeMap::new();
+            theme.insert("id".to_owned(), scheme.id.to_json());
+            theme.insert("name".to_owned(), scheme.name.to_json());
+            theme.insert("css".to_owned(), scheme.css.as_ref().map(|p| relative(p)).to_json());
+            theme.insert("dark".to_owned(), scheme.dark.to_json());
+            theme.insert("highlight-css".to_owned(), scheme.highlight_css.as_ref().map(|p| relative(p)).to_json());
+            theme
+        })
+        .collect();
+    config.insert("themes".to_owned(), themes.to_json());
+    config.insert("default-theme".to_owned(), book.get_default_theme().to_json());
+    config.insert("preferred-dark-theme".to_owned(), book.get_preferred_dark_theme().to_json());
+    config.to_json()
+}
+
+fn items_to_json(book: &MDBook, items: &[BookItem]) -> Result<Value, Box<Error>> {
+    let mut entries = vec![];
+
+    for item in items {
+        let mut entry = BTreeMap::new();
+
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(s), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => {
+                entry.insert("type".to_owned(), "spacer".to_json());
+                entries.push(entry);
+                continue;
+            },
+        };
+
+        entry.insert("type".to_owned(), (if section.is_some() { "chapter" } else { "affix" }).to_json());
+        entry.insert("section".to_owned(), section.to_json());
+        entry.insert("name".to_owned(), ch.name.to_json());
+
+        if ch.path != PathBuf::new() {
+            let path = match ch.path.to_str() {
+                Some(p) => p,
+                None => return Err(Box::new(io::Error::new(io::ErrorKind::Other, "Could not convert path to str"))),
+            };
+            entry.insert("path".to_owned(), path.to_json());
+            let link = ch.path.with_extension("html").to_string_lossy().replace("\\", "/");
+            entry.insert("link".to_owned(), link.to_json());
+            entry.insert("chapter".to_owned(), try!(chapter_to_json(book, &ch.path)));
+        } else {
+            entry.insert("path".to_owned(), Value::Null);
+        }
+
+        entry.insert("children".to_owned(), try!(items_to_json(book, &ch.sub_items)));
+        entries.push(entry);
+    }
+
+    Ok(entries.to_json())
+}
+
+/// The content of a chapter: its markdown as written, front matter, and the html the html
+/// renderer makes of it with the headings and links it contains
+fn chapter_to_json(book: &MDBook, path: &Path) -> Result<Value, Box<Error>> {
+    let chapter = try!(html_handlebars::render_chapter(book, path));
+    let markdown = utils::split_front_matter(&chapter.source).1;
+
+    let headings: Vec<BTreeMap<String, Value>> = chapter.headings
+        .iter()
+        .map(|heading| {
+            let mut data = BTreeMap::new();
+            data.insert("level".to_owned(), heading.level.to_json());
+            data.insert("text".to_owned(), heading.text.to_json());
+            data.insert("id".to_owned(), heading.id.to_json());
+            data
+        })
+        .collect();
+
+    let found_links: Vec<BTreeMap<String, Value>> = links::find_chapter_links(book.get_src(), path, &chapter.source)
+        .into_iter()
+        .map(|(file, link)| {
+            let mut data = BTreeMap::new();
+            if file != path {
+                data.insert("file".to_owned(), file.to_string_lossy().into_owned().to_json());
+            }
+            data.insert("external".to_owned(), (!utils::is_relative_link(&link.dest)).to_json());
+            data.insert("dest".to_owned(), link.dest.to_json());
+            data.insert("line".to_owned(), link.line.to_json());
+            data
+        })
+        .collect();
+
+    let mut data = BTreeMap::new();
+    data.insert("markdown".to_owned(), markdown.to_json());
+    data.insert("front-matter".to_owned(), chapter.front_matter.to_json());
+    data.insert("html".to_owned(), chapter.html.to_json());
+    data.insert("headings".to_owned(), headings.to_json());
+    data.insert("links".to_owned(), found_links.to_json());
+    Ok(data.to_json())
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_chapter_to_json() {
+    extern crate tempdir;
+    use std::fs::{self, File};
+
+    let root = tempdir::TempDir::new("json").unwrap();
+    fs::create_dir(root.path().join("src")).unwrap();
+    File::create(root.path().join("src/intro.md"))
+        .unwrap()
+        .write_all(b"---\nname: intro\n---\n# Intro\n\nSee [next](sub/next.md#top) and <https://example.com>.\n")
+        .unwrap();
+
+    let book = MDBook::new(root.path(), true);
+    let chapter = chapter_to_json(&book, Path::new("intro.md")).unwrap();
+    let chapter = chapter.as_object().unwrap();
+
+    assert_eq!(chapter["markdown"].as_str(),
+               Some("# Intro\n\nSee [next](sub/next.md#top) and <https://example.com>.\n"));
+    assert_eq!(chapter["front-matter"].find("name").and_then(|n| n.as_str()), Some("intro"));
+    assert!(chapter["html"].as_str().unwrap().contains("<a href=\"sub/next.html#top\">next</a>"));
+
+    let headings = chapter["headings"].as_array().unwrap();
+    assert_eq!(headings.len(), 1);
+    assert_eq!(headings[0].find("id").and_then(|id| id.as_str()), Some("intro"));
+
+    let links: Vec<(&str, bool)> = chapter["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| (l.find("dest").unwrap().as_str().unwrap(), l.find("external").unwrap().as_bool().unwrap()))
+        .collect();
+    assert_eq!(links, vec![("sub/next.md#top", false), ("https://example.com", true)]);
+}
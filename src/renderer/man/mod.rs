@@ -0,0 +1,81 @@
+//! Renders chapters of the book as man pages, in the `man/` directory of the destination.
+//!
+//! Every chapter becomes a page unless the `man-pages` setting lists the ones to convert. The
+//! front matter of a chapter can set the `name`, `section`, `description`, `date` and `manual` of
+//! its page, by default the name is the file name of the chapter and its description the name of
+//! the chapter in `SUMMARY.md`.
+
+mod roff;
+
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use utils;
+
+pub struct Man;
+
+impl Man {
+    pub fn new() -> Self {
+        Man
+    }
+}
+
+impl Renderer for Man {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (man)");
+
+        let selected = book.get_man_pages();
+        let mut rendered = vec![];
+
+        for item in book.iter() {
+            let ch = match *item {
+                BookItem::Chapter(_, ref ch) |
+                BookItem::Affix(ref ch) if ch.path != PathBuf::new() => ch,
+                _ => continue,
+            };
+            if let Some(pages) = selected {
+                if !pages.iter().any(|p| Path::new(p) == ch.path) {
+                    continue;
+                }
+            }
+
+            let (front_matter, markdown) = try!(super::read_chapter_with_front_matter(book, &ch.path));
+            let value = |key: &str, default: &str| front_matter.get(key).cloned().unwrap_or(default.to_owned());
+
+            let stem = ch.path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            let name = value("name", &stem);
+            let section = value("section", book.get_man_section());
+            let description = value("description", &ch.name);
+            let date = value("date", "");
+            let manual = value("manual", "");
+
+            let page = roff::Page {
+                name: &name,
+                section: &section,
+                description: &description,
+                date: &date,
+                source: book.get_title(),
+                manual: &manual,
+            };
+
+            let path = book.get_dest().join("man").join(format!("{}.{}", name, section));
+            let mut file = try!(utils::fs::create_file(&path));
+            try!(file.write_all(roff::render(&markdown, &page).as_bytes()));
+            info!("[*] Creating {:?} ✓", path);
+
+            rendered.push(ch.path.clone());
+        }
+
+        if let Some(pages) = selected {
+            for page in pages.iter().filter(|p| !rendered.iter().any(|r| r == Path::new(p))) {
+                warn!("[-] {} is not a chapter of the book, it has no man page", page);
+            }
+        }
+
+        Ok(())
+    }
+}
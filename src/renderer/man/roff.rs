@@ -0,0 +1,452 @@
+//! Turns the markdown of a chapter into a man(7) page written in roff.
+
+use pulldown_cmark::{Parser, Event, Tag, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+
+use utils;
+
+/// What goes in the header of a man page
+pub struct Page<'a> {
+    pub name: &'a str,
+    pub section: &'a str,
+    /// The one line summary following the name in the NAME section
+    pub description: &'a str,
+    pub date: &'a str,
+    /// The project the page belongs to, shown in the footer
+    pub source: &'a str,
+    /// The title of the manual, shown in the header. `man` uses the name of the section when empty.
+    pub manual: &'a str,
+}
+
+/// Renders a chapter as a man page. A first level heading at the start of the chapter is left
+/// out, the NAME section takes its place.
+pub fn render(markdown: &str, page: &Page) -> String {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let mut state = State {
+        out: String::new(),
+        buffer: None,
+        started: false,
+        title: false,
+        heading: None,
+        bold: 0,
+        italic: 0,
+        code: false,
+        links: vec![],
+        code_block: None,
+        lists: vec![],
+        item_start: false,
+        table: None,
+        has_table: false,
+    };
+
+    for event in Parser::new_ext(markdown, opts) {
+        state.event(event);
+    }
+
+    let mut roff = String::new();
+    // Tells `man` to run the page through tbl
+    if state.has_table {
+        roff.push_str("'\\\" t\n");
+    }
+    roff.push_str(&format!(".TH \"{}\" \"{}\" \"{}\" \"{}\" \"{}\"\n",
+                           quote(&page.name.to_uppercase()),
+                           quote(page.section),
+                           quote(page.date),
+                           quote(page.source),
+                           quote(page.manual)));
+    roff.push_str(&format!(".SH NAME\n{} \\- {}\n", escape(page.name), escape(page.description)));
+    roff.push_str(&state.out);
+    if !roff.ends_with('\n') {
+        roff.push('\n');
+    }
+    roff
+}
+
+/// Escapes the characters roff gives a meaning to in text
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+/// Escapes the argument of a request written between double quotes
+fn quote(text: &str) -> String {
+    escape(text).replace('"', "\\(dq")
+}
+
+struct State {
+    out: String,
+    /// The text of the current heading or table cell, which is written out at its end
+    buffer: Option<String>,
+
+    /// Whether a block was seen, the first level heading is only left out at the start
+    started: bool,
+    /// Inside the heading that is left out
+    title: bool,
+    heading: Option<i32>,
+
+    bold: usize,
+    italic: usize,
+    code: bool,
+    /// The destinations of the links the text is in, with their text so far
+    links: Vec<(String, String)>,
+
+    /// The info string and content of the current code block
+    code_block: Option<(String, String)>,
+
+    /// The next number of the ordered lists, `None` for bullet lists
+    lists: Vec<Option<usize>>,
+    /// Right after the `.IP` of a list item, its first paragraph needs no request of its own
+    item_start: bool,
+
+    /// The rows of the current table, each row has the text of its cells
+    table: Option<Vec<Vec<String>>>,
+    has_table: bool,
+}
+
+impl State {
+    fn end_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes a request on a line of its own
+    fn request(&mut self, request: &str) {
+        self.end_line();
+        self.out.push_str(request);
+        self.out.push('\n');
+    }
+
+    /// Starts a paragraph, unless it directly follows a heading or the marker of a list item
+    fn paragraph(&mut self) {
+        if self.item_start {
+            self.item_start = false;
+        } else if !self.lists.is_empty() {
+            self.request(".sp");
+        } else {
+            let last = self.out.trim_right_matches('\n').rsplit('\n').next().unwrap_or("");
+            if !(last.starts_with(".SH") || last.starts_with(".SS")) {
+                self.request(".PP");
+            }
+        }
+    }
+
+    fn font(&self) -> &'static str {
+        match (self.bold > 0 || self.code, self.italic > 0) {
+            (false, false) => "\\fR",
+            (true, false) => "\\fB",
+            (false, true) => "\\fI",
+            (true, true) => "\\f(BI",
+        }
+    }
+
+    /// Writes roff that is already escaped to the current heading, table cell or the page
+    fn push_roff(&mut self, roff: &str) {
+        let target = self.buffer.as_mut().unwrap_or(&mut self.out);
+        // A line starting with a dot or a quote would be a request
+        if (target.is_empty() || target.ends_with('\n')) && (roff.starts_with('.') || roff.starts_with('\'')) {
+            target.push_str("\\&");
+        }
+        target.push_str(roff);
+    }
+
+    fn push_text(&mut self, text: &str) {
+        for &mut (_, ref mut link_text) in &mut self.links {
+            link_text.push_str(text);
+        }
+
+        let text = match self.heading {
+            Some(level) if level <= 2 => text.to_uppercase(),
+            _ => text.to_owned(),
+        };
+        self.push_roff(&escape(&text));
+    }
+
+    fn switch_font(&mut self) {
+        let font = self.font();
+        self.push_roff(font);
+    }
+
+    fn event(&mut self, event: Event) {
+        if let Some((_, ref mut code)) = self.code_block {
+            match event {
+                Event::Text(ref text) => {
+                    code.push_str(text);
+                    return;
+                },
+                Event::End(Tag::CodeBlock(_)) => {},
+                _ => return,
+            }
+        }
+        if self.title {
+            if let Event::End(Tag::Header(_)) = event {
+                self.title = false;
+            }
+            return;
+        }
+
+        if let Event::Start(ref tag) = event {
+            let first = !self.started;
+            self.started = true;
+            if let Tag::Header(1) = *tag {
+                if first {
+                    self.title = true;
+                    return;
+                }
+            }
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) => self.paragraph(),
+            Event::End(Tag::Paragraph) => self.end_line(),
+
+            Event::Start(Tag::Header(level)) => {
+                self.heading = Some(level);
+                self.buffer = Some(String::new());
+            },
+            Event::End(Tag::Header(level)) => {
+                let text = self.buffer.take().unwrap_or_default();
+                self.heading = None;
+                let request = if level <= 2 { ".SH" } else { ".SS" };
+                self.request(&format!("{} {}", request, text.trim()));
+            },
+
+            Event::Start(Tag::BlockQuote) => self.request(".RS 4"),
+            Event::End(Tag::BlockQuote) => self.request(".RE"),
+
+            Event::Start(Tag::CodeBlock(info)) => self.code_block = Some((info.into_owned(), String::new())),
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((info, code)) = self.code_block.take() {
+                    self.write_code_block(&info, &code);
+                }
+            },
+
+            Event::Start(Tag::List(start)) => {
+                // Nested lists are indented by the width of the marker of their parent
+                if let Some(parent) = self.lists.last().cloned() {
+                    self.request(&format!(".RS {}", marker_width(parent)));
+                }
+                self.lists.push(start);
+            },
+            Event::End(Tag::List(_)) => {
+                self.lists.pop();
+                if !self.lists.is_empty() {
+                    self.request(".RE");
+                }
+            },
+            Event::Start(Tag::Item) => {
+                let marker = match self.lists.last_mut() {
+                    Some(&mut Some(ref mut n)) => {
+                        *n += 1;
+                        format!("\"{}.\"", *n - 1)
+                    },
+                    _ => "\\(bu".to_owned(),
+                };
+                let width = marker_width(self.lists.last().cloned().unwrap_or(None));
+                self.request(&format!(".IP {} {}", marker, width));
+                self.item_start = true;
+            },
+            Event::End(Tag::Item) => self.item_start = false,
+
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                self.request(&format!(".IP \"[{}]\" 4", quote(&name)));
+                self.item_start = true;
+            },
+            Event::End(Tag::FootnoteDefinition(_)) => self.item_start = false,
+            Event::FootnoteReference(name) => self.push_roff(&format!("[{}]", escape(&name))),
+
+            Event::Start(Tag::Table(_)) => {
+                self.table = Some(vec![]);
+                self.has_table = true;
+            },
+            Event::End(Tag::Table(_)) => {
+                if let Some(rows) = self.table.take() {
+                    self.write_table(&rows);
+                }
+            },
+            Event::Start(Tag::TableHead) |
+            Event::Start(Tag::TableRow) => {
+                if let Some(ref mut rows) = self.table {
+                    rows.push(vec![]);
+                }
+            },
+            Event::Start(Tag::TableCell) => self.buffer = Some(String::new()),
+            Event::End(Tag::TableCell) => {
+                let cell = self.buffer.take().unwrap_or_default();
+                if let Some(row) = self.table.as_mut().and_then(|rows| rows.last_mut()) {
+                    row.push(cell.trim().to_owned());
+                }
+            },
+
+            Event::Start(Tag::Emphasis) |
+            Event::Start(Tag::Image(..)) => {
+                self.italic += 1;
+                self.switch_font();
+            },
+            Event::End(Tag::Emphasis) |
+            Event::End(Tag::Image(..)) => {
+                self.italic -= 1;
+                self.switch_font();
+            },
+            Event::Start(Tag::Strong) => {
+                self.bold += 1;
+                self.switch_font();
+            },
+            Event::End(Tag::Strong) => {
+                self.bold -= 1;
+                self.switch_font();
+            },
+            Event::Start(Tag::Code) => {
+                self.code = true;
+                self.switch_font();
+            },
+            Event::End(Tag::Code) => {
+                self.code = false;
+                self.switch_font();
+            },
+
+            Event::Start(Tag::Link(dest, _)) => self.links.push((dest.into_owned(), String::new())),
+            Event::End(Tag::Link(..)) => {
+                // Links to other chapters lead nowhere in a man page, only the text is kept
+                if let Some((dest, text)) = self.links.pop() {
+                    if !utils::is_relative_link(&dest) && text != dest {
+                        self.push_roff(&format!(" <{}>", escape(&dest)));
+                    }
+                }
+            },
+
+            Event::Start(Tag::Rule) => self.request(".sp"),
+
+            Event::Text(text) => self.push_text(&text),
+            Event::SoftBreak => self.push_roff("\n"),
+            Event::HardBreak => self.request(".br"),
+
+            // Raw html can not be shown in a man page
+            Event::Html(_) |
+            Event::InlineHtml(_) => {},
+
+            _ => {},
+        }
+    }
+
+    fn write_code_block(&mut self, info: &str, code: &str) {
+        let lang = info.split(',').next().unwrap_or("").trim();
+
+        if self.lists.is_empty() && !self.item_start {
+            self.paragraph();
+        } else {
+            self.request(".sp");
+        }
+        self.item_start = false;
+        self.request(".RS 4");
+        self.request(".nf");
+        for line in code.trim_right_matches('\n').lines() {
+            // Like rustdoc, lines starting with `# ` are hidden in Rust code
+            if lang == "rust" && (line.starts_with("# ") || line.trim() == "#") {
+                continue;
+            }
+            self.push_roff(&escape(line));
+            self.out.push('\n');
+        }
+        self.request(".fi");
+        self.request(".RE");
+    }
+
+    /// Writes a table for tbl, the cells are separated by tabs and the header is bold
+    fn write_table(&mut self, rows: &[Vec<String>]) {
+        let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        self.paragraph();
+        self.request(".TS");
+        self.request(&vec!["lb"; columns].join(" "));
+        self.request(&format!("{}.", vec!["l"; columns].join(" ")));
+        for (i, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row.iter()
+                .map(|cell| if cell.starts_with('.') || cell.starts_with('\'') { format!("\\&{}", cell) } else { cell.clone() })
+                .collect();
+            self.request(&cells.join("\t"));
+            if i == 0 {
+                self.request("_");
+            }
+        }
+        self.request(".TE");
+    }
+}
+
+/// The indentation of the items of a list, enough for their marker
+fn marker_width(list: Option<usize>) -> usize {
+    if list.is_some() { 4 } else { 2 }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[cfg(test)]
+fn test_page() -> Page<'static> {
+    Page {
+        name: "mdbook-build",
+        section: "1",
+        description: "build a book",
+        date: "",
+        source: "mdBook",
+        manual: "",
+    }
+}
+
+#[test]
+fn test_render() {
+    let markdown = "# The build command\n\nBuilds the book from `src` to **book**.\n\n## Options\n\n\
+                    ### --open\n\n.hidden and -o, see [the site](https://example.com) or [serve](serve.md).\n\n\
+                    ```rust\n# fn main() {\nlet a = \"\\n\";\n# }\n```\n";
+
+    assert_eq!(render(markdown, &test_page()),
+               ".TH \"MDBOOK\\-BUILD\" \"1\" \"\" \"mdBook\" \"\"\n\
+                .SH NAME\n\
+                mdbook\\-build \\- build a book\n\
+                .PP\n\
+                Builds the book from \\fBsrc\\fR to \\fBbook\\fR.\n\
+                .SH OPTIONS\n\
+                .SS \\-\\-open\n\
+                \\&.hidden and \\-o, see the site <https://example.com> or serve.\n\
+                .PP\n\
+                .RS 4\n\
+                .nf\n\
+                let a = \"\\en\";\n\
+                .fi\n\
+                .RE\n");
+}
+
+#[test]
+fn test_render_lists() {
+    let markdown = "- one\n- two\n    1. first\n    2. second\n\nAfter\n";
+
+    assert_eq!(render(markdown, &test_page()).split(".SH NAME\n").nth(1).unwrap(),
+               "mdbook\\-build \\- build a book\n\
+                .IP \\(bu 2\n\
+                one\n\
+                .IP \\(bu 2\n\
+                two\n\
+                .RS 2\n\
+                .IP \"1.\" 4\n\
+                first\n\
+                .IP \"2.\" 4\n\
+                second\n\
+                .RE\n\
+                .PP\n\
+                After\n");
+}
+
+#[test]
+fn test_render_table() {
+    let markdown = "| Key | Default |\n|-----|---------|\n| `dest` | book |\n";
+    let roff = render(markdown, &test_page());
+
+    assert!(roff.starts_with("'\\\" t\n.TH "));
+    assert!(roff.ends_with(".PP\n.TS\nlb lb\nl l.\nKey\tDefault\n_\n\\fBdest\\fR\tbook\n.TE\n"));
+}
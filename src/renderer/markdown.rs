@@ -0,0 +1,360 @@
+//! Renders the whole book to a single markdown file, `book.md` in the destination directory.
+//!
+//! The chapters follow each other in the order of `SUMMARY.md`, with the included files and the
+//! playpens spliced in. The headings of a chapter are moved down one level for each level it is
+//! nested at, and every chapter and heading gets an anchor so the links between chapters can point
+//! into the document.
+
+extern crate regex;
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::io::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Parser, Event, Tag, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use renderer::html_handlebars::print;
+use utils;
+
+lazy_static! {
+    static ref FOOTNOTE_RE: regex::Regex = regex::Regex::new(r"\[\^([^\]\s]+)\]").unwrap();
+}
+
+pub struct Markdown;
+
+impl Markdown {
+    pub fn new() -> Self {
+        Markdown
+    }
+}
+
+impl Renderer for Markdown {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (markdown)");
+
+        let anchors = print::chapter_anchors(book.iter().filter_map(|item| match *item {
+            BookItem::Chapter(_, ref ch) |
+            BookItem::Affix(ref ch) if ch.path != PathBuf::new() => Some(&*ch.path),
+            _ => None,
+        }));
+
+        // The title of the book is the only first level heading when there is one
+        let title_shift = if book.get_title().is_empty() { 0 } else { 1 };
+
+        let mut content = String::new();
+        if !book.get_title().is_empty() {
+            content.push_str(&format!("# {}\n\n", book.get_title()));
+        }
+        if !book.get_description().is_empty() {
+            content.push_str(&format!("{}\n\n", book.get_description()));
+        }
+        if book.get_markdown_toc() {
+            render_toc(&book.content, &anchors, 0, &mut content);
+            content.push('\n');
+        }
+
+        for item in book.iter() {
+            let (section, ch) = match *item {
+                BookItem::Chapter(ref s, ref ch) => (Some(s), ch),
+                BookItem::Affix(ref ch) => (None, ch),
+                BookItem::Spacer => continue,
+            };
+            let shift = title_shift + section.map(|s| section_depth(s)).unwrap_or(0);
+
+            // Chapters without a file only have their name as heading
+            if ch.path == PathBuf::new() {
+                content.push_str(&format!("{} {}\n\n", hashes(shift + 1), ch.name));
+                continue;
+            }
+
+            let markdown = try!(super::read_chapter(book, &ch.path));
+            content.push_str(&render_chapter(&markdown, &ch.path, shift, &anchors, book.get_math()));
+            content.push('\n');
+        }
+
+        let path = book.get_dest().join("book.md");
+        let mut file = try!(utils::fs::create_file(&path));
+        try!(file.write_all(content.as_bytes()));
+        info!("[*] Creating {:?} ✓", path);
+
+        // Images and other files keep their path relative to book.md
+        try!(utils::fs::copy_files_except_ext(book.get_src(), book.get_dest(), true, &["md"]));
+
+        Ok(())
+    }
+}
+
+/// The list of the chapters, linking to their anchors
+fn render_toc(items: &[BookItem], anchors: &BTreeMap<PathBuf, String>, depth: usize, toc: &mut String) {
+    for item in items {
+        let (section, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (Some(s), ch),
+            BookItem::Affix(ref ch) => (None, ch),
+            BookItem::Spacer => continue,
+        };
+
+        let indent: String = iter::repeat("    ").take(depth).collect();
+        if let Some(anchor) = anchors.get(&ch.path.with_extension("html")) {
+            let label = section.map(|s| format!("{} {}", s, ch.name)).unwrap_or(ch.name.clone());
+            toc.push_str(&format!("{}- [{}](#{})\n", indent, label, anchor));
+        } else {
+            // The dots are escaped, `1. Name` at the start of a list item would start another list
+            let label = section.map(|s| format!("{} {}", s.replace(".", "\\."), ch.name)).unwrap_or(ch.name.clone());
+            toc.push_str(&format!("{}- {}\n", indent, label));
+        }
+        render_toc(&ch.sub_items, anchors, depth + 1, toc);
+    }
+}
+
+/// The number of levels a chapter is nested at, 0 for `1.` and 1 for `1.2.`
+fn section_depth(section: &str) -> usize {
+    section.trim_right_matches('.').split('.').count() - 1
+}
+
+fn hashes(level: usize) -> String {
+    iter::repeat('#').take(level.min(6)).collect()
+}
+
+/// Prepares a chapter for the single file: its headings are moved down `shift` levels and get an
+/// anchor prefixed with the anchor of the chapter, links to chapters point to the anchors and the
+/// footnotes are prefixed with the anchor of the chapter so they do not collide with the footnotes
+/// of other chapters. `anchors` contains the anchors of all the chapters, `math` is true when the
+/// book has math, which changes the ids of headings containing math.
+pub fn render_chapter(markdown: &str,
+                      path: &Path,
+                      shift: usize,
+                      anchors: &BTreeMap<PathBuf, String>,
+                      math: bool)
+                      -> String {
+    let page = path.with_extension("html");
+    let anchor = match anchors.get(&page) {
+        Some(anchor) => anchor.clone(),
+        None => print::chapter_anchors(iter::once(path)).remove(&page).unwrap_or_default(),
+    };
+    let markdown = rewrite_links(markdown, &page, anchors);
+    let lines: Vec<&str> = markdown.lines().collect();
+
+    let mut out = format!("<a id=\"{}\"></a>\n\n", anchor);
+    let mut ids = HashSet::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+
+        // Code blocks are left as they are
+        if let Some((c, length)) = fence {
+            let closing = match code_fence(line) {
+                Some((d, l)) => d == c && l >= length && line.trim().chars().all(|x| x == c),
+                None => false,
+            };
+            if closing {
+                fence = None;
+            }
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        if let Some(opening) = code_fence(line) {
+            fence = Some(opening);
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        if let Some((level, text)) = atx_heading(line) {
+            out.push_str(&heading(level + shift, text, &anchor, &mut ids, math));
+            continue;
+        }
+
+        // A setext heading is turned into an ATX heading, there is no third level for them
+        let setext = lines.get(i).and_then(|next| setext_level(next));
+        if let Some(level) = setext {
+            if starts_paragraph(&lines, i - 1) {
+                out.push_str(&heading(level + shift, line.trim(), &anchor, &mut ids, math));
+                i += 1;
+                continue;
+            }
+        }
+
+        out.push_str(&FOOTNOTE_RE.replace_all(line, &*format!("[^{}]", print::heading_anchor(&anchor, "$1"))));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// A heading with an anchor, the id is the one the heading gets in the html pages
+fn heading(level: usize, text: &str, anchor: &str, ids: &mut HashSet<String>, math: bool) -> String {
+    let (_, headings) = utils::render_markdown_with_headings(&format!("# {}", text), math, false);
+    let id = headings.first().map(|h| h.id.clone()).unwrap_or_else(|| utils::normalize_id(text));
+
+    // A custom id is replaced by the anchor
+    let custom = format!("{{#{}}}", id);
+    let text = text.trim_right().trim_right_matches(&*custom).trim_right();
+
+    let id = utils::unique_id(id, ids);
+    format!("{} <a id=\"{}\"></a>{}\n", hashes(level), print::heading_anchor(anchor, &id), text)
+}
+
+/// Rewrites the links to chapters, and to headings in chapters, to their anchor in the single
+/// file. `page` is the html file of the chapter.
+fn rewrite_links(markdown: &str, page: &Path, anchors: &BTreeMap<PathBuf, String>) -> String {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let mut out = String::with_capacity(markdown.len());
+    let mut offset = 0;
+
+    for event in Parser::new_ext(markdown, opts) {
+        let dest = match event {
+            Event::Start(Tag::Link(dest, _)) => dest,
+            _ => continue,
+        };
+
+        let link = utils::md_link_to_html(&dest).unwrap_or(dest.to_string());
+        let target = match print::rewrite_link(&link, page, anchors) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        // The parser does not tell where the link is, so look for the destination in the source
+        let position = markdown[offset..]
+            .match_indices(&*dest)
+            .map(|(i, _)| i + offset)
+            .find(|&i| markdown[..i].ends_with(|c: char| c == '(' || c == '<' || c.is_whitespace()));
+        if let Some(position) = position {
+            out.push_str(&markdown[offset..position]);
+            out.push_str(&target);
+            offset = position + dest.len();
+        }
+    }
+
+    out.push_str(&markdown[offset..]);
+    out
+}
+
+/// Returns the level and the text of an ATX heading, `## Text ##`
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_left_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return None;
+    }
+
+    let level = rest.len() - rest.trim_left_matches('#').len();
+    let text = &rest[level..];
+    if level == 0 || level > 6 || !(text.is_empty() || text.starts_with(' ') || text.starts_with('\t')) {
+        return None;
+    }
+
+    // The closing sequence of `#` is not part of the text
+    let text = text.trim();
+    let without_closing = text.trim_right_matches('#');
+    if without_closing.is_empty() || without_closing.ends_with(' ') || without_closing.ends_with('\t') {
+        Some((level, without_closing.trim_right()))
+    } else {
+        Some((level, text))
+    }
+}
+
+/// Returns the level of the heading a setext underline, `===` or `---`, makes
+fn setext_level(line: &str) -> Option<usize> {
+    let text = line.trim();
+    if line.len() - line.trim_left_matches(' ').len() > 3 || text.is_empty() {
+        None
+    } else if text.chars().all(|c| c == '=') {
+        Some(1)
+    } else if text.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Whether the line `i` is a paragraph of a single line, which an underline turns into a heading
+fn starts_paragraph(lines: &[&str], i: usize) -> bool {
+    let line = lines[i];
+    let text = line.trim();
+    let first = text.chars().next().unwrap_or(' ');
+    let list_item = first.is_digit(10) &&
+                    text.trim_left_matches(|c: char| c.is_digit(10)).starts_with(|c| c == '.' || c == ')');
+
+    !text.is_empty() && line.len() - line.trim_left_matches(' ').len() <= 3 && !"#>-*+|<".contains(first) &&
+    !list_item && (i == 0 || lines[i - 1].trim().is_empty())
+}
+
+/// Returns the character and length of the fence when the line opens or closes a code block
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let rest = line.trim_left_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return None;
+    }
+
+    let c = match rest.chars().next() {
+        Some(c) if c == '`' || c == '~' => c,
+        _ => return None,
+    };
+    let length = rest.len() - rest.trim_left_matches(c).len();
+    if length >= 3 { Some((c, length)) } else { None }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_render_chapter() {
+    let anchors = print::chapter_anchors(vec![Path::new("intro.md"), Path::new("sub/next.md")]);
+    let markdown = "# Next {#start}\n\
+                    \n\
+                    See [the intro](intro.md#hello-world) and [below](#details).[^1]\n\
+                    \n\
+                    Or [the web](https://example.com/#details).\n\
+                    \n\
+                    Details\n\
+                    -------\n\
+                    \n\
+                    ```rust\n\
+                    # hidden\n\
+                    ```\n\
+                    \n\
+                    ## Details ##\n\
+                    \n\
+                    [^1]: A note\n";
+
+    assert_eq!(render_chapter(markdown, Path::new("sub/next.md"), 1, &anchors, false),
+               "<a id=\"sub-next\"></a>\n\
+                \n\
+                ## <a id=\"sub-next.start\"></a>Next\n\
+                \n\
+                See [the intro](#intro.hello-world) and [below](#sub-next.details).[^sub-next.1]\n\
+                \n\
+                Or [the web](https://example.com/#details).\n\
+                \n\
+                ### <a id=\"sub-next.details\"></a>Details\n\
+                \n\
+                ```rust\n\
+                # hidden\n\
+                ```\n\
+                \n\
+                ### <a id=\"sub-next.details-1\"></a>Details\n\
+                \n\
+                [^sub-next.1]: A note\n");
+}
+
+#[test]
+fn test_atx_heading() {
+    assert_eq!(atx_heading("## Title ##"), Some((2, "Title")));
+    assert_eq!(atx_heading("# C#"), Some((1, "C#")));
+    assert_eq!(atx_heading("#hashtag"), None);
+    assert_eq!(atx_heading("    # code"), None);
+    assert_eq!(section_depth("1.2."), 1);
+}
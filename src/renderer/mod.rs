@@ -1,9 +1,54 @@
 pub use self::html_handlebars::HtmlHandlebars;
+pub use self::pdf::Pdf;
+pub use self::epub::Epub;
+pub use self::markdown::Markdown;
+pub use self::man::Man;
+pub use self::text::Text;
+pub use self::json::Json;
 
 mod html_handlebars;
+mod pdf;
+mod epub;
+mod markdown;
+mod man;
+mod text;
+mod json;
 
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use book::MDBook;
+use utils;
 
 pub trait Renderer {
     fn render(&self, book: &::book::MDBook) -> Result<(), Box<Error>>;
 }
+
+/// Reads the markdown of a chapter with the included files and playpens spliced in, for the
+/// renderers that do not output html. `path` is relative to the source directory.
+fn read_chapter(book: &MDBook, path: &Path) -> Result<String, Box<Error>> {
+    read_chapter_with_front_matter(book, path).map(|(_, content)| content)
+}
+
+/// Reads a chapter like `read_chapter` and also returns the values of its front matter
+fn read_chapter_with_front_matter(book: &MDBook, path: &Path)
+                                  -> Result<(BTreeMap<String, String>, String), Box<Error>> {
+    let path = book.get_src().join(path);
+
+    debug!("[*]: Opening file: {:?}", path);
+    let mut content = String::new();
+    try!(try!(File::open(&path)).read_to_string(&mut content));
+
+    let (front_matter, rest) = utils::split_front_matter(&content);
+    let mut content = rest.to_owned();
+
+    content = html_handlebars::helpers::include::render_include(&content, &path);
+    if let Some(p) = path.parent() {
+        content = html_handlebars::helpers::playpen::render_playpen_markdown(&content, p);
+    }
+
+    Ok((front_matter, content))
+}
@@ -0,0 +1,188 @@
+//! The fonts used by the renderer: DejaVu Sans and DejaVu Sans Mono, which cover Latin, Greek and
+//! Cyrillic text and many symbols. The fonts are embedded in the PDF, reduced to the glyphs the
+//! book uses, and text is written as glyph ids.
+
+extern crate ttf_parser;
+extern crate subsetter;
+
+use self::ttf_parser::{Face, GlyphId};
+use self::subsetter::Profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+pub const ALL_FONTS: [Font; 5] = [Font::Regular, Font::Bold, Font::Italic, Font::BoldItalic, Font::Mono];
+
+lazy_static! {
+    static ref FACES: Vec<Face<'static>> = ALL_FONTS.iter()
+        .map(|font| Face::parse(font.data(), 0).expect("Invalid font"))
+        .collect();
+}
+
+/// The values of the font descriptor, in thousandths of the font size
+pub struct Metrics {
+    pub bbox: [f32; 4],
+    pub ascent: f32,
+    pub descent: f32,
+    pub cap_height: f32,
+    pub italic_angle: f32,
+}
+
+impl Font {
+    /// The name of the font in the resources of the pages
+    pub fn resource_name(&self) -> &'static str {
+        match *self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::BoldItalic => "F4",
+            Font::Mono => "F5",
+        }
+    }
+
+    pub fn base_font(&self) -> &'static str {
+        match *self {
+            Font::Regular => "DejaVuSans",
+            Font::Bold => "DejaVuSans-Bold",
+            Font::Italic => "DejaVuSans-Oblique",
+            Font::BoldItalic => "DejaVuSans-BoldOblique",
+            Font::Mono => "DejaVuSansMono",
+        }
+    }
+
+    /// The TrueType file of the font
+    pub fn data(&self) -> &'static [u8] {
+        match *self {
+            Font::Regular => include_bytes!("fonts/DejaVuSans.ttf"),
+            Font::Bold => include_bytes!("fonts/DejaVuSans-Bold.ttf"),
+            Font::Italic => include_bytes!("fonts/DejaVuSans-Oblique.ttf"),
+            Font::BoldItalic => include_bytes!("fonts/DejaVuSans-BoldOblique.ttf"),
+            Font::Mono => include_bytes!("fonts/DejaVuSansMono.ttf"),
+        }
+    }
+
+    fn face(&self) -> &'static Face<'static> {
+        &FACES[*self as usize]
+    }
+
+    pub fn bold(&self) -> Font {
+        match *self {
+            Font::Regular | Font::Bold => Font::Bold,
+            Font::Italic | Font::BoldItalic => Font::BoldItalic,
+            Font::Mono => Font::Mono,
+        }
+    }
+
+    pub fn italic(&self) -> Font {
+        match *self {
+            Font::Regular | Font::Italic => Font::Italic,
+            Font::Bold | Font::BoldItalic => Font::BoldItalic,
+            Font::Mono => Font::Mono,
+        }
+    }
+
+    /// The glyph of a character, `None` when the font has no glyph for it
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.face().glyph_index(c).map(|g| g.0)
+    }
+
+    /// Width of a glyph in thousandths of the font size
+    pub fn glyph_width(&self, glyph: u16) -> f32 {
+        let face = self.face();
+        face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0) as f32 * 1000.0 / face.units_per_em() as f32
+    }
+
+    /// Width of a character in thousandths of the font size, characters without a glyph are shown
+    /// with the empty box of glyph 0
+    pub fn char_width(&self, c: char) -> f32 {
+        self.glyph_width(self.glyph(c).unwrap_or(0))
+    }
+
+    /// Width of `text` in points when set in `size`
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c)).sum::<f32>() * size / 1000.0
+    }
+
+    /// Encodes text as the glyphs of the font, two bytes per glyph
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let glyph = self.glyph(c).unwrap_or(0);
+            bytes.push((glyph >> 8) as u8);
+            bytes.push(glyph as u8);
+        }
+        bytes
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let face = self.face();
+        let scale = 1000.0 / face.units_per_em() as f32;
+        let bbox = face.global_bounding_box();
+
+        Metrics {
+            bbox: [bbox.x_min as f32 * scale,
+                   bbox.y_min as f32 * scale,
+                   bbox.x_max as f32 * scale,
+                   bbox.y_max as f32 * scale],
+            ascent: face.ascender() as f32 * scale,
+            descent: face.descender() as f32 * scale,
+            cap_height: face.capital_height().unwrap_or(face.ascender()) as f32 * scale,
+            italic_angle: face.italic_angle().unwrap_or(0.0),
+        }
+    }
+
+    /// The font file reduced to the outlines of `glyphs`, the glyph ids are kept. The whole file
+    /// is returned if the font can not be reduced.
+    pub fn subset(&self, glyphs: &[u16]) -> Vec<u8> {
+        match subsetter::subset(self.data(), 0, Profile::pdf(glyphs)) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("[-] Could not reduce the font {}: {}", self.base_font(), e);
+                self.data().to_vec()
+            },
+        }
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_text_width() {
+    let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+
+    assert_eq!(Font::Mono.text_width("abcd", 10.0), Font::Mono.text_width("ijkl", 10.0));
+    assert!(close(Font::Mono.text_width("abcd", 10.0), 4.0 * 6.0205));
+    assert!(close(Font::Regular.text_width("Hi", 10.0), (751.95 + 277.83) / 100.0));
+    assert!(close(Font::Bold.text_width("Hi", 10.0), (836.91 + 342.77) / 100.0));
+}
+
+#[test]
+fn test_encode() {
+    let glyphs = |text: &str| Font::Regular.encode(text);
+
+    // Greek and Cyrillic letters and symbols have glyphs, the other characters are glyph 0
+    assert_eq!(glyphs("é").len(), 2);
+    assert!(["π", "ж", "✓", "→"].iter().all(|c| glyphs(c) != vec![0, 0]));
+    assert_eq!(glyphs("中"), vec![0, 0]);
+    assert_eq!(Font::Regular.glyph('中'), None);
+    assert_eq!(glyphs("ab")[..2], glyphs("a")[..]);
+}
+
+#[test]
+fn test_subset_keeps_glyph_ids() {
+    let a = Font::Regular.glyph('a').unwrap();
+    let data = Font::Regular.subset(&[0, a]);
+    let face = Face::parse(&data, 0).unwrap();
+
+    assert!(data.len() < Font::Regular.data().len() / 4);
+    assert_eq!(face.glyph_hor_advance(GlyphId(a)), Font::Regular.face().glyph_hor_advance(GlyphId(a)));
+}
@@ -0,0 +1,87 @@
+//! Colors of the code blocks of the PDF, the code is split into tokens by the highlighter of the
+//! html. Code in other languages still gets its comments, strings and numbers colored.
+
+use utils::highlight::{self, Kind, Language};
+
+/// A color as red, green and blue between 0 and 1
+pub type Color = (f32, f32, f32);
+
+pub const PLAIN: Color = (0.2, 0.2, 0.2);
+pub const COMMENT: Color = (0.55, 0.55, 0.55);
+pub const STRING: Color = (0.25, 0.5, 0.1);
+pub const NUMBER: Color = (0.75, 0.35, 0.0);
+pub const KEYWORD: Color = (0.55, 0.15, 0.6);
+pub const NAME: Color = (0.15, 0.35, 0.65);
+
+pub struct Highlighter(highlight::Highlighter);
+
+impl Highlighter {
+    /// A highlighter for a code block of the language `lang`, the info string of the block. Code
+    /// blocks without a language are Rust, like in rustdoc.
+    pub fn new(lang: &str) -> Self {
+        let language = if lang.trim().is_empty() { Language::find("rust") } else { Language::find(lang) };
+        Highlighter(highlight::Highlighter::new(language.unwrap_or_else(Language::generic)))
+    }
+
+    /// Splits a line into pieces of text of the same color
+    pub fn line(&mut self, line: &str) -> Vec<(String, Color)> {
+        let mut pieces: Vec<(String, Color)> = vec![];
+
+        for (kind, text) in self.0.line(line) {
+            let color = color(kind);
+            if let Some(last) = pieces.last_mut() {
+                if last.1 == color {
+                    last.0.push_str(&text);
+                    continue;
+                }
+            }
+            pieces.push((text, color));
+        }
+
+        pieces
+    }
+}
+
+fn color(kind: Kind) -> Color {
+    match kind {
+        Kind::Plain | Kind::Variable => PLAIN,
+        Kind::Comment => COMMENT,
+        Kind::String | Kind::Symbol => STRING,
+        Kind::Number | Kind::BuiltIn => NUMBER,
+        Kind::Keyword | Kind::Literal | Kind::Preprocessor => KEYWORD,
+        Kind::Title | Kind::Attribute => NAME,
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_highlight_rust() {
+    let mut highlighter = Highlighter::new("rust");
+    assert_eq!(highlighter.line("let x = \"a\\\"b\"; // 42"),
+               vec![("let".to_owned(), KEYWORD),
+                    (" x = ".to_owned(), PLAIN),
+                    ("\"a\\\"b\"".to_owned(), STRING),
+                    ("; ".to_owned(), PLAIN),
+                    ("// 42".to_owned(), COMMENT)]);
+
+    assert_eq!(highlighter.line("/* a"), vec![("/* a".to_owned(), COMMENT)]);
+    assert_eq!(highlighter.line("b */ 1"),
+               vec![("b */".to_owned(), COMMENT), (" ".to_owned(), PLAIN), ("1".to_owned(), NUMBER)]);
+}
+
+#[test]
+fn test_highlight_other_languages() {
+    let mut highlighter = Highlighter::new("unknown");
+    assert_eq!(highlighter.line("let x = 1; // \"a\""),
+               vec![("let x = ".to_owned(), PLAIN),
+                    ("1".to_owned(), NUMBER),
+                    ("; ".to_owned(), PLAIN),
+                    ("// \"a\"".to_owned(), COMMENT)]);
+
+    let mut highlighter = Highlighter::new("");
+    assert_eq!(highlighter.line("fn"), vec![("fn".to_owned(), KEYWORD)]);
+}
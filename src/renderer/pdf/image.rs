@@ -0,0 +1,326 @@
+//! Reads JPEG and PNG images so they can be embedded in the PDF without decoding them: the
+//! compressed data of JPEG files and the deflated data of PNG files can be used as they are.
+//!
+//! PDF has no colour space with transparency, the pixels of PNG images with an alpha channel are
+//! decoded to separate the colours from the alpha channel, which becomes a soft mask.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A palette of RGB colours
+    Indexed(Vec<u8>),
+}
+
+impl ColorSpace {
+    /// The number of values of a pixel
+    pub fn components(&self) -> i32 {
+        match *self {
+            ColorSpace::Gray | ColorSpace::Indexed(_) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Jpeg,
+    /// Deflated pixels
+    Deflate,
+    /// The deflated data of a PNG image, every row starts with the PNG filter of the row
+    Png,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub color_space: ColorSpace,
+    pub encoding: Encoding,
+    pub data: Vec<u8>,
+    /// The deflated alpha channel, one byte per pixel
+    pub smask: Option<Vec<u8>>,
+}
+
+/// Loads a JPEG or PNG image, the error describes why the image can not be embedded
+pub fn load(path: &Path) -> Result<Image, String> {
+    let mut data = vec![];
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_end(&mut data)) {
+        return Err(format!("could not read {}: {}", path.display(), e));
+    }
+
+    if data.starts_with(&[0xff, 0xd8]) {
+        parse_jpeg(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        parse_png(data)
+    } else {
+        Err(format!("{} is not a JPEG or PNG image", path.display()))
+    }
+}
+
+fn be16(data: &[u8], i: usize) -> u32 {
+    ((data[i] as u32) << 8) | data[i + 1] as u32
+}
+
+fn be32(data: &[u8], i: usize) -> u32 {
+    (be16(data, i) << 16) | be16(data, i + 2)
+}
+
+fn parse_jpeg(data: Vec<u8>) -> Result<Image, String> {
+    let mut i = 2;
+
+    // The size is in the start of frame segment
+    while i + 4 <= data.len() {
+        if data[i] != 0xff {
+            return Err("invalid JPEG image".to_owned());
+        }
+        let marker = data[i + 1];
+        match marker {
+            0xff => {
+                i += 1;
+                continue;
+            },
+            0x01 | 0xd0...0xd9 => {
+                i += 2;
+                continue;
+            },
+            _ => {},
+        }
+
+        let length = be16(&data, i + 2) as usize;
+        let is_start_of_frame = marker >= 0xc0 && marker <= 0xcf && marker != 0xc4 && marker != 0xc8 &&
+                                marker != 0xcc;
+        if is_start_of_frame && i + 10 <= data.len() {
+            let color_space = match data[i + 9] {
+                1 => ColorSpace::Gray,
+                3 => ColorSpace::Rgb,
+                4 => ColorSpace::Cmyk,
+                _ => return Err("unsupported JPEG color space".to_owned()),
+            };
+
+            return Ok(Image {
+                width: be16(&data, i + 7),
+                height: be16(&data, i + 5),
+                color_space: color_space,
+                encoding: Encoding::Jpeg,
+                data: data,
+                smask: None,
+            });
+        }
+
+        i += 2 + length;
+    }
+
+    Err("invalid JPEG image".to_owned())
+}
+
+fn parse_png(data: Vec<u8>) -> Result<Image, String> {
+    let mut i = 8;
+    let mut header = None;
+    let mut palette = None;
+    let mut idat = vec![];
+
+    while i + 8 <= data.len() {
+        let length = be32(&data, i) as usize;
+        let kind = &data[i + 4..i + 8];
+        let start = i + 8;
+        if start + length > data.len() {
+            return Err("invalid PNG image".to_owned());
+        }
+        let chunk = &data[start..start + length];
+
+        match kind {
+            b"IHDR" if length >= 13 => {
+                header = Some((be32(chunk, 0), be32(chunk, 4), chunk[8], chunk[9], chunk[12]));
+            },
+            b"PLTE" => palette = Some(chunk.to_vec()),
+            b"IDAT" => idat.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {},
+        }
+
+        // Length, type, data and crc
+        i = start + length + 4;
+    }
+
+    let (width, height, depth, color_type, interlace) = match header {
+        Some(header) => header,
+        None => return Err("invalid PNG image".to_owned()),
+    };
+
+    if depth != 8 || interlace != 0 {
+        return Err("only non interlaced PNG images with 8 bits per channel are supported".to_owned());
+    }
+
+    let color_space = match color_type {
+        0 | 4 => ColorSpace::Gray,
+        2 | 6 => ColorSpace::Rgb,
+        3 => {
+            match palette {
+                Some(palette) => ColorSpace::Indexed(palette),
+                None => return Err("invalid PNG image".to_owned()),
+            }
+        },
+        _ => return Err("invalid PNG image".to_owned()),
+    };
+
+    // Images with an alpha channel are decoded
+    if color_type == 4 || color_type == 6 {
+        let colors = color_space.components() as usize;
+        let (data, smask) = try!(split_alpha(&idat, width as usize, height as usize, colors));
+        return Ok(Image {
+            width: width,
+            height: height,
+            color_space: color_space,
+            encoding: Encoding::Deflate,
+            data: data,
+            smask: Some(smask),
+        });
+    }
+
+    Ok(Image {
+        width: width,
+        height: height,
+        color_space: color_space,
+        encoding: Encoding::Png,
+        data: idat,
+        smask: None,
+    })
+}
+
+/// Separates the colours of the pixels of a PNG image with an alpha channel from their alpha, and
+/// returns both deflated
+fn split_alpha(idat: &[u8], width: usize, height: usize, colors: usize) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut filtered = vec![];
+    if ZlibDecoder::new(idat).read_to_end(&mut filtered).is_err() {
+        return Err("invalid PNG image".to_owned());
+    }
+
+    let pixel = colors + 1;
+    let stride = width * pixel;
+    if filtered.len() < height * (stride + 1) {
+        return Err("invalid PNG image".to_owned());
+    }
+
+    let mut color = Vec::with_capacity(width * height * colors);
+    let mut alpha = Vec::with_capacity(width * height);
+    let mut previous = vec![0; stride];
+    for row in filtered.chunks(stride + 1).take(height) {
+        let line = try!(unfilter(row[0], &row[1..], &previous, pixel));
+        for p in line.chunks(pixel) {
+            color.extend_from_slice(&p[..colors]);
+            alpha.push(p[colors]);
+        }
+        previous = line;
+    }
+
+    Ok((deflate(&color), deflate(&alpha)))
+}
+
+/// Reverses the filter of a row of a PNG image, `previous` is the row above once unfiltered
+fn unfilter(filter: u8, row: &[u8], previous: &[u8], pixel: usize) -> Result<Vec<u8>, String> {
+    let mut line = row.to_vec();
+    for i in 0..line.len() {
+        let left = if i >= pixel { line[i - pixel] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= pixel { previous[i - pixel] } else { 0 };
+
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err("invalid PNG image".to_owned()),
+        };
+        line[i] = line[i].wrapping_add(predicted);
+    }
+    Ok(line)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    // Writing to a vector does not fail
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_parse_png() {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend_from_slice(&[0, 0, 0, 13]);
+    png.extend_from_slice(b"IHDR");
+    png.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+    png.extend_from_slice(&[0, 0, 0, 0]);
+    png.extend_from_slice(&[0, 0, 0, 2]);
+    png.extend_from_slice(b"IDAT");
+    png.extend_from_slice(&[0x78, 0x9c]);
+    png.extend_from_slice(&[0, 0, 0, 0]);
+
+    let image = parse_png(png).unwrap();
+    assert_eq!((image.width, image.height), (3, 2));
+    assert_eq!(image.color_space, ColorSpace::Rgb);
+    assert_eq!(image.data, vec![0x78, 0x9c]);
+}
+
+#[test]
+fn test_parse_jpeg() {
+    let jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc0, 0, 11, 8, 0, 20, 0, 30, 3, 1, 0x22, 0];
+    let image = parse_jpeg(jpeg).unwrap();
+    assert_eq!((image.width, image.height), (30, 20));
+    assert_eq!(image.color_space, ColorSpace::Rgb);
+}
+
+#[test]
+fn test_parse_png_with_alpha() {
+    // Two rows of two grey and alpha pixels, with the sub and up filters
+    let idat = deflate(&[1, 10, 255, 5, 0, 2, 1, 0, 1, 1]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend_from_slice(&[0, 0, 0, 13]);
+    png.extend_from_slice(b"IHDR");
+    png.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 2, 8, 4, 0, 0, 0]);
+    png.extend_from_slice(&[0, 0, 0, 0]);
+    png.extend_from_slice(&[0, 0, 0, idat.len() as u8]);
+    png.extend_from_slice(b"IDAT");
+    png.extend_from_slice(&idat);
+    png.extend_from_slice(&[0, 0, 0, 0]);
+
+    let image = parse_png(png).unwrap();
+    assert_eq!(image.color_space, ColorSpace::Gray);
+    assert_eq!(image.encoding, Encoding::Deflate);
+
+    let inflate = |data: &[u8]| {
+        let mut inflated = vec![];
+        ZlibDecoder::new(data).read_to_end(&mut inflated).unwrap();
+        inflated
+    };
+    assert_eq!(inflate(&image.data), vec![10, 15, 11, 16]);
+    assert_eq!(inflate(&image.smask.unwrap()), vec![255, 255, 255, 0]);
+}
@@ -0,0 +1,585 @@
+//! Lays out blocks of text, code, images and tables on A4 pages and produces the content streams
+//! of the pages.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use super::fonts::Font;
+use super::highlight::Color;
+use super::image::Image;
+
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+pub const MARGIN_X: f32 = 64.0;
+pub const MARGIN_TOP: f32 = 72.0;
+pub const MARGIN_BOTTOM: f32 = 72.0;
+pub const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN_X;
+
+/// Pixels of images are shown at 96 dpi
+const POINTS_PER_PIXEL: f32 = 0.75;
+
+const CODE_SIZE: f32 = 9.0;
+const CODE_LINE_HEIGHT: f32 = 12.0;
+const CODE_PADDING: f32 = 6.0;
+
+/// A position in the document, `y` is measured from the bottom of the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Destination {
+    pub page: usize,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// A web page or anything else outside of the book
+    Uri(String),
+    /// The html file of a chapter, relative to the source directory
+    Chapter(PathBuf),
+    Position(Destination),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub rect: [f32; 4],
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub content: String,
+    pub links: Vec<Link>,
+    /// The indices of the images shown on the page
+    pub images: Vec<usize>,
+    /// The name of the chapter shown in the header
+    pub header: Option<String>,
+    /// The characters shown on the page in each font, only their glyphs are embedded
+    pub chars: BTreeSet<(Font, char)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub font: Font,
+    pub size: f32,
+    pub color: Color,
+}
+
+impl Style {
+    pub fn width(&self, text: &str) -> f32 {
+        self.font.text_width(text, self.size)
+    }
+}
+
+/// A piece of text with the same style, `\n` forces a line break
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<Target>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    x: f32,
+    width: f32,
+    text: String,
+    style: Style,
+    link: Option<Target>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    runs: Vec<Run>,
+    width: f32,
+    size: f32,
+}
+
+impl Line {
+    fn new(size: f32) -> Self {
+        Line {
+            runs: vec![],
+            width: 0.0,
+            size: size,
+        }
+    }
+
+    fn push(&mut self, text: &str, style: &Style, link: &Option<Target>) {
+        let width = style.width(text);
+        if style.size > self.size || self.runs.is_empty() {
+            self.size = style.size;
+        }
+
+        if let Some(last) = self.runs.last_mut() {
+            if last.style == *style && last.link == *link {
+                last.text.push_str(text);
+                last.width += width;
+                self.width += width;
+                return;
+            }
+        }
+
+        self.runs.push(Run {
+            x: self.width,
+            width: width,
+            text: text.to_owned(),
+            style: *style,
+            link: link.clone(),
+        });
+        self.width += width;
+    }
+
+    fn height(&self) -> f32 {
+        self.size * 1.4
+    }
+}
+
+/// Splits text into words, each followed by the spaces after it
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut in_space = false;
+
+    for (i, c) in text.char_indices() {
+        if c == ' ' {
+            in_space = true;
+        } else if in_space {
+            words.push(&text[start..i]);
+            start = i;
+            in_space = false;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Breaks the spans into lines no wider than `width`
+fn wrap(spans: &[Span], width: f32) -> Vec<Line> {
+    let size = spans.first().map(|s| s.style.size).unwrap_or(11.0);
+    let mut lines = vec![Line::new(size)];
+
+    for span in spans {
+        for (i, part) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Line::new(span.style.size));
+            }
+
+            for word in split_words(part) {
+                let needed = span.style.width(word.trim_right_matches(' '));
+                if lines.last().map(|l| !l.runs.is_empty() && l.width + needed > width).unwrap_or(false) {
+                    lines.push(Line::new(span.style.size));
+                }
+
+                let mut word = word;
+                if lines.last().map(|l| l.runs.is_empty()).unwrap_or(false) {
+                    word = word.trim_left_matches(' ');
+                }
+
+                // Words longer than a line are broken anywhere
+                while span.style.width(word.trim_right_matches(' ')) > width {
+                    let mut end = 0;
+                    let mut used = 0.0;
+                    for (i, c) in word.char_indices() {
+                        let w = span.style.font.char_width(c) * span.style.size / 1000.0;
+                        if used + w > width && end > 0 {
+                            break;
+                        }
+                        used += w;
+                        end = i + c.len_utf8();
+                    }
+                    if let Some(line) = lines.last_mut() {
+                        line.push(&word[..end], &span.style, &span.link);
+                    }
+                    lines.push(Line::new(span.style.size));
+                    word = &word[end..];
+                }
+
+                if !word.is_empty() {
+                    if let Some(line) = lines.last_mut() {
+                        line.push(word, &span.style, &span.link);
+                    }
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+fn color_operator(color: Color, stroke: bool) -> String {
+    format!("{:.3} {:.3} {:.3} {}", color.0, color.1, color.2, if stroke { "RG" } else { "rg" })
+}
+
+/// Writes text on a page with its baseline at `y`, tabs are shown as spaces
+pub fn add_text(page: &mut Page, x: f32, y: f32, text: &str, style: &Style) {
+    let text = text.replace('\t', " ");
+    let glyphs: String = style.font.encode(&text).iter().map(|b| format!("{:02x}", b)).collect();
+    page.content.push_str(&format!("BT /{} {:.1} Tf {} {:.2} {:.2} Td <{}> Tj ET\n",
+                                   style.font.resource_name(),
+                                   style.size,
+                                   color_operator(style.color, false),
+                                   x,
+                                   y,
+                                   glyphs));
+    page.chars.extend(text.chars().map(|c| (style.font, c)));
+}
+
+pub struct Typesetter {
+    pub pages: Vec<Page>,
+    pub images: Vec<Image>,
+    image_ids: HashMap<PathBuf, usize>,
+    /// Distance of the text from the left margin, for lists and quotes
+    pub indent: f32,
+    header: Option<String>,
+    y: f32,
+}
+
+impl Typesetter {
+    pub fn new() -> Self {
+        Typesetter {
+            pages: vec![],
+            images: vec![],
+            image_ids: HashMap::new(),
+            indent: 0.0,
+            header: None,
+            y: 0.0,
+        }
+    }
+
+    /// Sets the text of the header of the following pages
+    pub fn set_header(&mut self, header: Option<String>) {
+        self.header = header;
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(Page {
+            header: self.header.clone(),
+            ..Page::default()
+        });
+        self.y = PAGE_HEIGHT - MARGIN_TOP;
+    }
+
+    /// The current position, the top of the next block
+    pub fn position(&mut self) -> Destination {
+        if self.pages.is_empty() {
+            self.new_page();
+        }
+        Destination {
+            page: self.pages.len() - 1,
+            y: self.y,
+        }
+    }
+
+    /// Starts a new page when less than `height` is left on the current one
+    fn ensure(&mut self, height: f32) {
+        if self.pages.is_empty() || (self.y - height < MARGIN_BOTTOM && self.y < PAGE_HEIGHT - MARGIN_TOP) {
+            self.new_page();
+        }
+    }
+
+    /// Vertical space between blocks, it is not carried over to the next page
+    pub fn space(&mut self, height: f32) {
+        if self.y - height < MARGIN_BOTTOM {
+            self.y = MARGIN_BOTTOM;
+        } else {
+            self.y -= height;
+        }
+    }
+
+    /// Moves down to the top of the remaining space of a page, used to vertically place a title
+    pub fn skip_to(&mut self, y: f32) {
+        if y < self.y {
+            self.y = y;
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        if self.pages.is_empty() {
+            self.new_page();
+        }
+        self.pages.last_mut().unwrap()
+    }
+
+    fn emit_line(&mut self, line: &Line, x: f32, baseline: f32) {
+        for run in &line.runs {
+            if run.text.trim().is_empty() {
+                continue;
+            }
+            add_text(self.page(), x + run.x, baseline, &run.text, &run.style);
+
+            if let Some(ref target) = run.link {
+                let rect = [x + run.x, baseline - run.style.size * 0.25, x + run.x + run.width, baseline + run.style.size];
+                self.page().links.push(Link {
+                    rect: rect,
+                    target: target.clone(),
+                });
+            }
+        }
+    }
+
+    /// Lays out a paragraph, the lines are broken to fit between the indent and the right margin.
+    /// The `marker` of a list item is placed left of the first line.
+    pub fn paragraph(&mut self, spans: &[Span], marker: Option<&Span>, centered: bool) {
+        let x = MARGIN_X + self.indent;
+        let lines = wrap(spans, CONTENT_WIDTH - self.indent);
+
+        for (i, line) in lines.iter().enumerate() {
+            let height = line.height();
+            self.ensure(height);
+            let baseline = self.y - line.size * 1.05;
+
+            if i == 0 {
+                if let Some(marker) = marker {
+                    let marker_x = x - marker.style.width(&marker.text) - 6.0;
+                    add_text(self.page(), marker_x, baseline, &marker.text, &marker.style);
+                }
+            }
+
+            let line_x = if centered { MARGIN_X + (CONTENT_WIDTH - line.width) / 2.0 } else { x };
+            self.emit_line(line, line_x, baseline);
+            self.y -= height;
+        }
+    }
+
+    /// A line of a table of contents: the text, shortened to fit, and the page number aligned to
+    /// the right margin. The whole line links to the target of the text.
+    pub fn toc_line(&mut self, text: &Span, number: &str) {
+        let x = MARGIN_X + self.indent;
+        let style = text.style;
+        let number_width = style.width(number);
+        let available = CONTENT_WIDTH - self.indent - number_width - 12.0;
+
+        let mut label = text.text.clone();
+        if style.width(&label) > available {
+            while !label.is_empty() && style.width(&label) + style.width("...") > available {
+                label.pop();
+            }
+            label.push_str("...");
+        }
+
+        let height = style.size * 1.6;
+        self.ensure(height);
+        let baseline = self.y - style.size * 1.05;
+        add_text(self.page(), x, baseline, &label, &style);
+        if !number.is_empty() {
+            add_text(self.page(), MARGIN_X + CONTENT_WIDTH - number_width, baseline, number, &style);
+        }
+
+        if let Some(ref target) = text.link {
+            let rect = [x, baseline - style.size * 0.25, MARGIN_X + CONTENT_WIDTH, baseline + style.size];
+            self.page().links.push(Link {
+                rect: rect,
+                target: target.clone(),
+            });
+        }
+        self.y -= height;
+    }
+
+    /// Lays out a code block on a grey background, the lines are the pieces of text of each line
+    /// with their color. Lines too long for the page are broken.
+    pub fn code_block(&mut self, lines: &[Vec<(String, Color)>]) {
+        let x = MARGIN_X + self.indent;
+        let width = CONTENT_WIDTH - self.indent;
+        let max_chars = ((width - 2.0 * CODE_PADDING) / Font::Mono.text_width(" ", CODE_SIZE)) as usize;
+
+        // Break the lines that are too long
+        let mut visual_lines = vec![];
+        for line in lines {
+            let mut current: Vec<(String, Color)> = vec![];
+            let mut length = 0;
+            for &(ref text, color) in line {
+                let mut text = &text[..];
+                while length + text.chars().count() > max_chars {
+                    let split = text.char_indices().nth(max_chars - length).map(|(i, _)| i).unwrap_or(text.len());
+                    current.push((text[..split].to_owned(), color));
+                    visual_lines.push(current);
+                    current = vec![];
+                    length = 0;
+                    text = &text[split..];
+                }
+                length += text.chars().count();
+                current.push((text.to_owned(), color));
+            }
+            visual_lines.push(current);
+        }
+
+        let background = (0.95, 0.95, 0.95);
+        let count = visual_lines.len();
+        for (i, line) in visual_lines.into_iter().enumerate() {
+            let top_padding = if i == 0 { CODE_PADDING } else { 0.0 };
+            let bottom_padding = if i + 1 == count { CODE_PADDING } else { 0.0 };
+            let height = top_padding + CODE_LINE_HEIGHT + bottom_padding;
+            self.ensure(height);
+
+            let y = self.y;
+            self.page().content.push_str(&format!("{} {:.2} {:.2} {:.2} {:.2} re f\n",
+                                                  color_operator(background, false),
+                                                  x,
+                                                  y - height,
+                                                  width,
+                                                  height));
+
+            let baseline = y - top_padding - CODE_SIZE;
+            let mut offset = 0.0;
+            for (text, color) in line {
+                let style = Style {
+                    font: Font::Mono,
+                    size: CODE_SIZE,
+                    color: color,
+                };
+                if !text.trim().is_empty() {
+                    add_text(self.page(), x + CODE_PADDING + offset, baseline, &text, &style);
+                }
+                offset += style.width(&text);
+            }
+
+            self.y -= height;
+        }
+    }
+
+    /// A horizontal line
+    pub fn rule(&mut self) {
+        self.ensure(12.0);
+        let y = self.y - 6.0;
+        let (x, end) = (MARGIN_X + self.indent, MARGIN_X + CONTENT_WIDTH);
+        self.page().content.push_str(&format!("{} 0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                                              color_operator((0.8, 0.8, 0.8), true),
+                                              x,
+                                              y,
+                                              end,
+                                              y));
+        self.y -= 12.0;
+    }
+
+    /// Shows an image, scaled down to fit on the page. The same file is only embedded once.
+    pub fn image(&mut self, path: PathBuf, image: Image) {
+        let (width, height) = (image.width as f32 * POINTS_PER_PIXEL, image.height as f32 * POINTS_PER_PIXEL);
+        if width <= 0.0 || height <= 0.0 {
+            return;
+        }
+
+        let id = match self.image_ids.get(&path) {
+            Some(&id) => id,
+            None => {
+                self.images.push(image);
+                self.images.len() - 1
+            },
+        };
+        self.image_ids.insert(path, id);
+
+        let max_width = CONTENT_WIDTH - self.indent;
+        let max_height = PAGE_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+        let scale = (max_width / width).min(max_height / height).min(1.0);
+        let (width, height) = (width * scale, height * scale);
+
+        self.ensure(height);
+        let (x, y) = (MARGIN_X + self.indent, self.y - height);
+        let page = self.page();
+        page.content.push_str(&format!("q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n", width, height, x, y, id + 1));
+        if !page.images.contains(&id) {
+            page.images.push(id);
+        }
+        self.y -= height;
+    }
+
+    /// Lays out a table with columns of equal width, every cell is a paragraph. The first
+    /// `header_rows` rows are followed by a darker line.
+    pub fn table(&mut self, rows: &[Vec<Vec<Span>>], header_rows: usize) {
+        let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        let x = MARGIN_X + self.indent;
+        let width = CONTENT_WIDTH - self.indent;
+        let column_width = width / columns as f32;
+        let padding = 4.0;
+
+        for (r, row) in rows.iter().enumerate() {
+            let cells: Vec<Vec<Line>> = row.iter().map(|cell| wrap(cell, column_width - 2.0 * padding)).collect();
+            let height = cells.iter()
+                .map(|lines| lines.iter().map(|l| l.height()).sum::<f32>())
+                .fold(0.0, f32::max) + 2.0 * padding;
+
+            self.ensure(height);
+            let top = self.y;
+
+            for (c, lines) in cells.iter().enumerate() {
+                let mut y = top - padding;
+                for line in lines {
+                    self.emit_line(line, x + c as f32 * column_width + padding, y - line.size * 1.05);
+                    y -= line.height();
+                }
+            }
+
+            let gray = if r + 1 == header_rows { 0.5 } else { 0.85 };
+            self.page().content.push_str(&format!("{} 0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                                                  color_operator((gray, gray, gray), true),
+                                                  x,
+                                                  top - height,
+                                                  x + width,
+                                                  top - height));
+            self.y -= height;
+        }
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[cfg(test)]
+fn span(text: &str) -> Span {
+    Span {
+        text: text.to_owned(),
+        style: Style {
+            font: Font::Mono,
+            size: 10.0,
+            color: (0.0, 0.0, 0.0),
+        },
+        link: None,
+    }
+}
+
+#[test]
+fn test_split_words() {
+    assert_eq!(split_words("a  bc d"), vec!["a  ", "bc ", "d"]);
+    assert_eq!(split_words(" a"), vec![" ", "a"]);
+}
+
+#[test]
+fn test_wrap() {
+    // Every character of the monospace font is a little more than 6 points wide in size 10
+    let lines = wrap(&[span("aaa bbb cc"), span(" dddddddddddd\nx")], 45.0);
+    let texts: Vec<String> = lines.iter()
+        .map(|l| l.runs.iter().map(|r| &r.text[..]).collect::<Vec<_>>().concat())
+        .collect();
+
+    assert_eq!(texts, vec!["aaa bbb ", "cc ", "ddddddd", "ddddd", "x"]);
+    assert!(lines.iter().all(|l| l.width <= 48.5));
+}
+
+#[test]
+fn test_paragraph_breaks_pages() {
+    let mut typesetter = Typesetter::new();
+    let text = vec!["word"; 2000].join(" ");
+    typesetter.paragraph(&[span(&text)], None, false);
+
+    assert!(typesetter.pages.len() > 1);
+    assert!(typesetter.pages.iter().all(|p| p.content.starts_with("BT /F5 10.0 Tf")));
+    assert!(typesetter.pages.iter().all(|p| p.chars.contains(&(Font::Mono, 'w'))));
+}
+
+#[test]
+fn test_add_text() {
+    let mut page = Page::default();
+    let style = span("").style;
+    // The last letter is a Cyrillic a
+    add_text(&mut page, 1.0, 2.0, "a\tа", &style);
+
+    let glyphs = |c| format!("{:04x}", Font::Mono.glyph(c).unwrap());
+    let expected = format!("<{}{}{}> Tj ET\n", glyphs('a'), glyphs(' '), glyphs('а'));
+    assert!(page.content.ends_with(&expected));
+    assert_eq!(page.chars.len(), 3);
+}
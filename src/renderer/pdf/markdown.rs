@@ -0,0 +1,388 @@
+//! Walks the markdown of a chapter and hands its blocks to the typesetter.
+
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Parser, Event, Tag, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+
+use renderer::html_handlebars::links;
+use utils;
+use super::fonts::Font;
+use super::highlight::{Color, Highlighter};
+use super::image;
+use super::layout::{Typesetter, Span, Style, Target};
+
+pub const BODY_SIZE: f32 = 11.0;
+pub const TEXT_COLOR: Color = (0.2, 0.2, 0.2);
+const LINK_COLOR: Color = (0.15, 0.4, 0.75);
+const CODE_COLOR: Color = (0.6, 0.15, 0.15);
+const QUOTE_COLOR: Color = (0.45, 0.45, 0.45);
+
+const LIST_INDENT: f32 = 18.0;
+const QUOTE_INDENT: f32 = 20.0;
+
+pub fn heading_size(level: i32) -> f32 {
+    match level {
+        1 => 22.0,
+        2 => 17.0,
+        3 => 14.0,
+        4 => 12.0,
+        _ => BODY_SIZE,
+    }
+}
+
+/// Typesets the markdown of a chapter, images are read relative to `src`, the source directory
+pub fn typeset(typesetter: &mut Typesetter, markdown: &str, src: &Path) {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let mut state = State {
+        typesetter: typesetter,
+        src: src.to_owned(),
+        spans: vec![],
+        bold: 0,
+        italic: 0,
+        code: false,
+        links: vec![],
+        heading: None,
+        code_block: None,
+        image: None,
+        lists: vec![],
+        marker: None,
+        quotes: 0,
+        table: None,
+        table_head: false,
+        footnote: false,
+    };
+
+    for event in Parser::new_ext(markdown, opts) {
+        state.event(event);
+    }
+    state.flush(0.0);
+}
+
+/// Turns the destination of a link into the target of a link annotation. Links to markdown or
+/// html files of the book point to the chapter, links to other files of the book have no target.
+fn link_target(dest: &str) -> Option<Target> {
+    if !utils::is_relative_link(dest) {
+        return Some(Target::Uri(dest.to_owned()));
+    }
+
+    let dest = utils::md_link_to_html(dest).unwrap_or(dest.to_owned());
+    let path = &dest[..dest.find(|c| c == '#' || c == '?').unwrap_or(dest.len())];
+    if !path.ends_with(".html") {
+        return None;
+    }
+    links::normalize(Path::new(path)).map(Target::Chapter)
+}
+
+struct State<'t> {
+    typesetter: &'t mut Typesetter,
+    src: PathBuf,
+
+    /// The text of the current paragraph, heading or table cell
+    spans: Vec<Span>,
+    bold: usize,
+    italic: usize,
+    code: bool,
+    links: Vec<Option<Target>>,
+    heading: Option<i32>,
+
+    /// The info string and content of the current code block
+    code_block: Option<(String, String)>,
+    /// The destination and alternative text of the current image
+    image: Option<(String, String)>,
+
+    /// The next number of the ordered lists, `None` for bullet lists
+    lists: Vec<Option<usize>>,
+    /// The marker of the current list item, until its first paragraph is laid out
+    marker: Option<String>,
+    quotes: usize,
+
+    /// The rows of the current table, each row has the spans of its cells
+    table: Option<Vec<Vec<Vec<Span>>>>,
+    table_head: bool,
+    footnote: bool,
+}
+
+impl<'t> State<'t> {
+    fn style(&self) -> Style {
+        let mut font = if self.code { Font::Mono } else { Font::Regular };
+        if self.bold > 0 || self.heading.is_some() || self.table_head {
+            font = font.bold();
+        }
+        if self.italic > 0 {
+            font = font.italic();
+        }
+
+        let size = match self.heading {
+            Some(level) => heading_size(level),
+            None if self.footnote => 9.0,
+            None if self.code => BODY_SIZE - 1.0,
+            None => BODY_SIZE,
+        };
+
+        let color = if self.links.iter().any(|l| l.is_some()) {
+            LINK_COLOR
+        } else if self.code {
+            CODE_COLOR
+        } else if self.quotes > 0 {
+            QUOTE_COLOR
+        } else {
+            TEXT_COLOR
+        };
+
+        Style {
+            font: font,
+            size: size,
+            color: color,
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let span = Span {
+            text: text.to_owned(),
+            style: self.style(),
+            link: self.links.iter().rev().filter_map(|l| l.clone()).next(),
+        };
+        self.spans.push(span);
+    }
+
+    /// Lays out the pending text as a paragraph followed by `space`
+    fn flush(&mut self, space: f32) {
+        let spans: Vec<Span> = self.spans.drain(..).collect();
+        let empty = spans.iter().all(|s| s.text.trim().is_empty());
+        if empty && self.marker.is_none() {
+            return;
+        }
+
+        let marker = self.marker.take().map(|text| {
+            Span {
+                text: text,
+                style: Style {
+                    font: Font::Regular,
+                    size: BODY_SIZE,
+                    color: TEXT_COLOR,
+                },
+                link: None,
+            }
+        });
+
+        self.typesetter.paragraph(&spans, marker.as_ref(), false);
+        self.typesetter.space(space);
+    }
+
+    fn paragraph_space(&self) -> f32 {
+        if self.lists.is_empty() { 8.0 } else { 3.0 }
+    }
+
+    fn event(&mut self, event: Event) {
+        // The content of code blocks and the alternative text of images is collected as it is
+        if let Some((_, ref mut code)) = self.code_block {
+            match event {
+                Event::Text(ref text) => {
+                    code.push_str(text);
+                    return;
+                },
+                Event::End(Tag::CodeBlock(_)) => {},
+                _ => return,
+            }
+        }
+        if let Some((_, ref mut alt)) = self.image {
+            match event {
+                Event::Text(ref text) => {
+                    alt.push_str(text);
+                    return;
+                },
+                Event::End(Tag::Image(..)) => {},
+                _ => return,
+            }
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) => {},
+            Event::End(Tag::Paragraph) => {
+                let space = self.paragraph_space();
+                self.flush(space);
+            },
+
+            Event::Start(Tag::Header(level)) => {
+                self.flush(0.0);
+                self.typesetter.space(if level <= 2 { 12.0 } else { 8.0 });
+                self.heading = Some(level);
+            },
+            Event::End(Tag::Header(_)) => {
+                self.flush(6.0);
+                self.heading = None;
+            },
+
+            Event::Start(Tag::BlockQuote) => {
+                self.flush(0.0);
+                self.quotes += 1;
+                self.typesetter.indent += QUOTE_INDENT;
+            },
+            Event::End(Tag::BlockQuote) => {
+                self.flush(0.0);
+                self.quotes -= 1;
+                self.typesetter.indent -= QUOTE_INDENT;
+            },
+
+            Event::Start(Tag::CodeBlock(info)) => {
+                self.flush(0.0);
+                self.code_block = Some((info.into_owned(), String::new()));
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((info, code)) = self.code_block.take() {
+                    self.typeset_code_block(&info, &code);
+                }
+            },
+
+            Event::Start(Tag::List(start)) => {
+                self.flush(0.0);
+                self.lists.push(start);
+            },
+            Event::End(Tag::List(_)) => {
+                self.flush(0.0);
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.typesetter.space(6.0);
+                }
+            },
+            Event::Start(Tag::Item) => {
+                self.flush(0.0);
+                self.marker = match self.lists.last_mut() {
+                    Some(&mut Some(ref mut number)) => {
+                        *number += 1;
+                        Some(format!("{}.", *number - 1))
+                    },
+                    _ => Some("\u{2022}".to_owned()),
+                };
+                self.typesetter.indent += LIST_INDENT;
+            },
+            Event::End(Tag::Item) => {
+                self.flush(3.0);
+                self.typesetter.indent -= LIST_INDENT;
+            },
+
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                self.flush(0.0);
+                self.footnote = true;
+                self.push_text(&format!("[{}] ", name));
+            },
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                self.flush(4.0);
+                self.footnote = false;
+            },
+            Event::FootnoteReference(name) => self.push_text(&format!("[{}]", name)),
+
+            Event::Start(Tag::Table(_)) => {
+                self.flush(0.0);
+                self.table = Some(vec![]);
+            },
+            Event::End(Tag::Table(_)) => {
+                if let Some(rows) = self.table.take() {
+                    let header_rows = if rows.is_empty() { 0 } else { 1 };
+                    self.typesetter.table(&rows, header_rows);
+                }
+                self.typesetter.space(8.0);
+            },
+            Event::Start(Tag::TableHead) => {
+                self.table_head = true;
+                self.table_row();
+            },
+            Event::End(Tag::TableHead) => self.table_head = false,
+            Event::Start(Tag::TableRow) => self.table_row(),
+            Event::Start(Tag::TableCell) => self.spans.clear(),
+            Event::End(Tag::TableCell) => {
+                let cell: Vec<Span> = self.spans.drain(..).collect();
+                if let Some(row) = self.table.as_mut().and_then(|rows| rows.last_mut()) {
+                    row.push(cell);
+                }
+            },
+
+            Event::Start(Tag::Emphasis) => self.italic += 1,
+            Event::End(Tag::Emphasis) => self.italic -= 1,
+            Event::Start(Tag::Strong) => self.bold += 1,
+            Event::End(Tag::Strong) => self.bold -= 1,
+            Event::Start(Tag::Code) => self.code = true,
+            Event::End(Tag::Code) => self.code = false,
+
+            Event::Start(Tag::Link(dest, _)) => self.links.push(link_target(&dest)),
+            Event::End(Tag::Link(..)) => {
+                self.links.pop();
+            },
+
+            Event::Start(Tag::Image(dest, _)) => {
+                self.flush(0.0);
+                self.image = Some((dest.into_owned(), String::new()));
+            },
+            Event::End(Tag::Image(..)) => {
+                if let Some((dest, alt)) = self.image.take() {
+                    self.typeset_image(&dest, &alt);
+                }
+            },
+
+            Event::Start(Tag::Rule) => {
+                self.flush(0.0);
+                self.typesetter.rule();
+            },
+
+            Event::Text(text) => self.push_text(&text),
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+
+            // Raw html can not be shown in the PDF
+            Event::Html(_) |
+            Event::InlineHtml(_) => {},
+
+            _ => {},
+        }
+    }
+
+    fn table_row(&mut self) {
+        if let Some(ref mut rows) = self.table {
+            rows.push(vec![]);
+        }
+    }
+
+    fn typeset_code_block(&mut self, info: &str, code: &str) {
+        let lang = info.split(',').next().unwrap_or("").trim();
+        let mut highlighter = Highlighter::new(lang);
+
+        let lines: Vec<_> = code.trim_right_matches('\n')
+            .lines()
+            // Like rustdoc, lines starting with `# ` are hidden in Rust code
+            .filter(|line| !(lang == "rust" && (line.starts_with("# ") || line.trim() == "#")))
+            .map(|line| highlighter.line(line))
+            .collect();
+
+        self.typesetter.code_block(&lines);
+        self.typesetter.space(8.0);
+    }
+
+    fn typeset_image(&mut self, dest: &str, alt: &str) {
+        let loaded = if utils::is_relative_link(dest) {
+            match links::normalize(Path::new(dest)) {
+                Some(path) => image::load(&self.src.join(&path)).map(|image| (path, image)),
+                None => Err(format!("{} is outside of the book", dest)),
+            }
+        } else {
+            Err(format!("{} is not a local file", dest))
+        };
+
+        match loaded {
+            Ok((path, image)) => {
+                self.typesetter.image(path, image);
+                self.typesetter.space(8.0);
+            },
+            Err(e) => {
+                warn!("[-] Image not included in the PDF: {}", e);
+                self.italic += 1;
+                self.push_text(&format!("[{}]", if alt.is_empty() { dest } else { alt }));
+                self.italic -= 1;
+                let space = self.paragraph_space();
+                self.flush(space);
+            },
+        }
+    }
+}
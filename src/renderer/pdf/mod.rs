@@ -0,0 +1,525 @@
+//! Renders the book to a single PDF file, `book.pdf` in the destination directory.
+//!
+//! The PDF is written directly with [pdf-writer](https://docs.rs/pdf-writer), without a browser:
+//! it starts with a title page and a table of contents with page numbers, every chapter starts on
+//! a new page and the chapters are listed in the bookmarks of the document. The glyphs the book
+//! uses are embedded from the DejaVu fonts, with a map back to the text so that it can be searched
+//! and copied, and JPEG and PNG images are embedded as they are.
+
+extern crate pdf_writer;
+
+mod fonts;
+mod highlight;
+mod image;
+mod layout;
+mod markdown;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use utils;
+
+use self::fonts::Font;
+use self::image::{ColorSpace, Encoding};
+use self::layout::{Destination, Link, Page, Span, Style, Target, Typesetter};
+use self::pdf_writer::{Filter, Name, Rect, Ref, Str, TextStr};
+use self::pdf_writer::types::{ActionType, AnnotationType, CidFontType, FontFlags, SystemInfo, UnicodeCmap};
+
+pub struct Pdf;
+
+impl Pdf {
+    pub fn new() -> Self {
+        Pdf
+    }
+}
+
+impl Renderer for Pdf {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (pdf)");
+
+        // The chapters are laid out first, the page numbers are needed for the table of contents
+        let mut content = Typesetter::new();
+        let mut chapters = HashMap::new();
+
+        for item in book.iter() {
+            match *item {
+                BookItem::Chapter(_, ref ch) |
+                BookItem::Affix(ref ch) if ch.path != PathBuf::new() => {
+                    let markdown = try!(super::read_chapter(book, &ch.path));
+
+                    content.set_header(Some(ch.name.clone()));
+                    content.new_page();
+                    chapters.insert(ch.path.with_extension("html"), content.position());
+
+                    markdown::typeset(&mut content, &markdown, book.get_src());
+                },
+                _ => {},
+            }
+        }
+
+        let entries = toc_entries(&book.content, 0);
+
+        // The table of contents has one line per chapter, whatever the page numbers are
+        let toc_pages = typeset_toc(&entries, &HashMap::new(), 0).pages.len();
+        let front_pages = 1 + toc_pages;
+
+        let mut pages = typeset_title(book).pages;
+        pages.extend(typeset_toc(&entries, &chapters, front_pages).pages);
+
+        for mut page in content.pages {
+            page.links = page.links
+                .into_iter()
+                .filter_map(|link| resolve_link(link, &chapters, front_pages))
+                .collect();
+            pages.push(page);
+        }
+
+        add_headers_and_footers(&mut pages, book.get_title());
+
+        let outline = outline(&book.content, &chapters, front_pages);
+        let pdf = write_pdf(book, &pages, &content.images, &outline);
+
+        let path = book.get_dest().join("book.pdf");
+        let mut file = try!(utils::fs::create_file(&path));
+        try!(file.write_all(&pdf));
+        info!("[*] Creating {:?} ✓", path);
+
+        Ok(())
+    }
+}
+
+/// A line of the table of contents
+struct TocEntry {
+    depth: usize,
+    title: String,
+    /// The html file of the chapter, `None` for chapters without a file
+    page: Option<PathBuf>,
+}
+
+fn toc_entries(items: &[BookItem], depth: usize) -> Vec<TocEntry> {
+    let mut entries = vec![];
+    for item in items {
+        let (title, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (format!("{} {}", s, ch.name), ch),
+            BookItem::Affix(ref ch) => (ch.name.clone(), ch),
+            BookItem::Spacer => continue,
+        };
+
+        entries.push(TocEntry {
+            depth: depth,
+            title: title,
+            page: if ch.path != PathBuf::new() { Some(ch.path.with_extension("html")) } else { None },
+        });
+        entries.extend(toc_entries(&ch.sub_items, depth + 1));
+    }
+    entries
+}
+
+fn typeset_title(book: &MDBook) -> Typesetter {
+    let mut typesetter = Typesetter::new();
+    typesetter.new_page();
+    typesetter.skip_to(layout::PAGE_HEIGHT * 0.62);
+
+    let span = |text: &str, font: Font, size: f32| {
+        Span {
+            text: text.to_owned(),
+            style: Style {
+                font: font,
+                size: size,
+                color: markdown::TEXT_COLOR,
+            },
+            link: None,
+        }
+    };
+
+    typesetter.paragraph(&[span(book.get_title(), Font::Bold, 28.0)], None, true);
+    typesetter.space(16.0);
+    if !book.get_description().is_empty() {
+        typesetter.paragraph(&[span(book.get_description(), Font::Regular, 13.0)], None, true);
+        typesetter.space(24.0);
+    }
+    if !book.get_author().is_empty() {
+        typesetter.paragraph(&[span(book.get_author(), Font::Italic, 12.0)], None, true);
+    }
+
+    typesetter
+}
+
+/// The table of contents, the pages of the chapters are moved by `front_pages`, the pages before
+/// the first chapter
+fn typeset_toc(entries: &[TocEntry], chapters: &HashMap<PathBuf, Destination>, front_pages: usize) -> Typesetter {
+    let mut typesetter = Typesetter::new();
+    typesetter.new_page();
+
+    let style = Style {
+        font: Font::Bold,
+        size: markdown::heading_size(1),
+        color: markdown::TEXT_COLOR,
+    };
+    typesetter.paragraph(&[Span {
+                               text: "Table of Contents".to_owned(),
+                               style: style,
+                               link: None,
+                           }],
+                         None,
+                         false);
+    typesetter.space(12.0);
+
+    for entry in entries {
+        let destination = entry.page.as_ref().and_then(|page| chapters.get(page)).map(|d| {
+            Destination {
+                page: d.page + front_pages,
+                y: d.y,
+            }
+        });
+
+        typesetter.indent = entry.depth as f32 * 16.0;
+        let span = Span {
+            text: entry.title.clone(),
+            style: Style {
+                font: if entry.depth == 0 { Font::Bold } else { Font::Regular },
+                size: markdown::BODY_SIZE,
+                color: markdown::TEXT_COLOR,
+            },
+            link: destination.map(Target::Position),
+        };
+        let number = destination.map(|d| (d.page + 1).to_string()).unwrap_or(String::new());
+        typesetter.toc_line(&span, &number);
+    }
+
+    typesetter
+}
+
+/// Turns links to chapters into links to the page of the chapter, links to files of the book
+/// that are not chapters are dropped
+fn resolve_link(link: Link, chapters: &HashMap<PathBuf, Destination>, front_pages: usize) -> Option<Link> {
+    let target = match link.target {
+        Target::Chapter(ref path) => {
+            match chapters.get(path) {
+                Some(d) => {
+                    Target::Position(Destination {
+                        page: d.page + front_pages,
+                        y: d.y,
+                    })
+                },
+                None => return None,
+            }
+        },
+        ref target => target.clone(),
+    };
+
+    Some(Link {
+        rect: link.rect,
+        target: target,
+    })
+}
+
+/// Adds the title of the book and the name of the chapter at the top of the pages of the
+/// chapters, and the page number at the bottom of every page but the title page
+fn add_headers_and_footers(pages: &mut [Page], title: &str) {
+    let style = Style {
+        font: Font::Regular,
+        size: 9.0,
+        color: (0.5, 0.5, 0.5),
+    };
+
+    for (i, page) in pages.iter_mut().enumerate().skip(1) {
+        let y = layout::PAGE_HEIGHT - layout::MARGIN_TOP + 28.0;
+        if let Some(header) = page.header.clone() {
+            layout::add_text(page, layout::MARGIN_X, y, title, &style);
+            let x = layout::MARGIN_X + layout::CONTENT_WIDTH - style.width(&header);
+            layout::add_text(page, x, y, &header, &style);
+        }
+
+        let number = (i + 1).to_string();
+        let x = (layout::PAGE_WIDTH - style.width(&number)) / 2.0;
+        layout::add_text(page, x, layout::MARGIN_BOTTOM - 36.0, &number, &style);
+    }
+}
+
+/// An entry of the bookmarks of the document
+struct OutlineItem {
+    title: String,
+    destination: Destination,
+    children: Vec<OutlineItem>,
+}
+
+/// Builds the bookmarks from the chapters. Chapters without a file point to their first sub
+/// chapter, they are left out when none of their sub chapters has a file.
+fn outline(items: &[BookItem], chapters: &HashMap<PathBuf, Destination>, front_pages: usize) -> Vec<OutlineItem> {
+    let mut bookmarks = vec![];
+    for item in items {
+        let (title, ch) = match *item {
+            BookItem::Chapter(ref s, ref ch) => (format!("{} {}", s, ch.name), ch),
+            BookItem::Affix(ref ch) => (ch.name.clone(), ch),
+            BookItem::Spacer => continue,
+        };
+
+        let children = outline(&ch.sub_items, chapters, front_pages);
+        let destination = chapters.get(&ch.path.with_extension("html"))
+            .map(|d| {
+                Destination {
+                    page: d.page + front_pages,
+                    y: d.y,
+                }
+            })
+            .or(children.first().map(|c| c.destination));
+
+        if let Some(destination) = destination {
+            bookmarks.push(OutlineItem {
+                title: title,
+                destination: destination,
+                children: children,
+            });
+        }
+    }
+    bookmarks
+}
+
+fn write_annotation(mut annotation: pdf_writer::writers::Annotation, link: &Link, page_ids: &[Ref]) {
+    annotation.subtype(AnnotationType::Link)
+        .rect(Rect::new(link.rect[0], link.rect[1], link.rect[2], link.rect[3]))
+        .border(0.0, 0.0, 0.0, None);
+
+    match link.target {
+        Target::Uri(ref uri) => {
+            annotation.action().action_type(ActionType::Uri).uri(Str(uri.as_bytes()));
+        },
+        Target::Position(ref d) => {
+            annotation.action().action_type(ActionType::GoTo).destination().page(page_ids[d.page]).xyz(0.0, d.y, None);
+        },
+        Target::Chapter(_) => {},
+    }
+}
+
+fn write_outline(pdf: &mut pdf_writer::Pdf,
+                 alloc: &mut Ref,
+                 items: &[OutlineItem],
+                 parent: Ref,
+                 page_ids: &[Ref])
+                 -> (Ref, Ref) {
+    let ids: Vec<Ref> = items.iter().map(|_| alloc.bump()).collect();
+
+    for (i, item) in items.iter().enumerate() {
+        // Sub chapters are collapsed
+        let children = if item.children.is_empty() {
+            None
+        } else {
+            Some(write_outline(pdf, alloc, &item.children, ids[i], page_ids))
+        };
+
+        let mut entry = pdf.outline_item(ids[i]);
+        entry.title(TextStr(&item.title)).parent(parent);
+        entry.dest().page(page_ids[item.destination.page]).xyz(0.0, item.destination.y, None);
+        if i > 0 {
+            entry.prev(ids[i - 1]);
+        }
+        if i + 1 < ids.len() {
+            entry.next(ids[i + 1]);
+        }
+        if let Some((first, last)) = children {
+            entry.first(first).last(last).count(-(item.children.len() as i32));
+        }
+    }
+
+    (ids[0], ids[ids.len() - 1])
+}
+
+/// Embeds the glyphs of a font used by the book as a composite font: the text of the pages is
+/// made of glyph ids, and the `ToUnicode` map gives the characters back. `glyphs` maps the glyph
+/// ids to the characters they show.
+fn write_font(pdf: &mut pdf_writer::Pdf, alloc: &mut Ref, id: Ref, font: Font, glyphs: &BTreeMap<u16, char>) {
+    let (cid_font, descriptor, to_unicode, file) = (alloc.bump(), alloc.bump(), alloc.bump(), alloc.bump());
+
+    // The names of reduced fonts start with a tag of six letters
+    let name = format!("MDBOOK+{}", font.base_font());
+    let system_info = SystemInfo {
+        registry: Str(b"Adobe"),
+        ordering: Str(b"Identity"),
+        supplement: 0,
+    };
+
+    pdf.type0_font(id)
+        .base_font(Name(name.as_bytes()))
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_font)
+        .to_unicode(to_unicode);
+
+    // Glyph 0 is the empty box shown for the characters the font does not have
+    let mut ids = vec![0];
+    ids.extend(glyphs.keys().filter(|&&g| g != 0));
+
+    {
+        let mut cid = pdf.cid_font(cid_font);
+        cid.subtype(CidFontType::Type2)
+            .base_font(Name(name.as_bytes()))
+            .system_info(system_info)
+            .font_descriptor(descriptor)
+            .cid_to_gid_map_predefined(Name(b"Identity"));
+        let mut widths = cid.widths();
+        for &glyph in &ids {
+            widths.consecutive(glyph, Some(font.glyph_width(glyph)));
+        }
+    }
+
+    let metrics = font.metrics();
+    let mut flags = FontFlags::SYMBOLIC;
+    if font == Font::Mono {
+        flags |= FontFlags::FIXED_PITCH;
+    }
+    if font == Font::Italic || font == Font::BoldItalic {
+        flags |= FontFlags::ITALIC;
+    }
+    pdf.font_descriptor(descriptor)
+        .name(Name(name.as_bytes()))
+        .flags(flags)
+        .bbox(Rect::new(metrics.bbox[0], metrics.bbox[1], metrics.bbox[2], metrics.bbox[3]))
+        .italic_angle(metrics.italic_angle)
+        .ascent(metrics.ascent)
+        .descent(metrics.descent)
+        .cap_height(metrics.cap_height)
+        .stem_v(if font == Font::Bold || font == Font::BoldItalic { 120.0 } else { 80.0 })
+        .font_file2(file);
+
+    let mut cmap = UnicodeCmap::new(Name(b"Custom"), system_info);
+    for (&glyph, &c) in glyphs.iter().filter(|&(&g, _)| g != 0) {
+        cmap.pair(glyph, c);
+    }
+    pdf.cmap(to_unicode, &cmap.finish());
+
+    let data = image::deflate(&font.subset(&ids));
+    pdf.stream(file, &data).filter(Filter::FlateDecode);
+}
+
+fn write_pdf(book: &MDBook, pages: &[Page], images: &[image::Image], outline: &[OutlineItem]) -> Vec<u8> {
+    let mut pdf = pdf_writer::Pdf::new();
+    let mut alloc = Ref::new(1);
+    let catalog = alloc.bump();
+    let pages_id = alloc.bump();
+
+    // The glyphs of every font used by the pages
+    let mut glyphs: BTreeMap<Font, BTreeMap<u16, char>> = BTreeMap::new();
+    let mut missing = BTreeSet::new();
+    for &(font, c) in pages.iter().flat_map(|page| &page.chars) {
+        let font_glyphs = glyphs.entry(font).or_default();
+        match font.glyph(c) {
+            Some(glyph) => {
+                font_glyphs.entry(glyph).or_insert(c);
+            },
+            None => {
+                missing.insert(c);
+            },
+        }
+    }
+    if !missing.is_empty() {
+        let chars: String = missing.into_iter().collect();
+        warn!("[-] The fonts of the PDF have no glyphs for the characters {}, they are shown as boxes", chars);
+    }
+
+    let mut font_ids = vec![];
+    for (&font, glyphs) in &glyphs {
+        let id = alloc.bump();
+        write_font(&mut pdf, &mut alloc, id, font, glyphs);
+        font_ids.push((font, id));
+    }
+
+    let image_ids: Vec<Ref> = images.iter()
+        .map(|image| {
+            let id = alloc.bump();
+            // The alpha channel is a separate grey image
+            let smask_id = image.smask.as_ref().map(|smask| {
+                let smask_id = alloc.bump();
+                let mut smask_image = pdf.image_xobject(smask_id, smask);
+                smask_image.filter(Filter::FlateDecode);
+                smask_image.width(image.width as i32)
+                    .height(image.height as i32)
+                    .bits_per_component(8)
+                    .color_space()
+                    .device_gray();
+                smask_id
+            });
+
+            let mut xobject = pdf.image_xobject(id, &image.data);
+            xobject.filter(match image.encoding {
+                Encoding::Jpeg => Filter::DctDecode,
+                Encoding::Deflate | Encoding::Png => Filter::FlateDecode,
+            });
+            xobject.width(image.width as i32).height(image.height as i32).bits_per_component(8);
+            match image.color_space {
+                ColorSpace::Gray => xobject.color_space().device_gray(),
+                ColorSpace::Rgb => xobject.color_space().device_rgb(),
+                ColorSpace::Cmyk => xobject.color_space().device_cmyk(),
+                ColorSpace::Indexed(ref palette) => {
+                    xobject.color_space().indexed(Name(b"DeviceRGB"), palette.len() as i32 / 3 - 1, palette)
+                },
+            }
+            if image.encoding == Encoding::Png {
+                xobject.insert(Name(b"DecodeParms"))
+                    .dict()
+                    .pair(Name(b"Predictor"), 15)
+                    .pair(Name(b"Colors"), image.color_space.components())
+                    .pair(Name(b"BitsPerComponent"), 8)
+                    .pair(Name(b"Columns"), image.width as i32);
+            }
+            if let Some(smask_id) = smask_id {
+                xobject.s_mask(smask_id);
+            }
+            id
+        })
+        .collect();
+
+    let page_ids: Vec<Ref> = pages.iter().map(|_| alloc.bump()).collect();
+    for (page, &id) in pages.iter().zip(&page_ids) {
+        let contents = alloc.bump();
+        pdf.stream(contents, page.content.as_bytes());
+
+        let mut pdf_page = pdf.page(id);
+        pdf_page.parent(pages_id)
+            .media_box(Rect::new(0.0, 0.0, layout::PAGE_WIDTH, layout::PAGE_HEIGHT))
+            .contents(contents);
+        {
+            let mut annotations = pdf_page.annotations();
+            for link in &page.links {
+                write_annotation(annotations.push(), link, &page_ids);
+            }
+        }
+
+        let mut resources = pdf_page.resources();
+        resources.fonts().pairs(font_ids.iter().map(|&(font, id)| (Name(font.resource_name().as_bytes()), id)));
+        let names: Vec<String> = page.images.iter().map(|i| format!("Im{}", i + 1)).collect();
+        resources.x_objects()
+            .pairs(page.images.iter().zip(&names).map(|(&i, name)| (Name(name.as_bytes()), image_ids[i])));
+    }
+
+    pdf.pages(pages_id).kids(page_ids.iter().cloned()).count(page_ids.len() as i32);
+
+    let outlines = if outline.is_empty() {
+        None
+    } else {
+        let outlines = alloc.bump();
+        let (first, last) = write_outline(&mut pdf, &mut alloc, outline, outlines, &page_ids);
+        pdf.outline(outlines).first(first).last(last).count(outline.len() as i32);
+        Some(outlines)
+    };
+
+    {
+        let mut catalog = pdf.catalog(catalog);
+        catalog.pages(pages_id);
+        if let Some(outlines) = outlines {
+            catalog.outlines(outlines).page_mode(pdf_writer::types::PageMode::UseOutlines);
+        }
+    }
+
+    let info = alloc.bump();
+    pdf.document_info(info)
+        .title(TextStr(book.get_title()))
+        .author(TextStr(book.get_author()))
+        .producer(TextStr("mdBook"));
+
+    pdf.finish()
+}
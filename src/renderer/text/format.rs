@@ -0,0 +1,591 @@
+//! Formats the markdown of a chapter as text wrapped to a width, optionally styled with ANSI escape
+//! codes. The destinations of links are listed at the end of the chapter, the text of a link is
+//! followed by its number in that list.
+
+use std::iter;
+
+use pulldown_cmark::{Parser, Event, Tag, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+
+/// Lines are never wrapped narrower than this, even when deeply indented
+const MIN_WIDTH: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    /// ANSI color code of the foreground
+    color: Option<u8>,
+}
+
+impl Style {
+    /// The escape code switching to this style from any other
+    fn ansi(&self) -> String {
+        let mut codes = vec!["0".to_owned()];
+        if self.bold {
+            codes.push("1".to_owned());
+        }
+        if self.italic {
+            codes.push("3".to_owned());
+        }
+        if self.underline {
+            codes.push("4".to_owned());
+        }
+        if let Some(color) = self.color {
+            codes.push(color.to_string());
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    text: String,
+    style: Style,
+}
+
+/// Formats a chapter, lines are at most `width` characters long unless a word or a line of code
+/// does not fit
+pub fn format(markdown: &str, width: usize, ansi: bool) -> String {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let mut state = State {
+        out: String::new(),
+        last_blank: true,
+        width: width,
+        ansi: ansi,
+        spans: vec![],
+        bold: 0,
+        italic: 0,
+        code: false,
+        links: vec![],
+        heading: None,
+        code_block: None,
+        lists: vec![],
+        marker: None,
+        quotes: 0,
+        footnote: None,
+        table: None,
+        table_head: false,
+        notes: vec![],
+    };
+
+    for event in Parser::new_ext(markdown, opts) {
+        state.event(event);
+    }
+    state.flush();
+
+    let notes: Vec<String> = state.notes.drain(..).collect();
+    if !notes.is_empty() {
+        state.gap();
+        for (i, dest) in notes.iter().enumerate() {
+            state.line(&format!("[{}] {}", i + 1, dest));
+        }
+    }
+
+    state.out
+}
+
+/// Breaks the text into lines of at most `width` characters. A span of `"\n"` starts a new line.
+fn wrap(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    // The words, each made of the parts of the spans it contains
+    let mut words: Vec<Option<Vec<Span>>> = vec![];
+    let mut word: Vec<Span> = vec![];
+
+    for span in spans {
+        if span.text == "\n" {
+            if !word.is_empty() {
+                words.push(Some(word.drain(..).collect()));
+            }
+            words.push(None);
+            continue;
+        }
+
+        let mut part = String::new();
+        for c in span.text.chars() {
+            if c.is_whitespace() {
+                if !part.is_empty() {
+                    word.push(Span { text: part.clone(), style: span.style });
+                    part.clear();
+                }
+                if !word.is_empty() {
+                    words.push(Some(word.drain(..).collect()));
+                }
+            } else {
+                part.push(c);
+            }
+        }
+        if !part.is_empty() {
+            word.push(Span { text: part, style: span.style });
+        }
+    }
+    if !word.is_empty() {
+        words.push(Some(word));
+    }
+
+    let mut lines = vec![];
+    let mut line: Vec<Span> = vec![];
+    let mut line_width = 0;
+
+    for word in words {
+        let word = match word {
+            Some(word) => word,
+            None => {
+                lines.push(line.drain(..).collect());
+                line_width = 0;
+                continue;
+            },
+        };
+
+        let word_width: usize = word.iter().map(|s| s.text.chars().count()).sum();
+        if line_width > 0 && line_width + 1 + word_width > width {
+            lines.push(line.drain(..).collect());
+            line_width = 0;
+        }
+
+        if line_width > 0 {
+            // The space between two words of a link is underlined like them
+            let style = match (line.last(), word.first()) {
+                (Some(a), Some(b)) if a.style == b.style => a.style,
+                _ => Style::default(),
+            };
+            line.push(Span { text: " ".to_owned(), style: style });
+            line_width += 1;
+        }
+        line_width += word_width;
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Writes the spans of a line, with the escape codes of their style when `ansi` is set
+fn paint(spans: &[Span], ansi: bool) -> String {
+    let mut out = String::new();
+    let mut current = Style::default();
+
+    for span in spans {
+        if ansi && span.style != current {
+            out.push_str(&span.style.ansi());
+            current = span.style;
+        }
+        out.push_str(&span.text);
+    }
+    if current != Style::default() {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+fn spaces(n: usize) -> String {
+    iter::repeat(" ").take(n).collect()
+}
+
+struct State {
+    out: String,
+    last_blank: bool,
+    width: usize,
+    ansi: bool,
+
+    /// The text of the current paragraph, heading or table cell
+    spans: Vec<Span>,
+    bold: usize,
+    italic: usize,
+    code: bool,
+    /// The numbers of the links the text is in, `None` for links within the chapter
+    links: Vec<Option<usize>>,
+    heading: Option<i32>,
+
+    /// The info string and content of the current code block
+    code_block: Option<(String, String)>,
+
+    /// The next number of the ordered lists, `None` for bullet lists
+    lists: Vec<Option<usize>>,
+    /// The marker of the current list item, until its first paragraph is written
+    marker: Option<String>,
+    quotes: usize,
+    /// The label of the current footnote definition, until its first paragraph is written
+    footnote: Option<String>,
+
+    /// The rows of the current table, each row has the spans of its cells
+    table: Option<Vec<Vec<Vec<Span>>>>,
+    table_head: bool,
+
+    /// The destinations of the links, in the order they are numbered
+    notes: Vec<String>,
+}
+
+impl State {
+    fn style(&self) -> Style {
+        let link = self.links.iter().any(|l| l.is_some());
+        Style {
+            bold: self.bold > 0 || self.heading.is_some() || self.table_head,
+            italic: self.italic > 0,
+            underline: link || self.heading == Some(1),
+            color: if self.code {
+                Some(36)
+            } else if link {
+                Some(34)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let style = self.style();
+        self.spans.push(Span { text: text.to_owned(), style: style });
+    }
+
+    /// The width of a list marker, which the following lines of the item are indented by
+    fn marker_width(list: Option<usize>) -> usize {
+        if list.is_some() { 4 } else { 2 }
+    }
+
+    /// The prefix of the lines of blockquotes and lists, the first is for the line with the marker
+    /// of a list item
+    fn prefixes(&self) -> (String, String) {
+        let quote = if self.ansi { "│ " } else { "> " };
+        let mut outer: String = iter::repeat(quote).take(self.quotes).collect();
+
+        let (last, parents) = match self.lists.split_last() {
+            Some((last, parents)) => (*last, parents),
+            None => return (outer.clone(), outer),
+        };
+        for list in parents {
+            outer.push_str(&spaces(State::marker_width(*list)));
+        }
+
+        let rest = format!("{}{}", outer, spaces(State::marker_width(last)));
+        let first = match self.marker {
+            Some(ref marker) => format!("{}{:<2$}", outer, marker, State::marker_width(last)),
+            None => rest.clone(),
+        };
+        (first, rest)
+    }
+
+    fn line(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+        self.last_blank = false;
+    }
+
+    /// Separates two blocks by an empty line
+    fn gap(&mut self) {
+        if !self.last_blank {
+            let (_, prefix) = self.prefixes();
+            self.out.push_str(prefix.trim_right());
+            self.out.push('\n');
+            self.last_blank = true;
+        }
+    }
+
+    /// Writes the pending text as a paragraph
+    fn flush(&mut self) {
+        let spans: Vec<Span> = self.spans.drain(..).collect();
+        if spans.iter().all(|s| s.text.trim().is_empty()) && self.marker.is_none() {
+            return;
+        }
+
+        let (first, rest) = self.prefixes();
+        self.marker = None;
+        let width = self.width.saturating_sub(rest.chars().count());
+        let lines = wrap(&spans, if width < MIN_WIDTH { MIN_WIDTH } else { width });
+
+        if lines.is_empty() {
+            self.line(first.trim_right());
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let text = format!("{}{}", if i == 0 { &first } else { &rest }, paint(line, self.ansi));
+            self.line(&text);
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        if let Some((_, ref mut code)) = self.code_block {
+            match event {
+                Event::Text(ref text) => {
+                    code.push_str(text);
+                    return;
+                },
+                Event::End(Tag::CodeBlock(_)) => {},
+                _ => return,
+            }
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                if self.marker.is_none() {
+                    self.gap();
+                }
+                if let Some(label) = self.footnote.take() {
+                    self.push_text(&label);
+                }
+            },
+            Event::End(Tag::Paragraph) => self.flush(),
+
+            Event::Start(Tag::Header(level)) => {
+                self.flush();
+                self.gap();
+                self.heading = Some(level);
+            },
+            Event::End(Tag::Header(level)) => {
+                let text: String = self.spans.iter().map(|s| &*s.text).collect();
+                self.flush();
+                self.heading = None;
+
+                // Without styles the first two levels of headings are underlined
+                if !self.ansi && level <= 2 {
+                    let width = text.chars().count();
+                    let underline: String = iter::repeat(if level == 1 { "=" } else { "-" })
+                        .take(if width < self.width { width } else { self.width })
+                        .collect();
+                    self.line(&underline);
+                }
+            },
+
+            Event::Start(Tag::BlockQuote) => {
+                self.flush();
+                self.gap();
+                self.quotes += 1;
+            },
+            Event::End(Tag::BlockQuote) => {
+                self.flush();
+                self.quotes -= 1;
+            },
+
+            Event::Start(Tag::CodeBlock(info)) => {
+                self.flush();
+                self.gap();
+                self.code_block = Some((info.into_owned(), String::new()));
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((info, code)) = self.code_block.take() {
+                    self.write_code_block(&info, &code);
+                }
+            },
+
+            Event::Start(Tag::List(start)) => {
+                self.flush();
+                if self.lists.is_empty() {
+                    self.gap();
+                }
+                self.lists.push(start);
+            },
+            Event::End(Tag::List(_)) => {
+                self.flush();
+                self.lists.pop();
+            },
+            Event::Start(Tag::Item) => {
+                self.flush();
+                let bullet = if self.ansi { "•" } else { "-" };
+                self.marker = Some(match self.lists.last_mut() {
+                    Some(&mut Some(ref mut n)) => {
+                        *n += 1;
+                        format!("{}.", *n - 1)
+                    },
+                    _ => bullet.to_owned(),
+                });
+            },
+            Event::End(Tag::Item) => self.flush(),
+
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                self.flush();
+                self.footnote = Some(format!("[^{}]: ", name));
+            },
+            Event::End(Tag::FootnoteDefinition(_)) => self.flush(),
+            Event::FootnoteReference(name) => self.push_text(&format!("[^{}]", name)),
+
+            Event::Start(Tag::Table(_)) => {
+                self.flush();
+                self.gap();
+                self.table = Some(vec![]);
+            },
+            Event::End(Tag::Table(_)) => {
+                if let Some(rows) = self.table.take() {
+                    self.write_table(&rows);
+                }
+            },
+            Event::Start(Tag::TableHead) => {
+                self.table_head = true;
+                self.table_row();
+            },
+            Event::End(Tag::TableHead) => self.table_head = false,
+            Event::Start(Tag::TableRow) => self.table_row(),
+            Event::Start(Tag::TableCell) => self.spans.clear(),
+            Event::End(Tag::TableCell) => {
+                let mut cell: Vec<Span> = self.spans.drain(..).collect();
+                if let Some(first) = cell.first_mut() {
+                    first.text = first.text.trim_left().to_owned();
+                }
+                if let Some(last) = cell.last_mut() {
+                    last.text = last.text.trim_right().to_owned();
+                }
+                if let Some(row) = self.table.as_mut().and_then(|rows| rows.last_mut()) {
+                    row.push(cell);
+                }
+            },
+
+            Event::Start(Tag::Emphasis) => self.italic += 1,
+            Event::End(Tag::Emphasis) => self.italic -= 1,
+            Event::Start(Tag::Strong) => self.bold += 1,
+            Event::End(Tag::Strong) => self.bold -= 1,
+            Event::Start(Tag::Code) => self.code = true,
+            Event::End(Tag::Code) => self.code = false,
+
+            Event::Start(Tag::Link(dest, _)) => {
+                if dest.starts_with('#') {
+                    self.links.push(None);
+                } else {
+                    self.notes.push(dest.into_owned());
+                    self.links.push(Some(self.notes.len()));
+                }
+            },
+            Event::End(Tag::Link(..)) => {
+                if let Some(Some(n)) = self.links.pop() {
+                    self.push_text(&format!("[{}]", n));
+                }
+            },
+
+            // Only the alternative text of images is shown
+            Event::Start(Tag::Image(..)) => {
+                self.italic += 1;
+                self.push_text("[");
+            },
+            Event::End(Tag::Image(..)) => {
+                self.push_text("]");
+                self.italic -= 1;
+            },
+
+            Event::Start(Tag::Rule) => {
+                self.flush();
+                self.gap();
+                let rule: String = iter::repeat(if self.ansi { "─" } else { "-" }).take(self.width).collect();
+                self.line(&rule);
+            },
+
+            Event::Text(text) => self.push_text(&text),
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+
+            // Raw html can not be shown as text
+            Event::Html(_) |
+            Event::InlineHtml(_) => {},
+
+            _ => {},
+        }
+    }
+
+    fn table_row(&mut self) {
+        if let Some(ref mut rows) = self.table {
+            rows.push(vec![]);
+        }
+    }
+
+    fn write_code_block(&mut self, info: &str, code: &str) {
+        let lang = info.split(',').next().unwrap_or("").trim();
+        let (_, prefix) = self.prefixes();
+        let style = Style { color: Some(36), ..Style::default() };
+
+        for line in code.trim_right_matches('\n').lines() {
+            // Like rustdoc, lines starting with `# ` are hidden in Rust code
+            if lang == "rust" && (line.starts_with("# ") || line.trim() == "#") {
+                continue;
+            }
+            let span = Span { text: line.to_owned(), style: style };
+            let text = format!("{}    {}", prefix, paint(&[span], self.ansi));
+            self.line(text.trim_right());
+        }
+        self.marker = None;
+    }
+
+    /// Writes a table with its columns aligned, the cells are not wrapped
+    fn write_table(&mut self, rows: &[Vec<Vec<Span>>]) {
+        let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| cell.iter().map(|s| s.text.chars().count()).sum())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let (_, prefix) = self.prefixes();
+        let separator = if self.ansi { " │ " } else { " | " };
+
+        for (i, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = widths.iter()
+                .enumerate()
+                .map(|(j, width)| {
+                    let cell = row.get(j).map(|c| &c[..]).unwrap_or(&[]);
+                    let len: usize = cell.iter().map(|s| s.text.chars().count()).sum();
+                    format!("{}{}", paint(cell, self.ansi), spaces(width - len))
+                })
+                .collect();
+            let text = format!("{}{}", prefix, cells.join(separator));
+            self.line(text.trim_right());
+
+            if i == 0 {
+                let (line, cross) = if self.ansi { ("─", "─┼─") } else { ("-", "-+-") };
+                let rule: Vec<String> = widths.iter().map(|w| iter::repeat(line).take(*w).collect()).collect();
+                let text = format!("{}{}", prefix, rule.join(cross));
+                self.line(&text);
+            }
+        }
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_wrap() {
+    let plain = Style::default();
+    let bold = Style { bold: true, ..Style::default() };
+    let spans = vec![Span { text: "one two ".to_owned(), style: plain },
+                     Span { text: "three".to_owned(), style: bold },
+                     Span { text: "s four\nfive".to_owned(), style: plain },
+                     Span { text: "\n".to_owned(), style: plain },
+                     Span { text: "six".to_owned(), style: plain }];
+
+    let lines: Vec<String> = wrap(&spans, 12).iter().map(|l| paint(l, false)).collect();
+    assert_eq!(lines, vec!["one two", "threes four", "five", "six"]);
+    assert_eq!(paint(&wrap(&spans, 12)[1], true), "\x1b[0;1mthree\x1b[0ms four");
+}
+
+#[test]
+fn test_format() {
+    let markdown = "# Title\n\nSome text that is long enough to be wrapped, with [a link](https://example.com).\n\n\
+                    - one\n- two\n    1. nested\n\n> quoted\n\n```rust\n# fn main() {\nlet a = 1;\n# }\n```\n\n\
+                    | Key | Value |\n|-----|-------|\n| a | long value |\n";
+
+    assert_eq!(format(markdown, 30, false),
+               "Title\n\
+                =====\n\
+                \n\
+                Some text that is long enough\n\
+                to be wrapped, with a link[1].\n\
+                \n\
+                - one\n\
+                - two\n  \
+                  1.  nested\n\
+                \n\
+                > quoted\n\
+                \n    \
+                let a = 1;\n\
+                \n\
+                Key | Value\n\
+                ----+-----------\n\
+                a   | long value\n\
+                \n\
+                [1] https://example.com\n");
+}
@@ -0,0 +1,80 @@
+//! Renders the book as plain text, `book.txt` in the destination directory. The same formatting,
+//! with ANSI styles, is used by `mdbook read` to show the chapters in a terminal.
+
+mod format;
+
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use book::MDBook;
+use book::bookitem::BookItem;
+use renderer::Renderer;
+use utils;
+
+pub struct Text {
+    width: usize,
+    ansi: bool,
+}
+
+impl Text {
+    pub fn new() -> Self {
+        Text {
+            width: 80,
+            ansi: false,
+        }
+    }
+
+    /// Sets the width the lines are wrapped at, 80 characters by default
+
+    pub fn set_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Styles the text with ANSI escape codes, for terminals. The text is not styled by default.
+
+    pub fn set_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Formats a chapter of the book, `path` is relative to the source directory
+    pub fn render_chapter(&self, book: &MDBook, path: &Path) -> Result<String, Box<Error>> {
+        let markdown = try!(super::read_chapter(book, path));
+        Ok(format::format(&markdown, self.width, self.ansi))
+    }
+}
+
+impl Renderer for Text {
+    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+        debug!("[fn]: render (text)");
+
+        let mut content = String::new();
+        for item in book.iter() {
+            let ch = match *item {
+                BookItem::Chapter(_, ref ch) |
+                BookItem::Affix(ref ch) => ch,
+                BookItem::Spacer => continue,
+            };
+
+            if !content.is_empty() {
+                content.push('\n');
+            }
+
+            // Chapters without a file only have their name as heading
+            if ch.path == PathBuf::new() {
+                content.push_str(&format::format(&format!("# {}", ch.name), self.width, self.ansi));
+            } else {
+                content.push_str(&try!(self.render_chapter(book, &ch.path)));
+            }
+        }
+
+        let path = book.get_dest().join("book.txt");
+        let mut file = try!(utils::fs::create_file(&path));
+        try!(file.write_all(content.as_bytes()));
+        info!("[*] Creating {:?} ✓", path);
+
+        Ok(())
+    }
+}
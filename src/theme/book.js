@@ -6,23 +6,30 @@ $( document ).ready(function() {
     // Fix back button cache problem
     window.onunload = function(){};
 
-    // Set theme
-    var theme = localStorage.getItem('theme');
-    if (theme === null) { theme = 'light'; }
+    // Set theme, the colour schemes are listed by the template
+    set_theme(preferred_theme());
 
-    set_theme(theme);
+    // Follow the colours the system prefers while they change, until the reader picks a scheme
+    if (window.matchMedia) {
+        window.matchMedia("(prefers-color-scheme: dark)").addListener(function() {
+            set_theme(preferred_theme());
+        });
+    }
 
 
-    // Syntax highlighting Configuration
-    hljs.configure({
-        tabReplace: '    ', // 4 spaces
-        languages: [],      // Languages used for auto-detection
-    });
+    // Code blocks are highlighted while building the book, highlight.js is only included with
+    // `highlight-js` in book.json and highlights the languages mdBook does not know
+    if (typeof hljs !== 'undefined') {
+        hljs.configure({
+            tabReplace: '    ', // 4 spaces
+            languages: [],      // Languages used for auto-detection
+        });
+
+        $('code').not('.hljs').each(function(i, block) {
+            hljs.highlightBlock(block);
+        });
+    }
 
-    $('code').each(function(i, block) {
-        hljs.highlightBlock(block);
-    });
-    
     // Adding the hljs class gives code blocks the color css
     // even if highlighting doesn't apply
     $('code').addClass('hljs');
@@ -56,19 +63,33 @@ $( document ).ready(function() {
     var content = $("#content");
 
 
-    // Add anchors for all content headers
-    content.find("h1, h2, h3, h4, h5").wrap(function(){
-        var wrapper = $("<a class=\"header\">");
-        var header_name = $(this).text().trim().replace(/\W/g, '-')
-        wrapper.attr("name", header_name);
-        // Add so that when you click the link actually shows up in the url bar...
-        // Remove any existing anchor then append the new one
-        // ensuring eg. no spaces are present within it ie. they become %20
-        wrapper.attr("href", $(location).attr('href').split("#")[0] + "#" + header_name );
-        return wrapper;
+    // Links to a heading on the same page, like the links on the headings themselves, would
+    // otherwise be resolved against the <base> of the page and point to the root of the book
+    content.find("a[href^='#']").each(function(){
+        $(this).attr("href", $(location).attr('href').split("#")[0] + $(this).attr("href"));
     });
 
 
+    // Highlight the entry of the page table of contents for the part of the page being read
+    var page_toc_links = $("#page-toc-panel a");
+    if (page_toc_links.length > 0) {
+        var update_page_toc = function() {
+            var current = page_toc_links[0];
+            page_toc_links.each(function(){
+                var heading = document.getElementById($(this).attr("data-id"));
+                if (heading && heading.getBoundingClientRect().top < 100) {
+                    current = this;
+                }
+            });
+            page_toc_links.removeClass("active");
+            $(current).addClass("active");
+        };
+
+        $(".page").on("scroll", update_page_toc);
+        update_page_toc();
+    }
+
+
     // Toggle sidebar
     $("#sidebar-toggle").click(function(event){
         if ( html.hasClass("sidebar-hidden") ) {
@@ -89,6 +110,33 @@ $( document ).ready(function() {
     });
 
 
+    // Collapsible sections of the sidebar, the state chosen by the reader is remembered. The
+    // sections leading to the current chapter are always expanded.
+    var folds = {};
+    try { folds = JSON.parse(localStorage.getItem('toc-folds')) || {}; } catch (e) {}
+
+    sidebar.find(".toggle").each(function(){
+        var item = $(this).parent();
+        var state = folds[$(this).attr("data-key")];
+        var leads_to_active = item.find(".active").length || item.next().find(".active").length;
+
+        if (state === "expanded" || leads_to_active) {
+            item.removeClass("collapsed").addClass("expanded");
+        } else if (state === "collapsed") {
+            item.removeClass("expanded").addClass("collapsed");
+        }
+    });
+
+    sidebar.find(".toggle").click(function(){
+        var item = $(this).parent();
+        var state = item.hasClass("expanded") ? "collapsed" : "expanded";
+
+        item.removeClass("expanded collapsed").addClass(state);
+        folds[$(this).attr("data-key")] = state;
+        localStorage.setItem('toc-folds', JSON.stringify(folds));
+    });
+
+
     // Scroll sidebar to current active section
     var activeSection = sidebar.find(".active");
     if(activeSection.length) {
@@ -111,11 +159,15 @@ $( document ).ready(function() {
         if($('.theme-popup').length) {
             $('.theme-popup').remove();
         } else {
-            var popup = $('<div class="theme-popup"></div>')
-                .append($('<div class="theme" id="light">Light <span class="default">(default)</span><div>'))
-                .append($('<div class="theme" id="rust">Rust</div>'))
-                .append($('<div class="theme" id="coal">Coal</div>'))
-                .append($('<div class="theme" id="navy">Navy</div>'));
+            var popup = $('<div class="theme-popup"></div>');
+            $.each(themes, function(i, theme) {
+                // The names are escaped by the template
+                var entry = $('<div class="theme"></div>').attr('id', theme.id).html(theme.name);
+                if (theme.id === default_theme) {
+                    entry.append(' <span class="default">(default)</span>');
+                }
+                popup.append(entry);
+            });
 
 
             popup.insertAfter(this);
@@ -123,29 +175,33 @@ $( document ).ready(function() {
             $('.theme').click(function(){
                 var theme = $(this).attr('id');
 
+                localStorage.setItem('theme', theme);
                 set_theme(theme);
             });
         }
 
     });
 
-    function set_theme(theme) {
-        if (theme == 'coal' || theme == 'navy') {
-            $("[href='tomorrow-night.css']").prop('disabled', false);
-            $("[href='highlight.css']").prop('disabled', true);
-        } else {
-            $("[href='tomorrow-night.css']").prop('disabled', true);
-            $("[href='highlight.css']").prop('disabled', false);
+    function set_theme(id) {
+        var theme = find_theme(id);
+        if (theme === null) {
+            return;
         }
 
-        localStorage.setItem('theme', theme);
-
-        $('body').removeClass().addClass(theme);
+        $('#highlight-css').attr('href', theme.highlight_css);
+        $('body').removeClass().addClass(theme.id);
     }
 
 
-    // Hide Rust code lines prepended with a specific character
-    var hiding_character = "#";
+    // Hide Rust code lines the same way rustdoc does: lines starting with "# " or consisting of
+    // a single "#" are hidden, a line starting with "##" is shown with one "#" removed.
+    function hidden_line(line) {
+        var hidden = line.match(/^(\s*)#(?: (.*))?$/);
+        if (hidden) {
+            return { hidden: true, text: hidden[1] + (hidden[2] || "") };
+        }
+        return { hidden: false, text: line.replace(/^(\s*)##/, "$1#") };
+    }
 
     $("code.language-rust").each(function(i, block){
 
@@ -156,25 +212,34 @@ $( document ).ready(function() {
         var first_non_hidden_line = false;
         var lines_hidden = false;
 
+        // Editable code shows every line, so that the hidden ones can be edited as well
+        var editable = code_block.hasClass("editable");
+
         for(var n = 0; n < lines.length; n++){
-            if($.trim(lines[n])[0] == hiding_character){
+            var line = hidden_line(lines[n]);
+            if(line.hidden && !editable){
                 if(first_non_hidden_line){
-                    lines[n] = "<span class=\"hidden\">" + "\n" + lines[n].replace(/(\s*)#/, "$1") + "</span>";
+                    lines[n] = "<span class=\"hidden\">" + "\n" + line.text + "</span>";
                 }
                 else {
-                    lines[n] = "<span class=\"hidden\">" + lines[n].replace(/(\s*)#/, "$1") + "\n"  +  "</span>";
+                    lines[n] = "<span class=\"hidden\">" + line.text + "\n"  +  "</span>";
                 }
                 lines_hidden = true;
             }
             else if(first_non_hidden_line) {
-                lines[n] = "\n" + lines[n];
+                lines[n] = "\n" + line.text;
             }
             else {
+                lines[n] = line.text;
                 first_non_hidden_line = true;
             }
         }
         code_block.html(lines.join(""));
 
+        if(editable) {
+            code_block.attr("contenteditable", "true").attr("spellcheck", "false");
+        }
+
         // If no lines were hidden, return
         if(!lines_hidden) { return; }
 
@@ -196,6 +261,11 @@ $( document ).ready(function() {
 
     // Process playpen code blocks
     $(".playpen").each(function(block){
+        // Self-contained books have no playground on another host
+        if (typeof playground_url !== 'undefined' && playground_url === null) {
+            return;
+        }
+
         var pre_block = $(this);
         // Add play button
         var buttons = pre_block.find(".buttons");
@@ -223,8 +293,11 @@ function run_rust_code(code_block) {
 
     result_block.text("Running...");
 
+    // The playground can be configured in book.json, for example to use a local server
+    var url = typeof playground_url !== 'undefined' ? playground_url : "https://play.rust-lang.org/evaluate.json";
+
     $.ajax({
-        url: "https://play.rust-lang.org/evaluate.json",
+        url: url,
         method: "POST",
         crossDomain: true,
         dataType: "json",
@@ -232,6 +305,9 @@ function run_rust_code(code_block) {
         data: JSON.stringify({version: "stable", optimize: "0", code: code_block.find(".language-rust").text() }),
         success: function(response){
             result_block.text(response.result);
+        },
+        error: function(xhr, status, error){
+            result_block.text("Could not reach the playground at " + url + ": " + (error || status));
         }
     });
 }
@@ -1,10 +1,15 @@
-use std::path::Path;
-use std::fs::File;
-use std::io::Read;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{self, Read};
+
+use grass;
 
 
 pub static INDEX: &'static [u8] = include_bytes!("index.hbs");
-pub static CSS: &'static [u8] = include_bytes!("book.css");
+// Compiled from `scss/book.scss` by build.rs
+pub static CSS: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/book.css"));
 pub static FAVICON: &'static [u8] = include_bytes!("favicon.png");
 pub static JS: &'static [u8] = include_bytes!("book.js");
 pub static HIGHLIGHT_JS: &'static [u8] = include_bytes!("highlight.js");
@@ -19,6 +24,67 @@ pub static FONT_AWESOME_WOFF: &'static [u8] = include_bytes!("_FontAwesome/fonts
 pub static FONT_AWESOME_WOFF2: &'static [u8] = include_bytes!("_FontAwesome/fonts/fontawesome-webfont.woff2");
 pub static FONT_AWESOME_OTF: &'static [u8] = include_bytes!("_FontAwesome/fonts/FontAwesome.otf");
 
+/// The fonts of the default theme, written to the `fonts` directory of the output so that books do not
+/// load them from other hosts
+pub static FONTS: &'static [(&'static str, &'static [u8])] = &[
+    ("fonts.css", include_bytes!("fonts/fonts.css")),
+    ("OPEN-SANS-LICENSE.txt", include_bytes!("fonts/OPEN-SANS-LICENSE.txt")),
+    ("open-sans-v17-all-charsets-300.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-300.woff2")),
+    ("open-sans-v17-all-charsets-300italic.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-300italic.woff2")),
+    ("open-sans-v17-all-charsets-regular.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-regular.woff2")),
+    ("open-sans-v17-all-charsets-italic.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-italic.woff2")),
+    ("open-sans-v17-all-charsets-600.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-600.woff2")),
+    ("open-sans-v17-all-charsets-600italic.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-600italic.woff2")),
+    ("open-sans-v17-all-charsets-700.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-700.woff2")),
+    ("open-sans-v17-all-charsets-700italic.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-700italic.woff2")),
+    ("open-sans-v17-all-charsets-800.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-800.woff2")),
+    ("open-sans-v17-all-charsets-800italic.woff2", include_bytes!("fonts/open-sans-v17-all-charsets-800italic.woff2")),
+];
+
+/// The handlebars partials `index.hbs` is made of, by name. They are included with `{{> name}}`.
+pub static PARTIALS: &'static [(&'static str, &'static [u8])] = &[
+    ("head", include_bytes!("partials/head.hbs")),
+    ("sidebar", include_bytes!("partials/sidebar.hbs")),
+    ("menu_bar", include_bytes!("partials/menu_bar.hbs")),
+    ("mobile_nav_buttons", include_bytes!("partials/mobile_nav_buttons.hbs")),
+    ("nav_buttons", include_bytes!("partials/nav_buttons.hbs")),
+    ("footer", include_bytes!("partials/footer.hbs")),
+];
+
+/// A colour scheme the reader can pick in the theme picker. While it is active, its id is the
+/// class of `<body>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorScheme {
+    pub id: String,
+    pub name: String,
+    /// The stylesheet defining the scheme, the built-in schemes are defined in `book.css`
+    pub css: Option<PathBuf>,
+    /// Dark schemes highlight code with `tomorrow-night.css` instead of `highlight.css`
+    pub dark: bool,
+    /// The stylesheet highlighting code, instead of the one given by `dark`
+    pub highlight_css: Option<PathBuf>,
+}
+
+impl ColorScheme {
+    pub fn new(id: &str, name: &str, dark: bool) -> Self {
+        ColorScheme {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            css: None,
+            dark: dark,
+            highlight_css: None,
+        }
+    }
+
+    /// The schemes of the default theme
+    pub fn builtin() -> Vec<ColorScheme> {
+        vec![ColorScheme::new("light", "Light", false),
+             ColorScheme::new("rust", "Rust", false),
+             ColorScheme::new("coal", "Coal", true),
+             ColorScheme::new("navy", "Navy", true)]
+    }
+}
+
 /// The `Theme` struct should be used instead of the static variables because the `new()` method
 /// will look if the user has a theme directory in his source folder and use the users theme instead
 /// of the default.
@@ -27,6 +93,9 @@ pub static FONT_AWESOME_OTF: &'static [u8] = include_bytes!("_FontAwesome/fonts/
 /// user has specified another theme.
 pub struct Theme {
     pub index: Vec<u8>,
+    /// The partials by name, the ones of the user's `theme/partials` directory replace or come in
+    /// addition to the default ones
+    pub partials: BTreeMap<String, Vec<u8>>,
     pub css: Vec<u8>,
     pub favicon: Vec<u8>,
     pub js: Vec<u8>,
@@ -34,6 +103,9 @@ pub struct Theme {
     pub tomorrow_night_css: Vec<u8>,
     pub highlight_js: Vec<u8>,
     pub jquery: Vec<u8>,
+    /// The directories of the user's theme, apart from `partials`, like `fonts` or `images`. They
+    /// are copied as a whole to the output directory, next to `book.css`.
+    pub asset_dirs: Vec<PathBuf>,
 }
 
 impl Theme {
@@ -42,6 +114,7 @@ impl Theme {
         // Default theme
         let mut theme = Theme {
             index: INDEX.to_owned(),
+            partials: PARTIALS.iter().map(|&(name, partial)| (name.to_owned(), partial.to_owned())).collect(),
             css: CSS.to_owned(),
             favicon: FAVICON.to_owned(),
             js: JS.to_owned(),
@@ -49,6 +122,7 @@ impl Theme {
             tomorrow_night_css: TOMORROW_NIGHT_CSS.to_owned(),
             highlight_js: HIGHLIGHT_JS.to_owned(),
             jquery: JQUERY.to_owned(),
+            asset_dirs: vec![],
         };
 
         // Check if the given path exists
@@ -70,6 +144,42 @@ impl Theme {
             let _ = f.read_to_end(&mut theme.index);
         }
 
+        // partials/*.hbs
+        if let Ok(entries) = fs::read_dir(&src.join("partials")) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if path.extension().map_or(true, |ext| ext != "hbs") {
+                    continue;
+                }
+                let name = match path.file_stem().and_then(|s| s.to_str()) {
+                    Some(name) => name.to_owned(),
+                    None => continue,
+                };
+                // Partials are registered like templates, `index` would replace the main template
+                if name == "index" {
+                    warn!("[-] {:?} is ignored, the template of the pages is theme/index.hbs", path);
+                    continue;
+                }
+
+                if let Ok(mut f) = File::open(&path) {
+                    let mut partial = vec![];
+                    let _ = f.read_to_end(&mut partial);
+                    theme.partials.insert(name, partial);
+                }
+            }
+        }
+
+        // Asset directories
+        if let Ok(entries) = fs::read_dir(&src) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if path.is_dir() && !path.ends_with("partials") {
+                    theme.asset_dirs.push(path);
+                }
+            }
+            theme.asset_dirs.sort();
+        }
+
         // book.js
         if let Ok(mut f) = File::open(&src.join("book.js")) {
             theme.js.clear();
@@ -108,4 +218,104 @@ impl Theme {
 
         theme
     }
+
+    /// Compiles the `*.scss` files of the theme directory in `src`, the partials starting with `_`
+    /// are only imported by the others. `book.scss`, `highlight.scss` and `tomorrow-night.scss`
+    /// replace these stylesheets of the theme. The others are returned, with the path of the css
+    /// file as if it was in the theme directory.
+
+    pub fn compile_stylesheets(&mut self, src: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>, Box<Error>> {
+        let mut compiled = vec![];
+
+        let mut files: Vec<PathBuf> = match fs::read_dir(src.join("theme")) {
+            Ok(entries) => {
+                entries.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "scss"))
+                    .filter(|path| path.file_name().and_then(|n| n.to_str()).map_or(false, |n| !n.starts_with('_')))
+                    .collect()
+            },
+            Err(_) => return Ok(compiled),
+        };
+        files.sort();
+
+        for file in files {
+            let css = match grass::from_path(&file, &grass::Options::default()) {
+                Ok(css) => css.into_bytes(),
+                Err(e) => {
+                    return Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                                       format!("Could not compile {:?}: {}", file, e))));
+                },
+            };
+            info!("[*] Compiling {:?} ✓", file);
+
+            let css_file = file.with_extension("css");
+            match css_file.file_name().and_then(|n| n.to_str()) {
+                Some("book.css") => self.css = css,
+                Some("highlight.css") => self.highlight_css = css,
+                Some("tomorrow-night.css") => self.tomorrow_night_css = css,
+                _ => compiled.push((css_file, css)),
+            }
+        }
+
+        Ok(compiled)
+    }
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_compile_stylesheets() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("mdbook").expect("Could not create a temp dir");
+    let theme_dir = tmp.path().join("theme");
+    fs::create_dir(&theme_dir).unwrap();
+    File::create(theme_dir.join("_colors.scss")).unwrap().write_all(b"$fg: #123;").unwrap();
+    File::create(theme_dir.join("book.scss"))
+        .unwrap()
+        .write_all(b"@import 'colors';\nbody { color: $fg; }\n")
+        .unwrap();
+    File::create(theme_dir.join("extra.scss")).unwrap().write_all(b".a { .b { margin: 0; } }").unwrap();
+
+    let mut theme = Theme::new(tmp.path());
+    let compiled = theme.compile_stylesheets(tmp.path()).unwrap();
+
+    assert_eq!(String::from_utf8(theme.css).unwrap(), "body {\n  color: #123;\n}\n");
+    assert_eq!(compiled, vec![(theme_dir.join("extra.css"), b".a .b {\n  margin: 0;\n}\n".to_vec())]);
+}
+
+#[test]
+fn test_partials() {
+    extern crate tempdir;
+    use std::io::Write;
+
+    let tmp = tempdir::TempDir::new("mdbook").expect("Could not create a temp dir");
+    let partials_dir = tmp.path().join("theme").join("partials");
+    fs::create_dir_all(&partials_dir).unwrap();
+    File::create(partials_dir.join("sidebar.hbs")).unwrap().write_all(b"my sidebar").unwrap();
+    File::create(partials_dir.join("banner.hbs")).unwrap().write_all(b"my banner").unwrap();
+    File::create(partials_dir.join("index.hbs")).unwrap().write_all(b"not the template").unwrap();
+    File::create(partials_dir.join("notes.txt")).unwrap().write_all(b"not a partial").unwrap();
+
+    let theme = Theme::new(tmp.path());
+
+    // A user partial replaces the built-in one, an extra partial is added, the others are inherited
+    assert_eq!(theme.partials["sidebar"], b"my sidebar".to_vec());
+    assert_eq!(theme.partials["banner"], b"my banner".to_vec());
+    for &(name, partial) in PARTIALS.iter().filter(|&&(name, _)| name != "sidebar") {
+        assert_eq!(theme.partials[name], partial.to_vec());
+    }
+    assert_eq!(theme.partials.len(), PARTIALS.len() + 1);
+
+    // A partial can not replace the main template
+    assert!(!theme.partials.contains_key("index"));
+    assert_eq!(theme.index, INDEX.to_vec());
+
+    // The partials directory is not an asset directory
+    assert!(theme.asset_dirs.is_empty());
 }
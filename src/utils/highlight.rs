@@ -0,0 +1,367 @@
+//! Syntax highlighting of code blocks while building the book, so that the pages, the print
+//! version and the EPUB are highlighted without JavaScript and without a flash of plain code.
+//!
+//! Code is parsed line by line with the syntax definitions of [syntect](https://docs.rs/syntect),
+//! a token never spans several lines. The scopes syntect gives the text are mapped to the class
+//! names of highlight.js, which keeps `highlight.css` and `tomorrow-night.css` working.
+
+extern crate syntect;
+
+use self::syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet};
+
+lazy_static! {
+    static ref SYNTAXES: SyntaxSet = {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        for syntax in &[include_str!("syntaxes/toml.sublime-syntax"), include_str!("syntaxes/generic.sublime-syntax")] {
+            builder.add(SyntaxDefinition::load_from_str(syntax, true, None).expect("Invalid syntax definition"));
+        }
+        builder.build()
+    };
+
+    static ref SCOPES: Vec<(Scope, Kind)> = SCOPE_KINDS.iter()
+        .map(|&(scope, kind)| (Scope::new(scope).expect("Invalid scope"), kind))
+        .collect();
+}
+
+/// The kind of a token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Plain,
+    Comment,
+    String,
+    Number,
+    Keyword,
+    /// Values like `true` or `None`
+    Literal,
+    /// Types and functions of the standard library, and macros
+    BuiltIn,
+    /// The name of a function or type where it is defined
+    Title,
+    /// Keys of configuration files
+    Attribute,
+    /// Rust attributes and C preprocessor directives
+    Preprocessor,
+    /// Shell variables
+    Variable,
+    /// Rust lifetimes
+    Symbol,
+}
+
+impl Kind {
+    /// The highlight.js class of the kind
+    pub fn class(&self) -> Option<&'static str> {
+        match *self {
+            Kind::Plain => None,
+            Kind::Comment => Some("hljs-comment"),
+            Kind::String => Some("hljs-string"),
+            Kind::Number => Some("hljs-number"),
+            Kind::Keyword => Some("hljs-keyword"),
+            Kind::Literal => Some("hljs-literal"),
+            Kind::BuiltIn => Some("hljs-built_in"),
+            Kind::Title => Some("hljs-title"),
+            Kind::Attribute => Some("hljs-attribute"),
+            Kind::Preprocessor => Some("hljs-preprocessor"),
+            Kind::Variable => Some("hljs-variable"),
+            Kind::Symbol => Some("hljs-symbol"),
+        }
+    }
+}
+
+/// The kind of the text in a scope, the first scope of the list the text is in decides, so a
+/// string in a comment is a comment and a number in a string is a string
+static SCOPE_KINDS: &'static [(&'static str, Kind)] = &[
+    ("comment", Kind::Comment),
+    ("meta.annotation", Kind::Preprocessor),
+    ("meta.preprocessor", Kind::Preprocessor),
+    ("meta.structure.dictionary.key", Kind::Attribute),
+    ("meta.mapping.key", Kind::Attribute),
+    ("entity.name.tag.yaml", Kind::Attribute),
+    ("entity.other.attribute-name", Kind::Attribute),
+    ("string", Kind::String),
+    ("storage.type.string", Kind::String),
+    ("constant.character", Kind::String),
+    ("constant.numeric", Kind::Number),
+    ("constant.language", Kind::Literal),
+    ("storage.modifier.lifetime", Kind::Symbol),
+    ("meta.group.expansion.parameter", Kind::Variable),
+    ("keyword.operator", Kind::Plain),
+    ("keyword", Kind::Keyword),
+    ("storage", Kind::Keyword),
+    ("entity.name", Kind::Title),
+    ("support", Kind::BuiltIn),
+];
+
+/// Language tags syntect does not know, with the tag of the same language it knows
+static ALIASES: &'static [(&'static str, &'static str)] = &[
+    ("shell", "sh"),
+    ("console", "sh"),
+    ("c++", "cpp"),
+];
+
+/// The syntax of a language
+#[derive(Debug, Clone, Copy)]
+pub struct Language(&'static SyntaxReference);
+
+impl Language {
+    /// The language of a language tag like `rust` or the info string of a code block like
+    /// `rust,ignore`, `None` for languages without highlighting
+    pub fn find(tag: &str) -> Option<Language> {
+        let tag = tag.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or("").to_lowercase();
+        let tag = ALIASES.iter().find(|&&(alias, _)| alias == tag).map_or(&*tag, |&(_, known)| known);
+        if tag.is_empty() {
+            return None;
+        }
+
+        let syntaxes: &'static SyntaxSet = &SYNTAXES;
+        syntaxes.find_syntax_by_token(tag).map(Language)
+    }
+
+    /// A language with C-like comments and strings, for the languages without highlighting
+    pub fn generic() -> Language {
+        let syntaxes: &'static SyntaxSet = &SYNTAXES;
+        Language(syntaxes.find_syntax_by_name("Generic").expect("The generic syntax is missing"))
+    }
+}
+
+/// Highlights `code` as html, with the language of the language tag `tag`. Returns `None` for
+/// languages without highlighting.
+
+pub fn highlight_html(tag: &str, code: &str) -> Option<String> {
+    let language = match Language::find(tag) {
+        Some(language) => language,
+        None => return None,
+    };
+
+    let mut highlighter = Highlighter::new(language);
+    let mut html = String::with_capacity(code.len() * 2);
+
+    for (n, line) in code.split('\n').enumerate() {
+        if n > 0 {
+            html.push('\n');
+        }
+        for (kind, text) in highlighter.line(line) {
+            match kind.class() {
+                Some(class) => {
+                    html.push_str("<span class=\"");
+                    html.push_str(class);
+                    html.push_str("\">");
+                    escape(&text, &mut html);
+                    html.push_str("</span>");
+                },
+                None => escape(&text, &mut html),
+            }
+        }
+    }
+
+    Some(html)
+}
+
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+/// Splits code into tokens line by line, remembering the comments and strings that continue on
+/// the next line
+
+pub struct Highlighter {
+    language: Language,
+    state: ParseState,
+    /// The scopes the end of the previous line is in
+    scopes: ScopeStack,
+}
+
+impl Highlighter {
+    pub fn new(language: Language) -> Self {
+        Highlighter {
+            language: language,
+            state: ParseState::new(language.0),
+            scopes: ScopeStack::new(),
+        }
+    }
+
+    /// Splits the next line of the code into tokens, consecutive tokens of the same kind are merged
+    pub fn line(&mut self, line: &str) -> Vec<(Kind, String)> {
+        let mut tokens: Vec<(Kind, String)> = vec![];
+        let mut start = 0;
+
+        // Like rustdoc, lines starting with `# ` are hidden and `##` stands for `#`, book.js needs
+        // these to be plain text
+        let in_code = match kind(&self.scopes) {
+            Kind::Comment | Kind::String => false,
+            _ => true,
+        };
+        if self.language.0.name == "Rust" && in_code {
+            let indent = line.len() - line.trim_left().len();
+            let rest = &line[indent..];
+            if rest == "#" || rest.starts_with("# ") {
+                start = indent + 1;
+            } else if rest.starts_with("##") {
+                start = indent + 2;
+            }
+            push(&mut tokens, Kind::Plain, &line[..start]);
+        }
+
+        // The syntaxes expect lines to end with a newline, which is not part of the tokens
+        let text = format!("{}\n", &line[start..]);
+        let end = text.len() - 1;
+        let ops = match self.state.parse_line(&text, &SYNTAXES) {
+            Ok(ops) => ops,
+            Err(e) => {
+                debug!("[*]: Could not highlight {:?}: {}", line, e);
+                push(&mut tokens, Kind::Plain, &text[..end]);
+                return tokens;
+            },
+        };
+
+        let mut last = 0;
+        for (i, op) in ops {
+            let i = i.min(end);
+            if i > last {
+                push(&mut tokens, kind(&self.scopes), &text[last..i]);
+                last = i;
+            }
+            if let Err(e) = self.scopes.apply(&op) {
+                debug!("[*]: Could not highlight {:?}: {:?}", line, e);
+            }
+        }
+        push(&mut tokens, kind(&self.scopes), &text[last..end]);
+
+        tokens
+    }
+}
+
+/// The kind of the text in the scopes
+fn kind(scopes: &ScopeStack) -> Kind {
+    SCOPES.iter()
+        .find(|&&(ref prefix, _)| scopes.as_slice().iter().any(|scope| prefix.is_prefix_of(*scope)))
+        .map_or(Kind::Plain, |&(_, kind)| kind)
+}
+
+/// Adds text to the tokens, merged with the last token when it is of the same kind
+fn push(tokens: &mut Vec<(Kind, String)>, kind: Kind, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(&mut (last, ref mut last_text)) = tokens.last_mut() {
+        if last == kind {
+            last_text.push_str(text);
+            return;
+        }
+    }
+    tokens.push((kind, text.to_owned()));
+}
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight_html, Highlighter, Kind, Language};
+
+    fn tokens(tag: &str, code: &str) -> Vec<Vec<(Kind, String)>> {
+        let mut highlighter = Highlighter::new(Language::find(tag).unwrap());
+        code.lines().map(|line| highlighter.line(line)).collect()
+    }
+
+    fn kinds(tag: &str, code: &str, kind: Kind) -> Vec<String> {
+        tokens(tag, code)
+            .into_iter()
+            .flat_map(|line| line.into_iter())
+            .filter(|&(k, _)| k == kind)
+            .map(|(_, text)| text)
+            .collect()
+    }
+
+    #[test]
+    fn languages() {
+        assert!(Language::find("rust,ignore").is_some());
+        assert!(Language::find("Python").is_some());
+        assert!(Language::find("sh").is_some());
+        assert!(Language::find("console").is_some());
+        assert!(Language::find("toml").is_some());
+        assert!(Language::find("").is_none());
+        assert!(Language::find("brainfuck").is_none());
+    }
+
+    #[test]
+    fn rust() {
+        let code = "#[derive(Debug)]\n\
+                    # fn hidden() {}\n\
+                    fn main<'a>(s: &'a str) -> Option<char> {\n    \
+                        /* a /* nested */ comment */ let c = '\\n';\n    \
+                        println!(\"{} {}\", r#\"raw \"quote\"\"#, 0x1F);\n    \
+                        Some('x') // done\n\
+                    }";
+
+        assert_eq!(kinds("rust", code, Kind::Preprocessor), vec!["#[derive(Debug)]"]);
+        assert_eq!(tokens("rust", code)[1][0], (Kind::Plain, "# ".to_owned()));
+        assert_eq!(kinds("rust", code, Kind::Title), vec!["hidden", "main"]);
+        assert_eq!(kinds("rust", code, Kind::Symbol), vec!["'a", "'a"]);
+        assert_eq!(kinds("rust", code, Kind::BuiltIn), vec!["println!", "Some"]);
+        assert_eq!(kinds("rust", code, Kind::Comment), vec!["/* a /* nested */ comment */", "// done"]);
+        assert_eq!(kinds("rust", code, Kind::String),
+                   vec!["'\\n'", "\"{} {}\"", "r#\"raw \"quote\"\"#", "'x'"]);
+        assert_eq!(kinds("rust", code, Kind::Number), vec!["0x1F"]);
+        assert_eq!(kinds("rust", code, Kind::Keyword), vec!["fn", "fn", "str", "char", "let"]);
+    }
+
+    #[test]
+    fn multiline_tokens() {
+        let code = "let s = \"one\ntwo\"; /* three\n# four */ x";
+        assert_eq!(tokens("rust", code),
+                   vec![vec![(Kind::Keyword, "let".to_owned()),
+                             (Kind::Plain, " s = ".to_owned()),
+                             (Kind::String, "\"one".to_owned())],
+                        vec![(Kind::String, "two\"".to_owned()),
+                             (Kind::Plain, "; ".to_owned()),
+                             (Kind::Comment, "/* three".to_owned())],
+                        vec![(Kind::Comment, "# four */".to_owned()), (Kind::Plain, " x".to_owned())]]);
+    }
+
+    #[test]
+    fn other_languages() {
+        let toml = "[package]\nname = \"mdbook\" # the name\nlicense-file = '''\nMPL'''\n";
+        assert_eq!(kinds("toml", toml, Kind::Title), vec!["[package]"]);
+        assert_eq!(kinds("toml", toml, Kind::Attribute), vec!["name", "license-file"]);
+        assert_eq!(kinds("toml", toml, Kind::String), vec!["\"mdbook\"", "'''", "MPL'''"]);
+        assert_eq!(kinds("toml", toml, Kind::Comment), vec!["# the name"]);
+
+        let json = "{\"title\": \"a: b\", \"count\": 2, \"ok\": true}";
+        assert_eq!(kinds("json", json, Kind::Attribute), vec!["\"title\"", "\"count\"", "\"ok\""]);
+        assert_eq!(kinds("json", json, Kind::String), vec!["\"a: b\""]);
+        assert_eq!(kinds("json", json, Kind::Literal), vec!["true"]);
+
+        let shell = "echo \"$HOME\" ${PATH}#not-a-comment $1 # comment";
+        assert_eq!(kinds("sh", shell, Kind::Variable), vec!["${PATH}", "$1"]);
+        assert_eq!(kinds("sh", shell, Kind::Comment), vec!["# comment"]);
+        assert_eq!(kinds("sh", shell, Kind::BuiltIn), vec!["echo"]);
+
+        let python = "def f(x):\n    return r'\\d' + None";
+        assert_eq!(kinds("python", python, Kind::Title), vec!["f"]);
+        assert_eq!(kinds("python", python, Kind::String), vec!["r'\\d'"]);
+        assert_eq!(kinds("python", python, Kind::Literal), vec!["None"]);
+
+        let c = "#include <stdio.h>\nint main() { return 0; }";
+        assert_eq!(kinds("c", c, Kind::Preprocessor), vec!["#include <stdio.h>"]);
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(highlight_html("rust", "let a = 1 < 2; // \"ok\"\n").unwrap(),
+                   "<span class=\"hljs-keyword\">let</span> a = <span class=\"hljs-number\">1</span> &lt; \
+                    <span class=\"hljs-number\">2</span>; <span class=\"hljs-comment\">// &quot;ok&quot;</span>\n");
+        assert_eq!(highlight_html("rust", "/* a\nb */").unwrap(),
+                   "<span class=\"hljs-comment\">/* a</span>\n<span class=\"hljs-comment\">b */</span>");
+        assert_eq!(highlight_html("brainfuck", "+++"), None);
+    }
+}
@@ -0,0 +1,962 @@
+//! Math support for the markdown renderer.
+//!
+//! Math between `$...$`, `$$...$$`, `\\(...\\)` and `\\[...\\]` is taken out of the markdown
+//! before it is parsed, so that pulldown-cmark does not interpret `_` and `*` as emphasis. After
+//! rendering, the math is put back either as TeX for MathJax or pre-rendered to MathML.
+
+use std::fmt::Write;
+
+/// Marks the start and end of a placeholder for a math expression. Characters from the Unicode
+/// private use area are passed through the markdown parser untouched.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Math {
+    pub tex: String,
+    pub display: bool,
+}
+
+
+/// Replaces every math expression in `text` by a placeholder and returns the expressions in order
+/// of appearance. Code blocks, code spans and the html elements MathJax skips are left untouched.
+
+pub fn protect(text: &str) -> (String, Vec<Math>) {
+    let mut out = String::with_capacity(text.len());
+    let mut math = vec![];
+    let mut in_fence: Option<String> = None;
+    let mut skip_until: Option<String> = None;
+
+    // Indented code blocks start after a blank line, except in lists where indented lines continue
+    // the items
+    let mut previous_blank = true;
+    let mut in_indented_code = false;
+    let mut in_list = false;
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut n = 0;
+    while n < lines.len() {
+        let line = lines[n];
+        if n > 0 {
+            out.push('\n');
+        }
+        n += 1;
+
+        // Fenced code blocks, only a line with a fence at least as long as the opening one and
+        // nothing else closes them
+        let trimmed = line.trim_left();
+        if let Some(fence) = in_fence.clone() {
+            let marker = fence.chars().next().unwrap();
+            if trimmed.starts_with(&*fence) && trimmed.trim_left_matches(marker).trim().is_empty() {
+                in_fence = None;
+            }
+            out.push_str(line);
+            continue;
+        }
+        if skip_until.is_none() && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            let marker = trimmed.chars().next().unwrap();
+            in_fence = Some(trimmed.chars().take_while(|&c| c == marker).collect());
+            out.push_str(line);
+            continue;
+        }
+
+        // Indented code blocks, the end of a html element like `</pre>` can be indented as well
+        let blank = trimmed.is_empty();
+        let indented = line.starts_with("    ") || line.starts_with('\t');
+        if !indented && !blank {
+            in_list = is_list_item(trimmed) || (in_list && !previous_blank);
+        }
+        in_indented_code = skip_until.is_none() && indented && !in_list && (previous_blank || in_indented_code);
+        previous_blank = blank;
+        if in_indented_code {
+            out.push_str(line);
+            continue;
+        }
+
+        // Display math can span several lines, until a blank line ends the paragraph
+        let mut rest = line;
+        while let Some((start, open, close)) = protect_line(rest, &mut out, &mut math, &mut skip_until) {
+            let after = &rest[start + open.len()..];
+            let end = lines[n..]
+                .iter()
+                .take_while(|line| !line.trim().is_empty())
+                .position(|line| line.contains(close));
+
+            match end {
+                Some(end) => {
+                    let last = lines[n + end];
+                    let close_at = last.find(close).unwrap();
+                    let mut tex = after.to_owned();
+                    for line in &lines[n..n + end] {
+                        tex.push('\n');
+                        tex.push_str(line);
+                    }
+                    tex.push('\n');
+                    tex.push_str(&last[..close_at]);
+
+                    push_math(&mut out, &mut math, &tex, true);
+                    n += end + 1;
+                    rest = &last[close_at + close.len()..];
+                },
+                None => {
+                    out.push_str(open);
+                    rest = after;
+                },
+            }
+        }
+    }
+
+    (out, math)
+}
+
+/// Whether a line starts an item of a list, like `- item` or `1. item`
+fn is_list_item(trimmed: &str) -> bool {
+    let digits = trimmed.chars().take_while(|c| c.is_digit(10)).count();
+    let after = if digits == 0 {
+        match trimmed.chars().next() {
+            Some('-') | Some('*') | Some('+') => &trimmed[1..],
+            _ => return false,
+        }
+    } else if digits < 10 && (trimmed[digits..].starts_with('.') || trimmed[digits..].starts_with(')')) {
+        &trimmed[digits + 1..]
+    } else {
+        return false;
+    };
+    after.is_empty() || after.starts_with(' ') || after.starts_with('\t')
+}
+
+/// Protects the math of a line. Display math opening without closing on the line stops it: the
+/// text before it is written and its position, opening and closing delimiters are returned.
+fn protect_line(line: &str,
+                out: &mut String,
+                math: &mut Vec<Math>,
+                skip_until: &mut Option<String>)
+                -> Option<(usize, &'static str, &'static str)> {
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        // Inside a html element that should not be processed, like <pre> or <script>
+        if let Some(end) = skip_until.clone() {
+            match rest.find(&*end) {
+                Some(i) => {
+                    out.push_str(&rest[..i + end.len()]);
+                    rest = &rest[i + end.len()..];
+                    *skip_until = None;
+                    continue;
+                },
+                None => {
+                    out.push_str(rest);
+                    return None;
+                },
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        match c {
+            '`' => {
+                // Code span, find the closing run of backticks of the same length
+                let run = rest.chars().take_while(|&c| c == '`').count();
+                let ticks = &rest[..run];
+                match rest[run..].find(ticks) {
+                    Some(i) => {
+                        out.push_str(&rest[..run + i + run]);
+                        rest = &rest[run + i + run..];
+                    },
+                    None => {
+                        out.push_str(ticks);
+                        rest = &rest[run..];
+                    },
+                }
+            },
+            '<' => {
+                for tag in &["pre", "code", "script", "style", "textarea", "noscript"] {
+                    if starts_with_tag(rest, tag) {
+                        *skip_until = Some(format!("</{}>", tag));
+                        break;
+                    }
+                }
+                out.push('<');
+                rest = &rest[1..];
+            },
+            '\\' => {
+                if rest.starts_with("\\\\(") || rest.starts_with("\\\\[") {
+                    let display = rest.starts_with("\\\\[");
+                    let end = if display { "\\\\]" } else { "\\\\)" };
+                    if let Some(i) = rest[3..].find(end) {
+                        push_math(out, math, &rest[3..3 + i], display);
+                        rest = &rest[3 + i + 3..];
+                        continue;
+                    }
+                    if display {
+                        return Some((line.len() - rest.len(), "\\\\[", "\\\\]"));
+                    }
+                }
+                // Escaped character, like \$
+                let len = rest.chars().take(2).map(|c| c.len_utf8()).fold(0, |a, b| a + b);
+                out.push_str(&rest[..len]);
+                rest = &rest[len..];
+            },
+            '$' => {
+                if rest.starts_with("$$") {
+                    match rest[2..].find("$$") {
+                        Some(i) if i > 0 => {
+                            push_math(out, math, &rest[2..2 + i], true);
+                            rest = &rest[2 + i + 2..];
+                            continue;
+                        },
+                        None => return Some((line.len() - rest.len(), "$$", "$$")),
+                        _ => {},
+                    }
+                    out.push_str("$$");
+                    rest = &rest[2..];
+                } else {
+                    match inline_math_end(rest) {
+                        Some(i) => {
+                            push_math(out, math, &rest[1..i], false);
+                            rest = &rest[i + 1..];
+                        },
+                        None => {
+                            out.push('$');
+                            rest = &rest[1..];
+                        },
+                    }
+                }
+            },
+            _ => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+
+    None
+}
+
+/// Whether `text` contains any math, so MathJax has to be loaded to typeset it
+
+pub fn contains_math(text: &str) -> bool {
+    !protect(text).1.is_empty()
+}
+
+fn starts_with_tag(s: &str, tag: &str) -> bool {
+    if s.len() < tag.len() + 2 || !s[1..].to_lowercase().starts_with(tag) {
+        return false;
+    }
+    match s[1 + tag.len()..].chars().next() {
+        Some('>') | Some(' ') | Some('\t') | Some('/') => true,
+        _ => false,
+    }
+}
+
+/// Finds the closing `$` of inline math starting at the beginning of `s`.
+/// Like pandoc, the opening `$` must be followed by a non-space character, the closing `$` must
+/// be preceded by a non-space character and must not be followed by a digit.
+fn inline_math_end(s: &str) -> Option<usize> {
+    match s[1..].chars().next() {
+        Some(c) if !c.is_whitespace() && c != '$' => {},
+        _ => return None,
+    }
+
+    let mut escaped = false;
+    let mut previous = '$';
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '`' {
+            // Math never continues into a code span
+            return None;
+        } else if c == '$' {
+            let followed_by_digit = s[i + 1..].chars().next().map(|c| c.is_digit(10)).unwrap_or(false);
+            if !previous.is_whitespace() && !followed_by_digit {
+                return Some(i);
+            }
+        }
+        previous = c;
+    }
+
+    None
+}
+
+fn push_math(out: &mut String, math: &mut Vec<Math>, tex: &str, display: bool) {
+    let _ = write!(out, "{}{}{}", PLACEHOLDER_START, math.len(), PLACEHOLDER_END);
+    math.push(Math {
+        tex: tex.to_owned(),
+        display: display,
+    });
+}
+
+
+/// Puts the math expressions back into the rendered html. If `prerender` is true the math is
+/// converted to MathML, otherwise it is left for MathJax using the `\(...\)` and `\[...\]`
+/// delimiters.
+
+pub fn restore(html: &str, math: &[Math], prerender: bool) -> String {
+    replace_placeholders(html, math, |m| {
+        if prerender {
+            match to_mathml(&m.tex, m.display) {
+                Ok(mathml) => return mathml,
+                Err(e) => {
+                    warn!("[-] Could not pre-render math `{}`: {}", m.tex, e);
+                },
+            }
+        }
+
+        if m.display {
+            format!("\\[{}\\]", escape(&m.tex))
+        } else {
+            format!("\\({}\\)", escape(&m.tex))
+        }
+    })
+}
+
+/// Puts the TeX source of the math expressions back into `text`, used where the text of the
+/// markdown is needed rather than the html, like for the ids of headings.
+
+pub fn restore_tex(text: &str, math: &[Math]) -> String {
+    replace_placeholders(text, math, |m| m.tex.clone())
+}
+
+fn replace_placeholders<F>(s: &str, math: &[Math], replace: F) -> String
+    where F: Fn(&Math) -> String
+{
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + PLACEHOLDER_START.len_utf8()..];
+
+        let end = match rest.find(PLACEHOLDER_END) {
+            Some(end) => end,
+            None => break,
+        };
+        let index: usize = match rest[..end].parse() {
+            Ok(i) => i,
+            Err(_) => break,
+        };
+        rest = &rest[end + PLACEHOLDER_END.len_utf8()..];
+
+        if let Some(m) = math.get(index) {
+            out.push_str(&replace(m));
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+}
+
+
+// ------------------------------------------------------------------------------------------------
+//      TeX to MathML
+//
+// Supports the commonly used subset of TeX math: identifiers, numbers and operators, sub- and
+// superscripts, groups, fractions, roots, greek letters, common symbols and big operators,
+// function names, font commands, accents, \left/\right and \text.
+
+/// Converts a TeX math expression to a `<math>` element
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = TexParser {
+        chars: tex.chars().collect(),
+        pos: 0,
+    };
+    let nodes = try!(parser.expression(Stop::End));
+
+    Ok(format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}><semantics>{}<annotation \
+                encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+               if display { " display=\"block\"" } else { "" },
+               row(nodes),
+               escape(tex.trim())))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    End,
+    Brace,
+    Bracket,
+    Right,
+}
+
+struct TexParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+fn row(mut nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", nodes.join(""))
+    }
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{}</mo>", escape(op))
+}
+
+impl TexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expression(&mut self, stop: Stop) -> Result<Vec<String>, String> {
+        let mut nodes = vec![];
+
+        loop {
+            self.skip_whitespace();
+            match (self.peek(), stop) {
+                (None, Stop::End) => return Ok(nodes),
+                (None, _) => return Err("Unexpected end of expression".to_owned()),
+                (Some('}'), Stop::Brace) => {
+                    self.pos += 1;
+                    return Ok(nodes);
+                },
+                (Some(']'), Stop::Bracket) => {
+                    self.pos += 1;
+                    return Ok(nodes);
+                },
+                (Some('}'), _) => return Err("Unbalanced '}'".to_owned()),
+                _ => {},
+            }
+
+            if stop == Stop::Right && self.looking_at_command("right") {
+                return Ok(nodes);
+            }
+
+            let atom = try!(self.atom());
+            let atom = try!(self.scripts(atom));
+            nodes.push(atom);
+        }
+    }
+
+    fn looking_at_command(&self, name: &str) -> bool {
+        if self.peek() != Some('\\') {
+            return false;
+        }
+        let end = self.pos + 1 + name.len();
+        if end > self.chars.len() {
+            return false;
+        }
+        let word: String = self.chars[self.pos + 1..end].iter().cloned().collect();
+        word == name && self.chars.get(end).map(|c| !c.is_alphabetic()).unwrap_or(true)
+    }
+
+    /// Parses a mandatory argument: a group in braces or a single token
+    fn argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                Ok(row(try!(self.expression(Stop::Brace))))
+            },
+            Some(_) => self.atom(),
+            None => Err("Missing argument".to_owned()),
+        }
+    }
+
+    /// Reads the raw text of a group in braces, used for `\text{...}` and friends
+    fn raw_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err("Expected '{'".to_owned());
+        }
+        self.pos += 1;
+
+        let mut depth = 1;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(text);
+                    }
+                },
+                _ => {},
+            }
+            text.push(c);
+        }
+
+        Err("Unclosed '{'".to_owned())
+    }
+
+    fn scripts(&mut self, base: String) -> Result<String, String> {
+        let mut sub = None;
+        let mut sup = None;
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(try!(self.argument()));
+                },
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(try!(self.argument()));
+                },
+                Some('\'') if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(mo(&primes));
+                },
+                _ => break,
+            }
+        }
+
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        })
+    }
+
+    fn atom(&mut self) -> Result<String, String> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err("Unexpected end of expression".to_owned()),
+        };
+        self.pos += 1;
+
+        match c {
+            '{' => Ok(row(try!(self.expression(Stop::Brace)))),
+            '\\' => self.command(),
+            '~' => Ok("<mspace width=\"0.33em\"/>".to_owned()),
+            '-' => Ok(mo("−")),
+            '_' | '^' => Err(format!("Unexpected '{}'", c)),
+            '&' => Err("Alignment with '&' is not supported".to_owned()),
+            c if c.is_alphabetic() => Ok(format!("<mi>{}</mi>", c)),
+            c if c.is_digit(10) => {
+                let mut number = c.to_string();
+                while let Some(next) = self.peek() {
+                    let continues_number = next.is_digit(10) ||
+                                           (next == '.' &&
+                                            self.chars.get(self.pos + 1).map(|c| c.is_digit(10)).unwrap_or(false));
+                    if !continues_number {
+                        break;
+                    }
+                    number.push(next);
+                    self.pos += 1;
+                }
+                Ok(format!("<mn>{}</mn>", number))
+            },
+            c => Ok(mo(&c.to_string())),
+        }
+    }
+
+    fn command(&mut self) -> Result<String, String> {
+        let name: String = match self.peek() {
+            Some(c) if c.is_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    if !c.is_alphabetic() {
+                        break;
+                    }
+                    name.push(c);
+                    self.pos += 1;
+                }
+                name
+            },
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            },
+            None => return Err("Unexpected '\\' at end of expression".to_owned()),
+        };
+
+        if let Some(symbol) = greek(&name) {
+            return Ok(format!("<mi>{}</mi>", symbol));
+        }
+        if let Some(symbol) = operator(&name) {
+            return Ok(mo(symbol));
+        }
+        if let Some(symbol) = large_operator(&name) {
+            return Ok(format!("<mo largeop=\"true\">{}</mo>", symbol));
+        }
+        if is_function(&name) {
+            return Ok(format!("<mi>{}</mi>", name));
+        }
+
+        match &*name {
+            "," => Ok("<mspace width=\"0.17em\"/>".to_owned()),
+            ":" | ">" => Ok("<mspace width=\"0.22em\"/>".to_owned()),
+            ";" => Ok("<mspace width=\"0.28em\"/>".to_owned()),
+            " " => Ok("<mspace width=\"0.33em\"/>".to_owned()),
+            "quad" => Ok("<mspace width=\"1em\"/>".to_owned()),
+            "qquad" => Ok("<mspace width=\"2em\"/>".to_owned()),
+            "!" => Ok(String::new()),
+            "{" | "}" | "|" | "%" | "$" | "#" | "&" | "_" => Ok(mo(&name)),
+            "\\" => Ok("<mspace linebreak=\"newline\"/>".to_owned()),
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = try!(self.argument());
+                let denominator = try!(self.argument());
+                Ok(format!("<mfrac>{}{}</mfrac>", numerator, denominator))
+            },
+            "binom" => {
+                let n = try!(self.argument());
+                let k = try!(self.argument());
+                Ok(format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>", n, k))
+            },
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let index = row(try!(self.expression(Stop::Bracket)));
+                    let radicand = try!(self.argument());
+                    Ok(format!("<mroot>{}{}</mroot>", radicand, index))
+                } else {
+                    Ok(format!("<msqrt>{}</msqrt>", try!(self.argument())))
+                }
+            },
+            "text" | "textrm" | "mbox" => Ok(format!("<mtext>{}</mtext>", escape(&try!(self.raw_argument())))),
+            "operatorname" | "mathrm" => Ok(format!("<mi mathvariant=\"normal\">{}</mi>", escape(&try!(self.raw_argument())))),
+            "mathbf" | "mathit" | "mathbb" | "mathcal" | "mathfrak" | "mathsf" | "mathtt" | "boldsymbol" => {
+                let variant = match &*name {
+                    "mathbf" | "boldsymbol" => "bold",
+                    "mathit" => "italic",
+                    "mathbb" => "double-struck",
+                    "mathcal" => "script",
+                    "mathfrak" => "fraktur",
+                    "mathsf" => "sans-serif",
+                    _ => "monospace",
+                };
+                Ok(format!("<mstyle mathvariant=\"{}\">{}</mstyle>", variant, try!(self.argument())))
+            },
+            "hat" | "widehat" | "bar" | "overline" | "vec" | "dot" | "ddot" | "tilde" | "widetilde" => {
+                let accent = match &*name {
+                    "hat" | "widehat" => "^",
+                    "bar" | "overline" => "¯",
+                    "vec" => "→",
+                    "dot" => "˙",
+                    "ddot" => "¨",
+                    _ => "~",
+                };
+                Ok(format!("<mover accent=\"true\">{}{}</mover>", try!(self.argument()), mo(accent)))
+            },
+            "underline" => Ok(format!("<munder accentunder=\"true\">{}{}</munder>", try!(self.argument()), mo("_"))),
+            "left" => {
+                let open = try!(self.delimiter());
+                let inner = try!(self.expression(Stop::Right));
+                // Skip the `\right` command itself
+                self.pos += "\\right".len();
+                let close = try!(self.delimiter());
+                Ok(format!("<mrow><mo fence=\"true\" stretchy=\"true\">{}</mo>{}<mo fence=\"true\" \
+                            stretchy=\"true\">{}</mo></mrow>",
+                           escape(&open),
+                           inner.join(""),
+                           escape(&close)))
+            },
+            _ => Err(format!("Unsupported command \\{}", name)),
+        }
+    }
+
+    /// Reads the delimiter after `\left` or `\right`, `.` is the empty delimiter
+    fn delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('.') => {
+                self.pos += 1;
+                Ok(String::new())
+            },
+            Some('\\') => {
+                self.pos += 1;
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    if !c.is_alphabetic() && !name.is_empty() {
+                        break;
+                    }
+                    name.push(c);
+                    self.pos += 1;
+                    if !c.is_alphabetic() {
+                        break;
+                    }
+                }
+                match &*name {
+                    "{" | "}" => Ok(name),
+                    "|" => Ok("‖".to_owned()),
+                    _ => operator(&name).map(|s| s.to_owned()).ok_or(format!("Unsupported delimiter \\{}", name)),
+                }
+            },
+            Some(c) => {
+                self.pos += 1;
+                Ok(c.to_string())
+            },
+            None => Err("Missing delimiter".to_owned()),
+        }
+    }
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "pm" => "±",
+        "mp" => "∓",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "⋅",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "mid" | "vert" => "|",
+        "Vert" | "parallel" => "‖",
+        "perp" => "⊥",
+        "angle" => "∠",
+        "prime" => "′",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        _ => return None,
+    })
+}
+
+fn large_operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "bigoplus" => "⨁",
+        "bigotimes" => "⨂",
+        _ => return None,
+    })
+}
+
+fn is_function(name: &str) -> bool {
+    match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh" | "cosh" |
+        "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "lim" | "liminf" | "limsup" | "max" | "min" |
+        "sup" | "inf" | "det" | "dim" | "ker" | "deg" | "gcd" | "arg" | "hom" | "Pr" => true,
+        _ => false,
+    }
+}
+
+
+// ------------------------------------------------------------------------------------------------
+//      Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::{protect, restore, restore_tex, contains_math, to_mathml, Math};
+
+    #[test]
+    fn protect_inline_and_display() {
+        let (text, math) = protect("Let $a_1 * b_2$ be\n\n$$x^*_i$$\n");
+        assert_eq!(text, "Let \u{E000}0\u{E001} be\n\n\u{E000}1\u{E001}\n");
+        assert_eq!(math,
+                   vec![Math { tex: "a_1 * b_2".to_owned(), display: false },
+                        Math { tex: "x^*_i".to_owned(), display: true }]);
+    }
+
+    #[test]
+    fn protect_escaped_delimiters() {
+        let (text, math) = protect("\\\\( \\int x \\\\) and \\\\[ \\mu \\\\]");
+        assert_eq!(text, "\u{E000}0\u{E001} and \u{E000}1\u{E001}");
+        assert_eq!(math[0], Math { tex: " \\int x ".to_owned(), display: false });
+        assert!(math[1].display);
+    }
+
+    #[test]
+    fn protect_skips_code_and_prices() {
+        let input = "It costs $5 and $10.\n\n`$x$` and \\$y$\n\n```\nlet $a$ = 1;\n```\n\n<pre>$b$</pre> $c$";
+        let (text, math) = protect(input);
+        assert_eq!(math, vec![Math { tex: "c".to_owned(), display: false }]);
+        assert_eq!(text, input.replace("$c$", "\u{E000}0\u{E001}"));
+    }
+
+    #[test]
+    fn protect_closes_fences_on_a_fence_only() {
+        let input = "```\n```rust\n$a$\n```\n$b$";
+        let (text, math) = protect(input);
+        assert_eq!(math, vec![Math { tex: "b".to_owned(), display: false }]);
+        assert_eq!(text, input.replace("$b$", "\u{E000}0\u{E001}"));
+    }
+
+    #[test]
+    fn protect_stops_at_code_spans() {
+        let input = "It costs $5, `$x$` is code";
+        let (text, math) = protect(input);
+        assert!(math.is_empty());
+        assert_eq!(text, input);
+    }
+
+    #[test]
+    fn protect_multi_line_display_math() {
+        let input = "\nBefore\n$$\na_1 * b_2\n$$\nafter \\\\[x\ny\\\\] and $$ alone\n\n$$\nnot closed";
+        let (text, math) = protect(input);
+        assert_eq!(math,
+                   vec![Math { tex: "\na_1 * b_2\n".to_owned(), display: true },
+                        Math { tex: "x\ny".to_owned(), display: true }]);
+        assert_eq!(text,
+                   "\nBefore\n\u{E000}0\u{E001}\nafter \u{E000}1\u{E001} and $$ alone\n\n$$\nnot closed");
+    }
+
+    #[test]
+    fn protect_indented_lines() {
+        let input = "- item\n\n    with $x$\n\n<pre>\n    $y$\n    </pre>\n$z$\n\n    let $a$ = 1;";
+        let (text, math) = protect(input);
+        assert_eq!(math,
+                   vec![Math { tex: "x".to_owned(), display: false }, Math { tex: "z".to_owned(), display: false }]);
+        assert_eq!(text, input.replace("$x$", "\u{E000}0\u{E001}").replace("$z$", "\u{E000}1\u{E001}"));
+    }
+
+    #[test]
+    fn detect_math() {
+        assert!(contains_math("Let $x$ be"));
+        assert!(!contains_math("It costs $5 or $6"));
+        assert!(!contains_math("`$x$` is code"));
+    }
+
+    #[test]
+    fn restore_for_mathjax() {
+        let math = vec![Math { tex: "a<b".to_owned(), display: false }];
+        assert_eq!(restore("<p>\u{E000}0\u{E001}</p>", &math, false), "<p>\\(a&lt;b\\)</p>");
+        assert_eq!(restore_tex("Let \u{E000}0\u{E001}", &math), "Let a<b");
+    }
+
+    #[test]
+    fn mathml_conversion() {
+        assert_eq!(to_mathml("x^2", false).unwrap(),
+                   "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><semantics><msup><mi>x</mi><mn>2</mn>\
+                    </msup><annotation encoding=\"application/x-tex\">x^2</annotation></semantics></math>");
+
+        let mathml = to_mathml("\\frac{1}{N} \\sum_{i=0} x_i", true).unwrap();
+        assert!(mathml.contains("display=\"block\""));
+        assert!(mathml.contains("<mfrac><mn>1</mn><mi>N</mi></mfrac>"));
+        assert!(mathml.contains("<msub><mo largeop=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>0</mn></mrow></msub>"));
+
+        let mathml = to_mathml("\\left( \\alpha \\right)", false).unwrap();
+        assert!(mathml.contains("<mo fence=\"true\" stretchy=\"true\">(</mo><mi>α</mi>"));
+    }
+
+    #[test]
+    fn mathml_unsupported() {
+        assert!(to_mathml("\\begin{matrix} a & b \\end{matrix}", false).is_err());
+        assert!(to_mathml("{x", false).is_err());
+    }
+}
@@ -1,6 +1,12 @@
 pub mod fs;
+pub mod highlight;
+pub mod math;
 
-use pulldown_cmark::{Parser, html, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+use htmlescape::encode_minimal;
+use pulldown_cmark::{Parser, Event, Tag, html, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
 
 
 ///
@@ -8,13 +14,448 @@ use pulldown_cmark::{Parser, html, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_
 /// Wrapper around the pulldown-cmark parser and renderer to render markdown
 
 pub fn render_markdown(text: &str) -> String {
+    render_markdown_with_headings(text, false, false).0
+}
+
+
+///
+///
+/// Renders markdown like `render_markdown`, math between `$...$`, `$$...$$`, `\\(...\\)` and
+/// `\\[...\\]` is protected from the markdown parser. When `prerender_math` is true the math is
+/// converted to MathML, otherwise it is left for MathJax to typeset in the browser.
+///
+/// Headings get a unique id and link to themselves, a custom id can be given by ending the
+/// heading with `{#id}`. Code blocks in a language `highlight` knows are highlighted.
+
+pub fn render_markdown_with_math(text: &str, prerender_math: bool) -> String {
+    render_markdown_with_headings(text, true, prerender_math).0
+}
+
+
+/// A heading of a page, used for the outline of the page
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: i32,
+    pub text: String,
+    pub id: String,
+}
+
+
+///
+///
+/// Renders markdown like `render_markdown_with_math` when `math` is true, like `render_markdown`
+/// otherwise, and also returns the headings of the page in the order they appear.
+
+pub fn render_markdown_with_headings(text: &str, math: bool, prerender_math: bool) -> (String, Vec<Heading>) {
+    render_markdown_with_blocks(text, math, prerender_math, |_, _| None)
+}
+
+
+///
+///
+/// Renders markdown like `render_markdown_with_headings`, `blocks` is called with the info string
+/// and the code of every code block and returns the html that replaces the block, or `None` to
+/// render it as code. This is how the code blocks of diagrams are rendered.
+
+pub fn render_markdown_with_blocks<F>(text: &str,
+                                      math: bool,
+                                      prerender_math: bool,
+                                      blocks: F)
+                                      -> (String, Vec<Heading>)
+    where F: Fn(&str, &str) -> Option<String>
+{
+    let (text, math) = if math { math::protect(text) } else { (text.to_owned(), vec![]) };
+
     let mut s = String::with_capacity(text.len() * 3 / 2);
 
     let mut opts = Options::empty();
     opts.insert(OPTION_ENABLE_TABLES);
     opts.insert(OPTION_ENABLE_FOOTNOTES);
 
-    let p = Parser::new_ext(&text, opts);
-    html::push_html(&mut s, p);
-    s
+    // Links to other chapters point to the generated html files
+    let p = Parser::new_ext(&text, opts).map(|event| match event {
+        Event::Start(Tag::Link(dest, title)) => {
+            let dest = match md_link_to_html(&dest) {
+                Some(html) => Cow::Owned(html),
+                None => dest,
+            };
+            Event::Start(Tag::Link(dest, title))
+        },
+        event => event,
+    });
+    let mut headings = vec![];
+    let events = add_heading_ids(render_code_blocks(p.collect(), blocks), &math, &mut headings);
+    html::push_html(&mut s, events.into_iter());
+
+    if math.is_empty() {
+        (s, headings)
+    } else {
+        (math::restore(&s, &math, prerender_math), headings)
+    }
+}
+
+
+/// Replaces the events of headings by html with an id and a link to the heading itself
+
+fn add_heading_ids<'a>(events: Vec<Event<'a>>, math: &[math::Math], headings: &mut Vec<Heading>) -> Vec<Event<'a>> {
+    let mut ids = HashSet::new();
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let level = match event {
+            Event::Start(Tag::Header(level)) => level,
+            event => {
+                out.push(event);
+                continue;
+            },
+        };
+
+        let mut inner = vec![];
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::Header(_)) => break,
+                event => inner.push(event),
+            }
+        }
+
+        let mut text = String::new();
+        for event in &inner {
+            if let Event::Text(ref t) = *event {
+                text.push_str(t);
+            }
+        }
+
+        // A custom id at the end of the heading, remove it from the text
+        let custom = custom_id(&text);
+        if let Some(ref custom) = custom {
+            let suffix = format!("{{#{}}}", custom);
+            text = text.trim_right().trim_right_matches(&*suffix).to_owned();
+            if let Some(&mut Event::Text(ref mut t)) = inner.iter_mut().rev().find(|e| match **e {
+                Event::Text(_) => true,
+                _ => false,
+            }) {
+                let stripped = t.trim_right().trim_right_matches(&*suffix).trim_right().to_owned();
+                *t = Cow::Owned(stripped);
+            }
+        }
+
+        let text = math::restore_tex(&text, math);
+        let id = unique_id(custom.unwrap_or_else(|| normalize_id(&text)), &mut ids);
+
+        headings.push(Heading {
+            level: level,
+            text: text.trim().to_owned(),
+            id: id.clone(),
+        });
+
+        // Links can not be nested, a heading containing a link is not a link to itself
+        let has_link = inner.iter().any(|event| match *event {
+            Event::Start(Tag::Link(..)) => true,
+            Event::Html(ref html) |
+            Event::InlineHtml(ref html) => html.trim_left().to_lowercase().starts_with("<a"),
+            _ => false,
+        });
+        if has_link {
+            out.push(Event::Html(Cow::Owned(format!("<h{} id=\"{}\">", level, id))));
+            out.extend(inner);
+            out.push(Event::Html(Cow::Owned(format!("</h{}>\n", level))));
+        } else {
+            out.push(Event::Html(Cow::Owned(format!("<h{} id=\"{}\"><a class=\"header\" href=\"#{}\">", level, id, id))));
+            out.extend(inner);
+            out.push(Event::Html(Cow::Owned(format!("</a></h{}>\n", level))));
+        }
+    }
+
+    out
+}
+
+/// Replaces the events of code blocks by the html `blocks` returns for them, or else for code
+/// blocks in a language `highlight` knows by highlighted html. The language is the first word of
+/// the info string, like `rust` in `rust,ignore`.
+
+fn render_code_blocks<F>(events: Vec<Event>, blocks: F) -> Vec<Event>
+    where F: Fn(&str, &str) -> Option<String>
+{
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let info = match event {
+            Event::Start(Tag::CodeBlock(info)) => info,
+            event => {
+                out.push(event);
+                continue;
+            },
+        };
+
+        // The text of a code block can come in several events
+        let mut inner = vec![];
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::CodeBlock(_)) => break,
+                event => inner.push(event),
+            }
+        }
+        let mut code = String::new();
+        for event in &inner {
+            if let Event::Text(ref t) = *event {
+                code.push_str(t);
+            }
+        }
+
+        if let Some(html) = blocks(&info, &code) {
+            out.push(Event::Html(Cow::Owned(html)));
+            continue;
+        }
+
+        // The class pulldown-cmark gives code blocks, and the one highlight.js adds
+        let language = info.split(' ').next().unwrap_or("").to_owned();
+        match highlight::highlight_html(&language, &code) {
+            Some(html) => {
+                out.push(Event::Html(Cow::Owned(format!("<pre><code class=\"language-{} hljs\">{}</code></pre>\n",
+                                                        encode_minimal(&language),
+                                                        html))));
+            },
+            None => {
+                out.push(Event::Start(Tag::CodeBlock(info.clone())));
+                out.extend(inner);
+                out.push(Event::End(Tag::CodeBlock(info)));
+            },
+        }
+    }
+
+    out
+}
+
+/// Returns the id of a heading ending with `{#id}`
+fn custom_id(text: &str) -> Option<String> {
+    let text = text.trim_right();
+    if !text.ends_with('}') {
+        return None;
+    }
+
+    let start = match text.rfind("{#") {
+        Some(start) => start,
+        None => return None,
+    };
+    let id = &text[start + 2..text.len() - 1];
+
+    let valid = !id.is_empty() && !id.chars().any(|c| c.is_whitespace() || "\"'<>&{}#".contains(c));
+    if valid { Some(id.to_owned()) } else { None }
+}
+
+
+/// Turns the text of a heading into an id: lowercase, spaces replaced by `-` and punctuation
+/// other than `-` and `_` removed.
+
+pub fn normalize_id(text: &str) -> String {
+    let mut id = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+            id.extend(c.to_lowercase());
+        } else if c.is_whitespace() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+
+    if id.is_empty() {
+        id.push_str("section");
+    }
+    id
+}
+
+/// Makes `id` unique among `ids` by appending `-1`, `-2`, ... and adds it to `ids`
+
+pub fn unique_id(id: String, ids: &mut HashSet<String>) -> String {
+    let mut unique = id.clone();
+    let mut counter = 1;
+    while ids.contains(&unique) {
+        unique = format!("{}-{}", id, counter);
+        counter += 1;
+    }
+
+    ids.insert(unique.clone());
+    unique
+}
+
+
+/// Returns true for links relative to the book, false for links with a scheme like `http:` or
+/// `mailto:` and for absolute paths
+
+pub fn is_relative_link(dest: &str) -> bool {
+    if dest.starts_with('/') {
+        return false;
+    }
+
+    // A scheme is only valid before the path, query and fragment
+    let end = dest.find(|c| c == '/' || c == '?' || c == '#').unwrap_or(dest.len());
+    match dest[..end].find(':') {
+        Some(i) => {
+            let scheme = &dest[..i];
+            !(scheme.chars().next().map(|c| c.is_alphabetic()).unwrap_or(false) &&
+              scheme.chars().all(|c| c.is_alphanumeric() || c == '+' || c == '-' || c == '.'))
+        },
+        None => true,
+    }
+}
+
+
+/// Rewrites a relative link to a markdown file, like `chapter.md#section`, to the html file
+/// generated for it. Returns `None` for every other link.
+
+pub fn md_link_to_html(dest: &str) -> Option<String> {
+    if !is_relative_link(dest) {
+        return None;
+    }
+
+    let (path, rest) = dest.split_at(dest.find(|c| c == '?' || c == '#').unwrap_or(dest.len()));
+    if path.ends_with(".md") {
+        Some(format!("{}.html{}", &path[..path.len() - 3], rest))
+    } else {
+        None
+    }
+}
+
+
+/// Splits the front matter from the start of a chapter, it is a list of `key: value` lines between
+/// two lines of `---`:
+///
+/// ```text
+/// ---
+/// name: mdbook-build
+/// section: 1
+/// ---
+/// ```
+///
+/// Returns the values by key and the rest of the chapter. When the chapter does not start with a
+/// front matter, nothing is removed.
+
+pub fn split_front_matter(text: &str) -> (BTreeMap<String, String>, &str) {
+    let mut values = BTreeMap::new();
+
+    let mut lines = text.split('\n');
+    if lines.next().map(|l| l.trim_right()) != Some("---") {
+        return (values, text);
+    }
+
+    let mut end = text.find('\n').unwrap_or(text.len()) + 1;
+    for line in lines {
+        end += line.len() + 1;
+        let line = line.trim();
+
+        if line == "---" || line == "..." {
+            return (values, if end < text.len() { &text[end..] } else { "" });
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Anything else than `key: value` means this is not a front matter but a rule
+        let colon = match line.find(':') {
+            Some(colon) => colon,
+            None => break,
+        };
+        let key = &line[..colon];
+        if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            break;
+        }
+        let value = line[colon + 1..].trim().trim_matches('"');
+        values.insert(key.to_owned(), value.to_owned());
+    }
+
+    (BTreeMap::new(), text)
+}
+
+
+// ------------------------------------------------------------------------------------------------
+// ------------------------------------------------------------------------------------------------
+
+// tests
+
+#[cfg(test)]
+mod tests {
+    use super::{is_relative_link, md_link_to_html, normalize_id, custom_id, render_markdown, split_front_matter,
+                render_markdown_with_math, render_markdown_with_headings, Heading};
+
+    #[test]
+    fn relative_links() {
+        assert!(is_relative_link("chapter.md"));
+        assert!(is_relative_link("../images/a:b.png"));
+        assert!(is_relative_link("#section"));
+        assert!(!is_relative_link("https://example.com/a.md"));
+        assert!(!is_relative_link("mailto:someone@example.com"));
+        assert!(!is_relative_link("/absolute.md"));
+    }
+
+    #[test]
+    fn md_links_are_rewritten() {
+        assert_eq!(md_link_to_html("sub/chapter.md#section"), Some("sub/chapter.html#section".to_owned()));
+        assert_eq!(md_link_to_html("chapter.md"), Some("chapter.html".to_owned()));
+        assert_eq!(md_link_to_html("image.png"), None);
+        assert_eq!(md_link_to_html("https://example.com/README.md"), None);
+
+        assert_eq!(render_markdown("[a](a.md#b)"), "<p><a href=\"a.html#b\">a</a></p>\n");
+    }
+
+    #[test]
+    fn heading_ids() {
+        assert_eq!(normalize_id("Hello, World!"), "hello-world");
+        assert_eq!(normalize_id("  `Option<T>`  and  _more_ "), "optiont-and-_more_");
+        assert_eq!(normalize_id("Überschrift 2"), "überschrift-2");
+        assert_eq!(normalize_id("!!!"), "section");
+
+        assert_eq!(custom_id("Title {#my-id}"), Some("my-id".to_owned()));
+        assert_eq!(custom_id("Title {#}"), None);
+        assert_eq!(custom_id("Title"), None);
+
+        assert_eq!(render_markdown("# Intro\n\n## Intro\n\n### Custom {#own}\n"),
+                   "<h1 id=\"intro\"><a class=\"header\" href=\"#intro\">Intro</a></h1>\n\
+                    <h2 id=\"intro-1\"><a class=\"header\" href=\"#intro-1\">Intro</a></h2>\n\
+                    <h3 id=\"own\"><a class=\"header\" href=\"#own\">Custom</a></h3>\n");
+
+        // A heading with a link is not wrapped in a link to itself
+        assert_eq!(render_markdown("## See [the intro](intro.html)\n\n## <a href=\"x.html\">X</a>\n"),
+                   "<h2 id=\"see-the-intro\">See <a href=\"intro.html\">the intro</a></h2>\n\
+                    <h2 id=\"x\"><a href=\"x.html\">X</a></h2>\n");
+    }
+
+    #[test]
+    fn code_blocks_are_highlighted() {
+        assert_eq!(render_markdown("```rust,ignore\nlet x = 1;\n```\n"),
+                   "<pre><code class=\"language-rust,ignore hljs\"><span class=\"hljs-keyword\">let</span> x = \
+                    <span class=\"hljs-number\">1</span>;\n</code></pre>\n");
+        assert_eq!(render_markdown("```text\n<a>\n```\n"),
+                   "<pre><code class=\"language-text\">&lt;a&gt;\n</code></pre>\n");
+    }
+
+    #[test]
+    fn math_only_when_enabled() {
+        assert_eq!(render_markdown("$a*b*c$"), "<p>$a<em>b</em>c$</p>\n");
+        assert_eq!(render_markdown_with_math("$a*b*c$", false), "<p>\\(a*b*c\\)</p>\n");
+    }
+
+    #[test]
+    fn headings_outline() {
+        let (_, headings) = render_markdown_with_headings("# Title\n\n## With `code` {#custom}\n\n### $x^2$\n",
+                                                          true,
+                                                          false);
+        assert_eq!(headings,
+                   vec![Heading { level: 1, text: "Title".to_owned(), id: "title".to_owned() },
+                        Heading { level: 2, text: "With code".to_owned(), id: "custom".to_owned() },
+                        Heading { level: 3, text: "x^2".to_owned(), id: "x2".to_owned() }]);
+    }
+
+    #[test]
+    fn front_matter() {
+        let (values, rest) = split_front_matter("---\nname: mdbook-build\nsection: \"1\"\n---\n# Build\n");
+        assert_eq!(values.get("name").map(|v| &v[..]), Some("mdbook-build"));
+        assert_eq!(values.get("section").map(|v| &v[..]), Some("1"));
+        assert_eq!(rest, "# Build\n");
+
+        assert_eq!(split_front_matter("# Build\n---\n").1, "# Build\n---\n");
+        assert_eq!(split_front_matter("---\n\nSome text\n\n---\n").1, "---\n\nSome text\n\n---\n");
+        assert_eq!(split_front_matter("---\nname: a\n---").1, "");
+    }
 }